@@ -0,0 +1,21 @@
+use std::{env, process::Command};
+
+/// Embeds the commit the app is built from as `GIT_COMMIT`, unless it's already set.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    if env::var("GIT_COMMIT").is_ok_and(|commit| !commit.is_empty()) {
+        return;
+    }
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=GIT_COMMIT={}", commit.trim());
+    }
+}
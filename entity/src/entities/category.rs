@@ -9,14 +9,30 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
     pub name: String,
+    pub version: i32,
+    pub household_id: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::household::Entity",
+        from = "Column::HouseholdId",
+        to = "super::household::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Household,
     #[sea_orm(has_many = "super::record::Entity")]
     Record,
 }
 
+impl Related<super::household::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Household.def()
+    }
+}
+
 impl Related<super::record::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Record.def()
@@ -27,6 +43,7 @@ impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
             id: Set(Uuid::new_v4()),
+            version: Set(1),
             ..ActiveModelTrait::default()
         }
     }
@@ -0,0 +1,75 @@
+use sea_orm::{ActiveValue::Set, entity::prelude::*};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "household")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub name: String,
+    pub version: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::category::Entity")]
+    Category,
+    #[sea_orm(has_many = "super::household_invitation::Entity")]
+    HouseholdInvitation,
+    #[sea_orm(has_many = "super::household_member::Entity")]
+    HouseholdMember,
+    #[sea_orm(has_many = "super::record::Entity")]
+    Record,
+    #[sea_orm(has_many = "super::settlement::Entity")]
+    Settlement,
+}
+
+impl Related<super::category::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Category.def()
+    }
+}
+
+impl Related<super::household_invitation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::HouseholdInvitation.def()
+    }
+}
+
+impl Related<super::household_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::HouseholdMember.def()
+    }
+}
+
+impl Related<super::record::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Record.def()
+    }
+}
+
+impl Related<super::settlement::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Settlement.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::household_member::Relation::User.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::household_member::Relation::Household.def().rev())
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            id: Set(Uuid::new_v4()),
+            version: Set(1),
+            ..ActiveModelTrait::default()
+        }
+    }
+}
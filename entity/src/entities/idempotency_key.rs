@@ -0,0 +1,31 @@
+use sea_orm::{ActiveValue::Set, entity::prelude::*, sqlx::types::chrono::Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "idempotency_key")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub scope: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    pub request_hash: String,
+    /// `None` while the request is still being handled.
+    pub response_status: Option<i16>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub response_headers: Option<Json>,
+    #[sea_orm(column_type = "Blob", nullable)]
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(Utc::now().naive_utc()),
+            ..ActiveModelTrait::default()
+        }
+    }
+}
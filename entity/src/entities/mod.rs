@@ -2,6 +2,17 @@
 
 pub mod prelude;
 
+pub mod api_key;
 pub mod category;
+pub mod household;
+pub mod household_invitation;
+pub mod household_member;
+pub mod idempotency_key;
+pub mod outbox;
 pub mod record;
+pub mod record_event;
+pub mod record_share;
+pub mod settlement;
 pub mod user;
+pub mod webhook;
+pub mod webhook_delivery;
@@ -0,0 +1,38 @@
+use sea_orm::{ActiveValue::Set, entity::prelude::*, sqlx::types::chrono::Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "outbox")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// The event's type, e.g. `record.created`.
+    pub kind: String,
+    #[sea_orm(column_type = "JsonBinary")]
+    pub payload: Json,
+    pub created_at: DateTime,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime,
+    /// Space-separated names of the subscribers yet to handle the event, every
+    /// subscriber if not set.
+    #[sea_orm(column_type = "Text", nullable)]
+    pub pending_subscribers: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub last_error: Option<String>,
+    pub dispatched_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        let now = Utc::now().naive_utc();
+        Self {
+            attempts: Set(0),
+            created_at: Set(now),
+            next_attempt_at: Set(now),
+            ..ActiveModelTrait::default()
+        }
+    }
+}
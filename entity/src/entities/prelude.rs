@@ -1,5 +1,16 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.17
 
+pub use super::api_key::Entity as ApiKey;
 pub use super::category::Entity as Category;
+pub use super::household::Entity as Household;
+pub use super::household_invitation::Entity as HouseholdInvitation;
+pub use super::household_member::Entity as HouseholdMember;
+pub use super::idempotency_key::Entity as IdempotencyKey;
+pub use super::outbox::Entity as Outbox;
 pub use super::record::Entity as Record;
+pub use super::record_event::Entity as RecordEvent;
+pub use super::record_share::Entity as RecordShare;
+pub use super::settlement::Entity as Settlement;
 pub use super::user::Entity as User;
+pub use super::webhook::Entity as Webhook;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
@@ -12,6 +12,8 @@ pub struct Model {
     pub category_id: Uuid,
     pub created_at: DateTime,
     pub sum: Decimal,
+    pub version: i32,
+    pub household_id: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,6 +26,16 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Category,
+    #[sea_orm(
+        belongs_to = "super::household::Entity",
+        from = "Column::HouseholdId",
+        to = "super::household::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Household,
+    #[sea_orm(has_many = "super::record_share::Entity")]
+    RecordShare,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::UserId",
@@ -40,6 +52,18 @@ impl Related<super::category::Entity> for Entity {
     }
 }
 
+impl Related<super::household::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Household.def()
+    }
+}
+
+impl Related<super::record_share::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecordShare.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
@@ -51,6 +75,7 @@ impl ActiveModelBehavior for ActiveModel {
         Self {
             id: Set(Uuid::new_v4()),
             created_at: Set(Utc::now().naive_utc()),
+            version: Set(1),
             ..ActiveModelTrait::default()
         }
     }
@@ -0,0 +1,44 @@
+use sea_orm::{ActiveValue::Set, entity::prelude::*, sqlx::types::chrono::Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "record_event")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub kind: Kind,
+    pub record_id: Uuid,
+    pub user_id: Uuid,
+    pub category_id: Uuid,
+    pub household_id: Option<Uuid>,
+    #[sea_orm(column_type = "JsonBinary")]
+    pub data: Json,
+    pub created_at: DateTime,
+}
+
+/// What happened to the record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+pub enum Kind {
+    #[sea_orm(string_value = "record.created")]
+    #[serde(rename = "record.created")]
+    Created,
+    #[sea_orm(string_value = "record.updated")]
+    #[serde(rename = "record.updated")]
+    Updated,
+    #[sea_orm(string_value = "record.deleted")]
+    #[serde(rename = "record.deleted")]
+    Deleted,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(Utc::now().naive_utc()),
+            ..ActiveModelTrait::default()
+        }
+    }
+}
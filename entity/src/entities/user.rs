@@ -9,12 +9,54 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
     pub name: String,
+    pub version: i32,
+    pub role: Role,
+    #[sea_orm(unique)]
+    pub token_hash: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    #[sea_orm(string_value = "member")]
+    Member,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_key::Entity")]
+    ApiKey,
+    #[sea_orm(has_many = "super::household_member::Entity")]
+    HouseholdMember,
     #[sea_orm(has_many = "super::record::Entity")]
     Record,
+    #[sea_orm(has_many = "super::webhook::Entity")]
+    Webhook,
+}
+
+impl Related<super::api_key::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiKey.def()
+    }
+}
+
+impl Related<super::household_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::HouseholdMember.def()
+    }
+}
+
+impl Related<super::household::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::household_member::Relation::Household.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::household_member::Relation::User.def().rev())
+    }
 }
 
 impl Related<super::record::Entity> for Entity {
@@ -23,10 +65,18 @@ impl Related<super::record::Entity> for Entity {
     }
 }
 
+impl Related<super::webhook::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Webhook.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
             id: Set(Uuid::new_v4()),
+            version: Set(1),
+            role: Set(Role::Member),
             ..ActiveModelTrait::default()
         }
     }
@@ -3,6 +3,18 @@ pub use sea_orm_migration::prelude::*;
 mod m20251026_160714_create_users_table;
 mod m20251026_233421_create_categories_table;
 mod m20251027_010727_create_records_table;
+mod m20251102_141503_create_idempotency_keys_table;
+mod m20251104_183250_add_version_columns;
+mod m20251109_152040_add_user_roles_and_tokens;
+mod m20251112_093115_create_households;
+mod m20251115_174208_create_shares_and_settlements;
+mod m20251118_110532_create_api_keys_table;
+mod m20251121_091847_create_record_events_table;
+mod m20251124_143602_create_webhooks;
+mod m20251127_101845_create_outbox_table;
+mod m20251130_120412_scope_idempotency_keys;
+mod m20251203_094516_deliver_webhooks_from_outbox;
+mod m20251206_152230_drop_personal_record_shares;
 
 pub struct Migrator;
 
@@ -13,6 +25,18 @@ impl MigratorTrait for Migrator {
             Box::new(m20251026_160714_create_users_table::Migration),
             Box::new(m20251026_233421_create_categories_table::Migration),
             Box::new(m20251027_010727_create_records_table::Migration),
+            Box::new(m20251102_141503_create_idempotency_keys_table::Migration),
+            Box::new(m20251104_183250_add_version_columns::Migration),
+            Box::new(m20251109_152040_add_user_roles_and_tokens::Migration),
+            Box::new(m20251112_093115_create_households::Migration),
+            Box::new(m20251115_174208_create_shares_and_settlements::Migration),
+            Box::new(m20251118_110532_create_api_keys_table::Migration),
+            Box::new(m20251121_091847_create_record_events_table::Migration),
+            Box::new(m20251124_143602_create_webhooks::Migration),
+            Box::new(m20251127_101845_create_outbox_table::Migration),
+            Box::new(m20251130_120412_scope_idempotency_keys::Migration),
+            Box::new(m20251203_094516_deliver_webhooks_from_outbox::Migration),
+            Box::new(m20251206_152230_drop_personal_record_shares::Migration),
         ]
     }
 }
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(IdempotencyKey::Table)
+                    .if_not_exists()
+                    .col(string(IdempotencyKey::Key).primary_key())
+                    .col(string(IdempotencyKey::RequestHash))
+                    .col(small_integer(IdempotencyKey::ResponseStatus))
+                    .col(string_null(IdempotencyKey::ResponseContentType))
+                    .col(blob(IdempotencyKey::ResponseBody))
+                    .col(date_time(IdempotencyKey::CreatedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IdempotencyKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum IdempotencyKey {
+    Table,
+    Key,
+    RequestHash,
+    ResponseStatus,
+    ResponseContentType,
+    ResponseBody,
+    CreatedAt,
+}
@@ -0,0 +1,52 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251026_160714_create_users_table::User;
+use crate::m20251026_233421_create_categories_table::Category;
+use crate::m20251027_010727_create_records_table::Record;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in tables() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .add_column(integer(Version::Version).default(1))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in tables() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(Version::Version)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn tables() -> [DynIden; 3] {
+    [
+        User::Table.into_iden(),
+        Category::Table.into_iden(),
+        Record::Table.into_iden(),
+    ]
+}
+
+#[derive(DeriveIden)]
+enum Version {
+    Version,
+}
@@ -0,0 +1,166 @@
+use sea_orm_migration::{prelude::*, schema::*, sea_orm::DatabaseBackend};
+
+use crate::m20251026_160714_create_users_table::User;
+use crate::m20251026_233421_create_categories_table::Category;
+use crate::m20251027_010727_create_records_table::Record;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Household::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(Household::Id))
+                    .col(string(Household::Name))
+                    .col(integer(Household::Version).default(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(HouseholdMember::Table)
+                    .if_not_exists()
+                    .col(uuid(HouseholdMember::HouseholdId))
+                    .col(uuid(HouseholdMember::UserId))
+                    .col(string_len(HouseholdMember::Role, 16))
+                    .primary_key(
+                        Index::create()
+                            .col(HouseholdMember::HouseholdId)
+                            .col(HouseholdMember::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_household_member_household_id")
+                            .from(HouseholdMember::Table, HouseholdMember::HouseholdId)
+                            .to(Household::Table, Household::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_household_member_user_id")
+                            .from(HouseholdMember::Table, HouseholdMember::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(HouseholdInvitation::Table)
+                    .if_not_exists()
+                    .col(string(HouseholdInvitation::Code).primary_key())
+                    .col(uuid(HouseholdInvitation::HouseholdId))
+                    .col(string_len(HouseholdInvitation::Role, 16))
+                    .col(date_time(HouseholdInvitation::ExpiresAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_household_invitation_household_id")
+                            .from(HouseholdInvitation::Table, HouseholdInvitation::HouseholdId)
+                            .to(Household::Table, Household::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (table, fk) in scoped_tables() {
+            let mut alter = Table::alter()
+                .table(table.clone())
+                .add_column(uuid_null(Scope::HouseholdId))
+                .to_owned();
+            // SQLite can't add foreign keys to existing tables.
+            if manager.get_database_backend() != DatabaseBackend::Sqlite {
+                alter.add_foreign_key(
+                    TableForeignKey::new()
+                        .name(fk)
+                        .from_tbl(table)
+                        .from_col(Scope::HouseholdId)
+                        .to_tbl(Household::Table)
+                        .to_col(Household::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                );
+            }
+            manager.alter_table(alter).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, fk) in scoped_tables() {
+            if manager.get_database_backend() != DatabaseBackend::Sqlite {
+                manager
+                    .drop_foreign_key(ForeignKey::drop().name(fk).table(table.clone()).to_owned())
+                    .await?;
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(Scope::HouseholdId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for table in [
+            HouseholdInvitation::Table.into_iden(),
+            HouseholdMember::Table.into_iden(),
+            Household::Table.into_iden(),
+        ] {
+            manager
+                .drop_table(Table::drop().table(table).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn scoped_tables() -> [(DynIden, &'static str); 2] {
+    [
+        (Category::Table.into_iden(), "fk_category_household_id"),
+        (Record::Table.into_iden(), "fk_record_household_id"),
+    ]
+}
+
+#[derive(DeriveIden)]
+pub enum Household {
+    Table,
+    Id,
+    Name,
+    Version,
+}
+
+#[derive(DeriveIden)]
+pub enum HouseholdMember {
+    Table,
+    HouseholdId,
+    UserId,
+    Role,
+}
+
+#[derive(DeriveIden)]
+pub enum HouseholdInvitation {
+    Table,
+    Code,
+    HouseholdId,
+    Role,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Scope {
+    HouseholdId,
+}
@@ -0,0 +1,113 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251026_160714_create_users_table::User;
+use crate::m20251027_010727_create_records_table::Record;
+use crate::m20251112_093115_create_households::Household;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecordShare::Table)
+                    .if_not_exists()
+                    .col(uuid(RecordShare::RecordId))
+                    .col(uuid(RecordShare::UserId))
+                    .col(decimal(RecordShare::Sum))
+                    .primary_key(
+                        Index::create()
+                            .col(RecordShare::RecordId)
+                            .col(RecordShare::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_record_share_record_id")
+                            .from(RecordShare::Table, RecordShare::RecordId)
+                            .to(Record::Table, Record::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_record_share_user_id")
+                            .from(RecordShare::Table, RecordShare::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Settlement::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(Settlement::Id))
+                    .col(uuid(Settlement::FromUserId))
+                    .col(uuid(Settlement::ToUserId))
+                    .col(decimal(Settlement::Sum))
+                    .col(uuid_null(Settlement::HouseholdId))
+                    .col(date_time(Settlement::CreatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_settlement_from_user_id")
+                            .from(Settlement::Table, Settlement::FromUserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_settlement_to_user_id")
+                            .from(Settlement::Table, Settlement::ToUserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_settlement_household_id")
+                            .from(Settlement::Table, Settlement::HouseholdId)
+                            .to(Household::Table, Household::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Settlement::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RecordShare::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RecordShare {
+    Table,
+    RecordId,
+    UserId,
+    Sum,
+}
+
+#[derive(DeriveIden)]
+enum Settlement {
+    Table,
+    Id,
+    FromUserId,
+    ToUserId,
+    Sum,
+    HouseholdId,
+    CreatedAt,
+}
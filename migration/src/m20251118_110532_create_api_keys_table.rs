@@ -0,0 +1,55 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251026_160714_create_users_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKey::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(ApiKey::Id))
+                    .col(uuid(ApiKey::UserId))
+                    .col(string(ApiKey::Name))
+                    .col(string_uniq(ApiKey::SecretHash))
+                    .col(string(ApiKey::Scopes))
+                    .col(date_time(ApiKey::CreatedAt))
+                    .col(date_time_null(ApiKey::ExpiresAt))
+                    .col(date_time_null(ApiKey::LastUsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_api_key_user_id")
+                            .from(ApiKey::Table, ApiKey::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiKey {
+    Table,
+    Id,
+    UserId,
+    Name,
+    SecretHash,
+    Scopes,
+    CreatedAt,
+    ExpiresAt,
+    LastUsedAt,
+}
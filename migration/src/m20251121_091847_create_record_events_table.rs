@@ -0,0 +1,56 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // No foreign keys, the events outlive the records they're about.
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecordEvent::Table)
+                    .if_not_exists()
+                    .col(big_integer(RecordEvent::Id).auto_increment().primary_key())
+                    .col(string_len(RecordEvent::Kind, 16))
+                    .col(uuid(RecordEvent::RecordId))
+                    .col(uuid(RecordEvent::UserId))
+                    .col(uuid(RecordEvent::CategoryId))
+                    .col(uuid_null(RecordEvent::HouseholdId))
+                    .col(json_binary(RecordEvent::Data))
+                    .col(date_time(RecordEvent::CreatedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_record_event_created_at")
+                    .table(RecordEvent::Table)
+                    .col(RecordEvent::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RecordEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RecordEvent {
+    Table,
+    Id,
+    Kind,
+    RecordId,
+    UserId,
+    CategoryId,
+    HouseholdId,
+    Data,
+    CreatedAt,
+}
@@ -0,0 +1,113 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::m20251026_160714_create_users_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Webhook::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(Webhook::Id))
+                    .col(uuid(Webhook::UserId))
+                    .col(string(Webhook::Url))
+                    .col(string(Webhook::Events))
+                    .col(string(Webhook::Secret))
+                    .col(date_time(Webhook::CreatedAt))
+                    .col(integer(Webhook::Version).default(1))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webhook_user_id")
+                            .from(Webhook::Table, Webhook::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(WebhookDelivery::Id))
+                    .col(uuid(WebhookDelivery::WebhookId))
+                    .col(big_integer(WebhookDelivery::EventId))
+                    .col(string_len(WebhookDelivery::Kind, 16))
+                    .col(json_binary(WebhookDelivery::Payload))
+                    .col(string_len(WebhookDelivery::Status, 16))
+                    .col(integer(WebhookDelivery::Attempts).default(0))
+                    .col(date_time(WebhookDelivery::NextAttemptAt))
+                    .col(integer_null(WebhookDelivery::ResponseStatus))
+                    .col(text_null(WebhookDelivery::LastError))
+                    .col(date_time(WebhookDelivery::CreatedAt))
+                    .col(date_time_null(WebhookDelivery::DeliveredAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webhook_delivery_webhook_id")
+                            .from(WebhookDelivery::Table, WebhookDelivery::WebhookId)
+                            .to(Webhook::Table, Webhook::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_status_next_attempt_at")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::Status)
+                    .col(WebhookDelivery::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Webhook::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Webhook {
+    Table,
+    Id,
+    UserId,
+    Url,
+    Events,
+    Secret,
+    CreatedAt,
+    Version,
+}
+
+#[derive(DeriveIden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    WebhookId,
+    EventId,
+    Kind,
+    Payload,
+    Status,
+    Attempts,
+    NextAttemptAt,
+    ResponseStatus,
+    LastError,
+    CreatedAt,
+    DeliveredAt,
+}
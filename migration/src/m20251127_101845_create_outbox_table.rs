@@ -0,0 +1,58 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Outbox::Table)
+                    .if_not_exists()
+                    .col(big_integer(Outbox::Id).auto_increment().primary_key())
+                    .col(string_len(Outbox::Kind, 32))
+                    .col(json_binary(Outbox::Payload))
+                    .col(date_time(Outbox::CreatedAt))
+                    .col(integer(Outbox::Attempts).default(0))
+                    .col(date_time(Outbox::NextAttemptAt))
+                    .col(text_null(Outbox::PendingSubscribers))
+                    .col(text_null(Outbox::LastError))
+                    .col(date_time_null(Outbox::DispatchedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_outbox_dispatched_at_next_attempt_at")
+                    .table(Outbox::Table)
+                    .col(Outbox::DispatchedAt)
+                    .col(Outbox::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Outbox::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Outbox {
+    Table,
+    Id,
+    Kind,
+    Payload,
+    CreatedAt,
+    Attempts,
+    NextAttemptAt,
+    PendingSubscribers,
+    LastError,
+    DispatchedAt,
+}
@@ -0,0 +1,80 @@
+//! Scopes idempotency keys to their caller and reserves them while their request
+//! runs. Stored responses only live for a day, so the table is recreated rather
+//! than converted.
+
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IdempotencyKey::Table).to_owned())
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(IdempotencyKey::Table)
+                    .if_not_exists()
+                    .col(string(IdempotencyKey::Scope))
+                    .col(string(IdempotencyKey::Key))
+                    .col(string(IdempotencyKey::RequestHash))
+                    .col(small_integer_null(IdempotencyKey::ResponseStatus))
+                    .col(json_binary_null(IdempotencyKey::ResponseHeaders))
+                    .col(blob_null(IdempotencyKey::ResponseBody))
+                    .col(date_time(IdempotencyKey::CreatedAt))
+                    .primary_key(
+                        Index::create()
+                            .col(IdempotencyKey::Scope)
+                            .col(IdempotencyKey::Key),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_idempotency_key_created_at")
+                    .table(IdempotencyKey::Table)
+                    .col(IdempotencyKey::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IdempotencyKey::Table).to_owned())
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(IdempotencyKey::Table)
+                    .if_not_exists()
+                    .col(string(IdempotencyKey::Key).primary_key())
+                    .col(string(IdempotencyKey::RequestHash))
+                    .col(small_integer(IdempotencyKey::ResponseStatus))
+                    .col(string_null(IdempotencyKey::ResponseContentType))
+                    .col(blob(IdempotencyKey::ResponseBody))
+                    .col(date_time(IdempotencyKey::CreatedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum IdempotencyKey {
+    Table,
+    Scope,
+    Key,
+    RequestHash,
+    ResponseStatus,
+    ResponseHeaders,
+    ResponseContentType,
+    ResponseBody,
+    CreatedAt,
+}
@@ -0,0 +1,91 @@
+//! Delivers webhooks from the outbox, which schedules their attempts, so
+//! deliveries refer to outbox events rather than record events and each event is
+//! delivered to a webhook once. The deliveries made before can't be told apart
+//! from the new ones by their event IDs, and are only kept as a log, so they're
+//! dropped.
+
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(WebhookDelivery::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_webhook_delivery_status_next_attempt_at")
+                    .table(WebhookDelivery::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WebhookDelivery::Table)
+                    .drop_column(WebhookDelivery::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_event_id_webhook_id")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::EventId)
+                    .col(WebhookDelivery::WebhookId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_webhook_delivery_event_id_webhook_id")
+                    .table(WebhookDelivery::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WebhookDelivery::Table)
+                    .add_column(
+                        date_time(WebhookDelivery::NextAttemptAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_status_next_attempt_at")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::Status)
+                    .col(WebhookDelivery::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebhookDelivery {
+    Table,
+    EventId,
+    WebhookId,
+    Status,
+    NextAttemptAt,
+}
@@ -0,0 +1,47 @@
+//! Only household records can be split, between members of their household, so
+//! the shares of personal records, which may name any user, are dropped. They
+//! can't be restored.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(RecordShare::Table)
+                    .and_where(
+                        Expr::col(RecordShare::RecordId).in_subquery(
+                            Query::select()
+                                .column(Record::Id)
+                                .from(Record::Table)
+                                .and_where(Expr::col(Record::HouseholdId).is_null())
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum RecordShare {
+    Table,
+    RecordId,
+}
+
+#[derive(DeriveIden)]
+enum Record {
+    Table,
+    Id,
+    HouseholdId,
+}
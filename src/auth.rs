@@ -0,0 +1,401 @@
+//! Identifies callers by bearer token or API key and decides what they may do.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, OptionalFromRequestParts},
+    http::{HeaderMap, Method, header, request::Parts},
+};
+use chrono::{Duration, Utc};
+use entity::{api_key, household_member, user};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, TransactionTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::AppError,
+    outbox::{self, DomainEvent},
+};
+
+/// What a user is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Manages users and categories, and sees every record.
+    Admin,
+    /// Manages their own account and records.
+    Member,
+}
+
+impl From<user::Role> for Role {
+    fn from(value: user::Role) -> Self {
+        match value {
+            user::Role::Admin => Self::Admin,
+            user::Role::Member => Self::Member,
+        }
+    }
+}
+
+impl From<Role> for user::Role {
+    fn from(value: Role) -> Self {
+        match value {
+            Role::Admin => Self::Admin,
+            Role::Member => Self::Member,
+        }
+    }
+}
+
+/// Prefixes API keys, telling them apart from user tokens.
+pub const API_KEY_PREFIX: &str = "key_";
+
+/// How often an API key's last use is recorded, rather than on every request.
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// What an API key may access on behalf of its user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    /// Reading users.
+    #[serde(rename = "users:read")]
+    UsersRead,
+    /// Creating, changing and deleting users.
+    #[serde(rename = "users:write")]
+    UsersWrite,
+    /// Reading households.
+    #[serde(rename = "households:read")]
+    HouseholdsRead,
+    /// Creating, changing and deleting households.
+    #[serde(rename = "households:write")]
+    HouseholdsWrite,
+    /// Reading categories.
+    #[serde(rename = "categories:read")]
+    CategoriesRead,
+    /// Creating, changing and deleting categories.
+    #[serde(rename = "categories:write")]
+    CategoriesWrite,
+    /// Reading records, their shares and summaries.
+    #[serde(rename = "records:read")]
+    RecordsRead,
+    /// Creating, changing and deleting records.
+    #[serde(rename = "records:write")]
+    RecordsWrite,
+    /// Reading settlements and balances.
+    #[serde(rename = "settlements:read")]
+    SettlementsRead,
+    /// Recording and voiding settlements.
+    #[serde(rename = "settlements:write")]
+    SettlementsWrite,
+    /// Reading webhooks and their deliveries.
+    #[serde(rename = "webhooks:read")]
+    WebhooksRead,
+    /// Creating, changing, deleting and retrying webhooks.
+    #[serde(rename = "webhooks:write")]
+    WebhooksWrite,
+}
+
+impl Scope {
+    const ALL: [Self; 12] = [
+        Self::UsersRead,
+        Self::UsersWrite,
+        Self::HouseholdsRead,
+        Self::HouseholdsWrite,
+        Self::CategoriesRead,
+        Self::CategoriesWrite,
+        Self::RecordsRead,
+        Self::RecordsWrite,
+        Self::SettlementsRead,
+        Self::SettlementsWrite,
+        Self::WebhooksRead,
+        Self::WebhooksWrite,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UsersRead => "users:read",
+            Self::UsersWrite => "users:write",
+            Self::HouseholdsRead => "households:read",
+            Self::HouseholdsWrite => "households:write",
+            Self::CategoriesRead => "categories:read",
+            Self::CategoriesWrite => "categories:write",
+            Self::RecordsRead => "records:read",
+            Self::RecordsWrite => "records:write",
+            Self::SettlementsRead => "settlements:read",
+            Self::SettlementsWrite => "settlements:write",
+            Self::WebhooksRead => "webhooks:read",
+            Self::WebhooksWrite => "webhooks:write",
+        }
+    }
+
+    /// Parses scopes stored space-separated, skipping unknown ones.
+    pub fn parse_all(scopes: &str) -> Vec<Self> {
+        scopes
+            .split_whitespace()
+            .filter_map(|scope| Self::ALL.into_iter().find(|known| known.as_str() == scope))
+            .collect()
+    }
+
+    /// The scope a request needs, if API keys may make it at all.
+    ///
+    /// API keys can't manage other API keys or use GraphQL, whose queries span
+    /// every resource, nor create users or issue tokens, which authenticate
+    /// without any scope limits.
+    fn required(parts: &Parts) -> Option<Self> {
+        let path = parts
+            .extensions
+            .get::<MatchedPath>()
+            .map_or(parts.uri.path(), MatchedPath::as_str);
+        let issues_token = matches!(
+            path.trim_end_matches('/'),
+            "/users" | "/users/{user_id}/token"
+        );
+        if parts.method == Method::POST && issues_token {
+            return None;
+        }
+        let resource = path.strip_prefix('/')?.split('/').next()?;
+        let read = matches!(parts.method, Method::GET | Method::HEAD);
+        let scope = match (resource, read) {
+            ("users", true) => Self::UsersRead,
+            ("users", false) => Self::UsersWrite,
+            ("households", true) => Self::HouseholdsRead,
+            ("households", false) => Self::HouseholdsWrite,
+            ("categories", true) => Self::CategoriesRead,
+            ("categories", false) => Self::CategoriesWrite,
+            ("records", true) => Self::RecordsRead,
+            ("records", false) => Self::RecordsWrite,
+            ("settlements", true) => Self::SettlementsRead,
+            ("settlements", false) => Self::SettlementsWrite,
+            ("webhooks", true) => Self::WebhooksRead,
+            ("webhooks", false) => Self::WebhooksWrite,
+            _ => return None,
+        };
+        Some(scope)
+    }
+}
+
+/// The user making the request, authenticated by the `Authorization: Bearer` header.
+#[derive(Clone, Copy, Debug)]
+pub struct Caller {
+    pub id: Uuid,
+    pub role: Role,
+}
+
+impl Caller {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        match self.is_admin() {
+            true => Ok(()),
+            false => Err(AppError::Forbidden),
+        }
+    }
+
+    /// Allows admins, and the user with `id` acting on their own behalf.
+    pub fn require_user(&self, id: Uuid) -> Result<(), AppError> {
+        match self.is_admin() || self.id == id {
+            true => Ok(()),
+            false => Err(AppError::Forbidden),
+        }
+    }
+
+    /// Allows admins, and members of the household with at least the `min` role.
+    pub async fn require_household(
+        &self,
+        db: &DatabaseConnection,
+        household_id: Uuid,
+        min: household_member::Role,
+    ) -> Result<(), AppError> {
+        if self.is_admin() {
+            return Ok(());
+        }
+        let member = household_member::Entity::find_by_id((household_id, self.id))
+            .one(db)
+            .await?;
+        match member {
+            Some(member) if member.role >= min => Ok(()),
+            _ => Err(AppError::Forbidden),
+        }
+    }
+
+    /// Loads the caller's roles in all of their households, to authorize many items at once.
+    pub async fn memberships(&self, db: &DatabaseConnection) -> Result<Memberships, AppError> {
+        let roles = household_member::Entity::find()
+            .filter(household_member::Column::UserId.eq(self.id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|member| (member.household_id, member.role))
+            .collect();
+        Ok(Memberships {
+            admin: self.is_admin(),
+            roles,
+        })
+    }
+}
+
+/// The caller's roles in the households they belong to.
+pub struct Memberships {
+    admin: bool,
+    roles: HashMap<Uuid, household_member::Role>,
+}
+
+impl Memberships {
+    /// Allows admins, and members of the household with at least the `min` role.
+    pub fn require(&self, household_id: Uuid, min: household_member::Role) -> Result<(), AppError> {
+        match self.admin
+            || self
+                .roles
+                .get(&household_id)
+                .is_some_and(|role| *role >= min)
+        {
+            true => Ok(()),
+            false => Err(AppError::Forbidden),
+        }
+    }
+
+    /// The households the caller belongs to.
+    pub fn households(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.roles.keys().copied()
+    }
+}
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        <Self as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state)
+            .await?
+            .ok_or(AppError::Unauthorized)
+    }
+}
+
+/// No caller if the request has no credentials, but invalid ones are still rejected.
+impl OptionalFromRequestParts<AppState> for Caller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        if let Some(caller) = parts.extensions.get::<Self>() {
+            return Ok(Some(*caller));
+        }
+        let Some(token) = bearer_token(&parts.headers)? else {
+            return Ok(None);
+        };
+        let user = match token.starts_with(API_KEY_PREFIX) {
+            true => authenticate_key(parts, &state.db, token).await?,
+            false => user::Entity::find()
+                .filter(user::Column::TokenHash.eq(hash_token(token)))
+                .one(&state.db)
+                .await?
+                .ok_or(AppError::Unauthorized)?,
+        };
+        let caller = Self {
+            id: user.id,
+            role: user.role.into(),
+        };
+        parts.extensions.insert(caller);
+        Ok(Some(caller))
+    }
+}
+
+/// Finds the user an API key acts for, if it's valid and has the scope the request needs.
+async fn authenticate_key(
+    parts: &Parts,
+    db: &DatabaseConnection,
+    key: &str,
+) -> Result<user::Model, AppError> {
+    let now = Utc::now().naive_utc();
+    let (key, user) = api_key::Entity::find()
+        .filter(api_key::Column::SecretHash.eq(hash_token(key)))
+        .find_also_related(user::Entity)
+        .one(db)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let user = user.ok_or(AppError::Unauthorized)?;
+    if key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(AppError::Unauthorized);
+    }
+    match Scope::required(parts) {
+        Some(scope) if Scope::parse_all(&key.scopes).contains(&scope) => {}
+        _ => return Err(AppError::Forbidden),
+    }
+
+    api_key::Entity::update_many()
+        .col_expr(api_key::Column::LastUsedAt, Expr::value(now))
+        .filter(api_key::Column::Id.eq(key.id))
+        .filter(
+            Condition::any()
+                .add(api_key::Column::LastUsedAt.is_null())
+                .add(
+                    api_key::Column::LastUsedAt
+                        .lt(now - Duration::seconds(LAST_USED_RESOLUTION_SECS)),
+                ),
+        )
+        .exec(db)
+        .await?;
+    Ok(user)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, AppError> {
+    let Some(value) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| Some(token.trim()))
+        .ok_or(AppError::Unauthorized)
+}
+
+/// A new random token of 64 hex digits.
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// A new random API key.
+pub fn generate_api_key() -> String {
+    format!("{API_KEY_PREFIX}{}", generate_token())
+}
+
+/// Tokens are only stored hashed, so a leaked database doesn't leak credentials.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token))
+}
+
+/// Creates a user with `role`, returning it along with its token.
+pub async fn create_user(
+    db: &DatabaseConnection,
+    name: String,
+    role: Role,
+) -> Result<(user::Model, String), DbErr> {
+    let token = generate_token();
+    let user = user::ActiveModel {
+        name: Set(name),
+        role: Set(role.into()),
+        token_hash: Set(Some(hash_token(&token))),
+        ..Default::default()
+    };
+    let txn = db.begin().await?;
+    let user = user.insert(&txn).await?;
+    let created = DomainEvent::UserCreated {
+        user_id: user.id,
+        name: user.name.clone(),
+        role: user.role,
+    };
+    outbox::publish(&txn, [created]).await?;
+    txn.commit().await?;
+    Ok((user, token))
+}
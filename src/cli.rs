@@ -0,0 +1,59 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(version, about = "The expense tracker API")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs the server, the default if no command is given.
+    Serve {
+        /// Don't apply pending migrations before serving.
+        #[arg(long)]
+        no_migrate: bool,
+    },
+    /// Manages the database schema.
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manages users, e.g. to create the first admin.
+    #[command(subcommand)]
+    Users(UsersCommand),
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Serve { no_migrate: false }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Applies pending migrations.
+    Up {
+        /// How many migrations to apply, all if not set.
+        #[arg(short = 'n', long)]
+        steps: Option<u32>,
+    },
+    /// Reverts applied migrations.
+    Down {
+        /// How many migrations to revert.
+        #[arg(short = 'n', long, default_value_t = 1)]
+        steps: u32,
+    },
+    /// Lists migrations and whether they've been applied.
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum UsersCommand {
+    /// Creates a user and prints its token.
+    Create {
+        name: String,
+        /// Makes the user an admin rather than a member.
+        #[arg(long)]
+        admin: bool,
+    },
+}
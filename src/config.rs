@@ -0,0 +1,388 @@
+//! Settings read from the environment, `.env` and an optional TOML file.
+//!
+//! Every setting has an environment variable and a key in the TOML file, e.g.
+//! `DATABASE_MAX_CONNECTIONS` and `database.max_connections`. The environment
+//! takes precedence over the file, which takes precedence over the defaults.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::HeaderValue;
+use serde::{Deserialize, de::DeserializeOwned};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    idempotency,
+    limits::{self, Quota, RateLimits},
+};
+
+/// The file read when `CONFIG_FILE` isn't set, if it exists.
+const DEFAULT_FILE: &str = "config.toml";
+
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub log: LogConfig,
+    pub cors: CorsConfig,
+    pub features: Features,
+    pub idempotency_key_ttl: chrono::Duration,
+    pub rate_limit: RateLimits,
+    /// Whether webhooks may receive deliveries on loopback, private and
+    /// link-local addresses.
+    pub webhooks_private_networks: bool,
+}
+
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Serves metrics on a separate port instead of the API's.
+    pub metrics_port: Option<u16>,
+    /// How long in-flight requests and background tasks get to finish on shutdown.
+    pub shutdown_timeout: Duration,
+    /// The largest request body accepted, in bytes.
+    pub max_body_size: usize,
+    /// How long a request may take to handle.
+    pub request_timeout: Duration,
+}
+
+impl ServerConfig {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn metrics_addr(&self) -> Option<String> {
+        self.metrics_port
+            .map(|port| format!("{}:{}", self.host, port))
+    }
+}
+
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout: Duration,
+    pub idle_timeout: Duration,
+}
+
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Directives in the `RUST_LOG` syntax, e.g. `info,sqlx=debug`.
+    pub filter: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err("expected `text` or `json`".to_owned()),
+        }
+    }
+}
+
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests, none if empty.
+    pub allowed_origins: Vec<HeaderValue>,
+}
+
+/// Parts of the app that can be turned off.
+pub struct Features {
+    /// The OpenAPI document and Swagger UI.
+    pub docs: bool,
+    pub metrics: bool,
+    /// Compressing responses with gzip, Brotli or Zstandard.
+    pub compression: bool,
+}
+
+/// Every invalid setting found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut loader = Loader::new();
+
+        let server = ServerConfig {
+            host: loader.or("HOST", "server.host", "0.0.0.0".to_owned()),
+            port: loader.or("PORT", "server.port", 8080),
+            metrics_port: loader.value("METRICS_PORT", "server.metrics_port"),
+            shutdown_timeout: Duration::from_secs(loader.or(
+                "SHUTDOWN_TIMEOUT_SECS",
+                "server.shutdown_timeout_secs",
+                25,
+            )),
+            max_body_size: loader.or(
+                "MAX_BODY_SIZE",
+                "server.max_body_size",
+                limits::DEFAULT_MAX_BODY_SIZE,
+            ),
+            request_timeout: Duration::from_secs(loader.or(
+                "REQUEST_TIMEOUT_SECS",
+                "server.request_timeout_secs",
+                limits::DEFAULT_REQUEST_TIMEOUT_SECS,
+            )),
+        };
+        if server.request_timeout.is_zero() {
+            loader.error("REQUEST_TIMEOUT_SECS: must be at least 1");
+        }
+        if server.metrics_port == Some(server.port) {
+            loader.error("METRICS_PORT: must differ from PORT");
+        }
+
+        let database = DatabaseConfig {
+            url: loader.required("DATABASE_URL", "database.url"),
+            max_connections: loader.or("DATABASE_MAX_CONNECTIONS", "database.max_connections", 10),
+            min_connections: loader.or("DATABASE_MIN_CONNECTIONS", "database.min_connections", 0),
+            connect_timeout: Duration::from_secs(loader.or(
+                "DATABASE_CONNECT_TIMEOUT_SECS",
+                "database.connect_timeout_secs",
+                30,
+            )),
+            idle_timeout: Duration::from_secs(loader.or(
+                "DATABASE_IDLE_TIMEOUT_SECS",
+                "database.idle_timeout_secs",
+                600,
+            )),
+        };
+        if database.max_connections == 0 {
+            loader.error("DATABASE_MAX_CONNECTIONS: must be at least 1");
+        }
+        if database.min_connections > database.max_connections {
+            loader.error("DATABASE_MIN_CONNECTIONS: must not exceed DATABASE_MAX_CONNECTIONS");
+        }
+        if database.connect_timeout.is_zero() {
+            loader.error("DATABASE_CONNECT_TIMEOUT_SECS: must be at least 1");
+        }
+
+        let log = LogConfig {
+            format: loader.or("LOG_FORMAT", "log.format", LogFormat::Text),
+            filter: loader.or("RUST_LOG", "log.filter", "info".to_owned()),
+        };
+        if let Err(e) = EnvFilter::try_new(&log.filter) {
+            loader.error(format!("RUST_LOG: {e}"));
+        }
+
+        let origins: List = loader.or(
+            "CORS_ALLOWED_ORIGINS",
+            "cors.allowed_origins",
+            List::default(),
+        );
+        let mut allowed_origins = Vec::new();
+        for origin in origins.0 {
+            match HeaderValue::from_str(&origin) {
+                Ok(value) if origin.starts_with("http://") || origin.starts_with("https://") => {
+                    allowed_origins.push(value)
+                }
+                _ => loader.error(format!(
+                    "CORS_ALLOWED_ORIGINS: `{origin}` isn't an origin like `https://example.com`"
+                )),
+            }
+        }
+        let cors = CorsConfig { allowed_origins };
+
+        let features = Features {
+            docs: loader.or("DOCS_ENABLED", "features.docs", true),
+            metrics: loader.or("METRICS_ENABLED", "features.metrics", true),
+            compression: loader.or("COMPRESSION_ENABLED", "features.compression", true),
+        };
+
+        let ttl: u32 = loader.or(
+            "IDEMPOTENCY_KEY_TTL_SECS",
+            "idempotency.key_ttl_secs",
+            idempotency::DEFAULT_TTL_SECS,
+        );
+
+        let default: Quota = loader.or(
+            "RATE_LIMIT_DEFAULT",
+            "rate_limit.default",
+            Quota::new(300, Duration::from_secs(60)),
+        );
+        let groups: Quotas = loader.or("RATE_LIMIT_GROUPS", "rate_limit.groups", Quotas::default());
+        let rate_limit = groups
+            .0
+            .into_iter()
+            .fold(RateLimits::new(default), |limits, (group, quota)| {
+                limits.group(group, quota)
+            });
+
+        let webhooks_private_networks = loader.or(
+            "WEBHOOKS_PRIVATE_NETWORKS",
+            "webhooks.private_networks",
+            false,
+        );
+
+        loader.finish()?;
+        Ok(Self {
+            server,
+            database,
+            log,
+            cors,
+            features,
+            idempotency_key_ttl: chrono::Duration::seconds(ttl.into()),
+            rate_limit,
+            webhooks_private_networks,
+        })
+    }
+}
+
+/// A comma-separated list in the environment, an array in the file.
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+struct List(Vec<String>);
+
+impl FromStr for List {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items = s
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Ok(Self(items))
+    }
+}
+
+/// Quotas by route group, `group=quota` pairs separated by commas in the
+/// environment, a table in the file.
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+struct Quotas(BTreeMap<String, Quota>);
+
+impl FromStr for Quotas {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut quotas = BTreeMap::new();
+        for pair in List::from_str(s)?.0 {
+            let (group, quota) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected `group=quota`, got `{pair}`"))?;
+            quotas.insert(group.trim().to_owned(), quota.trim().parse()?);
+        }
+        Ok(Self(quotas))
+    }
+}
+
+/// Looks settings up, collecting errors instead of stopping at the first one.
+struct Loader {
+    file: Option<(PathBuf, toml::Table)>,
+    errors: Vec<String>,
+}
+
+impl Loader {
+    fn new() -> Self {
+        let mut loader = Self {
+            file: None,
+            errors: Vec::new(),
+        };
+        let path = match env::var_os("CONFIG_FILE") {
+            Some(path) => PathBuf::from(path),
+            None if Path::new(DEFAULT_FILE).exists() => PathBuf::from(DEFAULT_FILE),
+            None => return loader,
+        };
+        let table = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()));
+        match table {
+            Ok(table) => loader.file = Some((path, table)),
+            Err(e) => loader.error(format!("CONFIG_FILE: can't read {}: {e}", path.display())),
+        }
+        loader
+    }
+
+    fn error(&mut self, error: impl Into<String>) {
+        self.errors.push(error.into());
+    }
+
+    /// The setting from the environment or the file, if set in either.
+    fn value<T>(&mut self, var: &str, key: &str) -> Option<T>
+    where
+        T: FromStr + DeserializeOwned,
+        T::Err: fmt::Display,
+    {
+        if let Ok(value) = env::var(var)
+            && !value.is_empty()
+        {
+            return match value.parse() {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.error(format!("{var}: `{value}` is invalid: {e}"));
+                    None
+                }
+            };
+        }
+
+        let (path, table) = self.file.as_ref()?;
+        let mut parts = key.split('.');
+        let mut value = table.get(parts.next()?)?;
+        for part in parts {
+            value = value.get(part)?;
+        }
+        match T::deserialize(value.clone()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                let error = format!("`{key}` in {}: {}", path.display(), e.message());
+                self.error(error);
+                None
+            }
+        }
+    }
+
+    fn or<T>(&mut self, var: &str, key: &str, default: T) -> T
+    where
+        T: FromStr + DeserializeOwned,
+        T::Err: fmt::Display,
+    {
+        self.value(var, key).unwrap_or(default)
+    }
+
+    fn required<T>(&mut self, var: &str, key: &str) -> T
+    where
+        T: FromStr + DeserializeOwned + Default,
+        T::Err: fmt::Display,
+    {
+        let errors = self.errors.len();
+        self.value(var, key).unwrap_or_else(|| {
+            if self.errors.len() == errors {
+                self.error(format!("{var}: must be set, or `{key}` in the config file"));
+            }
+            T::default()
+        })
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(self.errors))
+        }
+    }
+}
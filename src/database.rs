@@ -1,9 +1,42 @@
-use std::{env, error::Error};
+use std::{error::Error, time::Duration};
 
-use sea_orm::{Database, DatabaseConnection};
+use log::LevelFilter;
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 
-pub async fn connect() -> Result<DatabaseConnection, Box<dyn Error>> {
-    let db_url = env::var("DATABASE_URL")?;
-    let db = Database::connect(db_url).await?;
+use crate::{config::DatabaseConfig, metrics};
+
+/// Connects to the database `config.url` points at, Postgres or, with the
+/// `sqlite` feature, SQLite.
+pub async fn connect(config: &DatabaseConfig) -> Result<DatabaseConnection, Box<dyn Error>> {
+    let sqlite = config.url.starts_with("sqlite:");
+    if sqlite && !cfg!(feature = "sqlite") {
+        return Err("SQLite support isn't enabled, build with `--features sqlite`".into());
+    }
+
+    let mut options = ConnectOptions::new(&config.url);
+    if sqlite && is_in_memory(&config.url) {
+        // An in-memory database lives as long as its connection, so the pool
+        // must keep exactly one, forever.
+        options
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(Duration::MAX)
+            .max_lifetime(Duration::MAX);
+    } else {
+        options
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .idle_timeout(config.idle_timeout);
+    }
+    options
+        .connect_timeout(config.connect_timeout)
+        .sqlx_logging(true)
+        .sqlx_logging_level(LevelFilter::Debug);
+    let mut db = Database::connect(options).await?;
+    db.set_metric_callback(metrics::record_query);
     Ok(db)
 }
+
+fn is_in_memory(url: &str) -> bool {
+    url.contains(":memory:") || url.contains("mode=memory")
+}
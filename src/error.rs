@@ -1,76 +1,316 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
 
+use async_graphql::ErrorExtensions;
 use axum::{
     Json,
-    http::StatusCode,
+    extract::Request,
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
-use sea_orm::DbErr;
+use sea_orm::{DbErr, SqlErr};
 use serde::Serialize;
+use serde_json::{Map, Value};
+use tower_http::request_id::RequestId;
+use utoipa::{
+    ToResponse, ToSchema,
+    openapi::{ContentBuilder, Ref, RefOr, ResponseBuilder, response::Response as ResponseSchema},
+};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
 
 pub enum AppError {
+    BadRequest(Vec<FieldError>),
+    Unauthorized,
+    Forbidden,
     NotFound,
-    UnprocessableEntity(UnprocessableEntityBody),
+    Conflict,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    UnprocessableEntity(Vec<FieldError>),
+    PreconditionRequired,
+    /// The client may retry after `retry_after` seconds.
+    TooManyRequests {
+        retry_after: u64,
+    },
+    /// The database has no connection to spare.
+    ServiceUnavailable,
+    /// The request took longer than the timeout to handle.
+    GatewayTimeout,
     Database(DbErr),
+    Internal(axum::Error),
 }
 
-#[derive(Serialize)]
-struct GenericErrorBody {
-    error: String,
+/// An error in a single field of the request, identified by a stable `code`.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    #[schema(value_type = String)]
+    field: Cow<'static, str>,
+    code: &'static str,
+    #[schema(value_type = String)]
+    detail: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    #[schema(value_type = Object)]
+    params: Map<String, Value>,
 }
 
-impl GenericErrorBody {
-    fn from_status(status: StatusCode) -> Self {
+impl FieldError {
+    pub fn new(
+        field: impl Into<Cow<'static, str>>,
+        code: &'static str,
+        detail: impl Into<Cow<'static, str>>,
+    ) -> Self {
         Self {
-            error: status.to_string(),
+            field: field.into(),
+            code,
+            detail: detail.into(),
+            params: Map::new(),
         }
     }
+
+    /// Adds a parameter clients can use to render their own message, e.g. a maximum length.
+    pub fn param(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(name.to_owned(), value.into());
+        self
+    }
 }
 
-#[derive(Serialize)]
-struct UnprocessableEntityBody {
-    errors: HashMap<&'static str, &'static str>,
+/// An RFC 7807 problem details object.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: String,
+    title: &'static str,
+    status: u16,
+    detail: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    /// The request's `X-Request-Id`, to look it up in the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl Problem {
+    fn new(status: StatusCode, code: &'static str, detail: &'static str) -> Self {
+        Self {
+            kind: format!("/problems/{}", code.replace('_', "-")),
+            title: status.canonical_reason().unwrap_or("Unknown Error"),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            request_id: None,
+            code,
+            errors: Vec::new(),
+        }
+    }
+
+    fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl<'s> ToResponse<'s> for Problem {
+    fn response() -> (&'s str, RefOr<ResponseSchema>) {
+        let response = ResponseBuilder::new()
+            .description("The request failed, the problem details object describes why.")
+            .content(
+                PROBLEM_JSON,
+                ContentBuilder::new()
+                    .schema(Some(Ref::from_schema_name(Problem::name())))
+                    .build(),
+            )
+            .build();
+        ("Problem", response.into())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut res = (status, Json(&self)).into_response();
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        res.extensions_mut().insert(self);
+        res
+    }
 }
 
 impl AppError {
-    pub fn unprocessable_entity(
-        errors: impl IntoIterator<Item = (&'static str, &'static str)>,
-    ) -> Self {
-        Self::UnprocessableEntity(UnprocessableEntityBody {
-            errors: errors.into_iter().collect(),
-        })
+    pub fn bad_request(errors: impl IntoIterator<Item = FieldError>) -> Self {
+        Self::BadRequest(errors.into_iter().collect())
+    }
+
+    pub fn unprocessable_entity(errors: impl IntoIterator<Item = FieldError>) -> Self {
+        Self::UnprocessableEntity(errors.into_iter().collect())
     }
 
     fn status_code(&self) -> StatusCode {
         match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::GatewayTimeout => StatusCode::GATEWAY_TIMEOUT,
             Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The stable, machine-readable identifier of the error.
+    fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "bad_request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not_found",
+            Self::Conflict => "conflict",
+            Self::PreconditionFailed => "precondition_failed",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::UnprocessableEntity(_) => "validation_failed",
+            Self::PreconditionRequired => "precondition_required",
+            Self::TooManyRequests { .. } => "rate_limited",
+            Self::ServiceUnavailable => "service_unavailable",
+            Self::GatewayTimeout => "timeout",
+            Self::Database(_) => "internal_error",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    fn detail(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "The request is malformed.",
+            Self::Unauthorized => "The request lacks valid credentials.",
+            Self::Forbidden => "The caller isn't allowed to perform this action.",
+            Self::NotFound => "The requested resource doesn't exist.",
+            Self::Conflict => "The request conflicts with the current state of the resource.",
+            Self::PreconditionFailed => "The resource has been modified since it was last read.",
+            Self::PayloadTooLarge => "The request body is too large.",
+            Self::UnsupportedMediaType => {
+                "The request body must be JSON, set the Content-Type header."
+            }
+            Self::UnprocessableEntity(_) => "The request contains invalid fields.",
+            Self::PreconditionRequired => {
+                "The request must be conditional, use the If-Match header."
+            }
+            Self::TooManyRequests { .. } => "Too many requests, try again later.",
+            Self::ServiceUnavailable => "The server is overloaded, try again later.",
+            Self::GatewayTimeout => "The server took too long to handle the request.",
+            Self::Database(_) => "The server encountered an unexpected error.",
+            Self::Internal(_) => "The server encountered an unexpected error.",
         }
     }
 }
 
 impl From<DbErr> for AppError {
     fn from(value: DbErr) -> Self {
-        AppError::Database(value)
+        if let DbErr::ConnectionAcquire(e) = &value {
+            tracing::warn!(error = %e, "no database connection available");
+            return AppError::ServiceUnavailable;
+        }
+        match value.sql_err() {
+            Some(
+                SqlErr::UniqueConstraintViolation(_) | SqlErr::ForeignKeyConstraintViolation(_),
+            ) => AppError::Conflict,
+            _ => AppError::Database(value),
+        }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let status = self.status_code();
+        let problem = Problem::new(self.status_code(), self.code(), self.detail());
         match self {
-            Self::UnprocessableEntity(errors) => (status, Json(errors)).into_response(),
+            Self::BadRequest(errors) | Self::UnprocessableEntity(errors) => {
+                problem.with_errors(errors).into_response()
+            }
             Self::Database(e) => {
-                eprintln!("database error: {:?}", e);
-
-                let body = GenericErrorBody::from_status(status);
-                (status, Json(body)).into_response()
+                tracing::error!(error = ?e, "database error");
+                problem.into_response()
             }
-            _ => {
-                let body = GenericErrorBody::from_status(status);
-                (status, Json(body)).into_response()
+            Self::Internal(e) => {
+                tracing::error!(error = ?e, "internal error");
+                problem.into_response()
             }
+            Self::Unauthorized => (
+                [(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))],
+                problem,
+            )
+                .into_response(),
+            Self::TooManyRequests { retry_after } => {
+                ([(header::RETRY_AFTER, retry_after.to_string())], problem).into_response()
+            }
+            _ => problem.into_response(),
         }
     }
 }
+
+/// A GraphQL error with the problem's detail as its message, and its code, status
+/// and field errors as extensions.
+impl From<AppError> for async_graphql::Error {
+    fn from(value: AppError) -> Self {
+        let status = value.status_code().as_u16();
+        let code = value.code();
+        let errors = match value {
+            AppError::BadRequest(ref errors) | AppError::UnprocessableEntity(ref errors) => {
+                errors.clone()
+            }
+            AppError::Database(ref e) => {
+                tracing::error!(error = ?e, "database error");
+                Vec::new()
+            }
+            AppError::Internal(ref e) => {
+                tracing::error!(error = ?e, "internal error");
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        async_graphql::Error::new(value.detail()).extend_with(|_, extensions| {
+            extensions.set("code", code);
+            extensions.set("status", status);
+            if !errors.is_empty() {
+                let errors = serde_json::to_value(&errors).expect("field errors serialize to JSON");
+                extensions.set(
+                    "errors",
+                    async_graphql::Value::from_json(errors).unwrap_or_default(),
+                );
+            }
+        })
+    }
+}
+
+/// Fills in the `instance` member of problem responses with the request path,
+/// and `request_id` with the request's ID.
+pub async fn problem_instance(req: Request, next: Next) -> Response {
+    let instance = req.uri().path().to_owned();
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_owned);
+    let mut res = next.run(req).await;
+    let Some(mut problem) = res.extensions_mut().remove::<Problem>() else {
+        return res;
+    };
+    problem.instance = Some(instance);
+    problem.request_id = request_id;
+
+    let (mut parts, _) = res.into_parts();
+    let body = Json(&problem).into_response().into_body();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.extensions.insert(problem);
+    Response::from_parts(parts, body)
+}
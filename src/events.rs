@@ -0,0 +1,255 @@
+//! Changes to records, streamed to clients as they're committed.
+//!
+//! Every change is logged to the `record_event` table in the transaction making
+//! it, so clients can resume from the last event they saw. Transactions logging
+//! events take turns from then until they commit, so events' IDs follow the
+//! order they're committed in and readers never see a later one before an
+//! earlier one. SQLite has a single writer anyway. With Postgres, each
+//! logged event is also announced with `NOTIFY`, which reaches the relay of every
+//! replica once the transaction commits. SQLite has no notifications, so the
+//! relay polls the table instead.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use entity::record_event;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr,
+    EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect,
+    sea_query::{Alias, Expr, Func, Query},
+    sqlx::{self, postgres::PgListener},
+};
+use tokio::{sync::broadcast, time};
+use tokio_util::sync::CancellationToken;
+
+/// The channel events are announced on, with their IDs as payloads.
+const CHANNEL: &str = "record_events";
+
+/// The advisory lock transactions logging events hold until they commit.
+///
+/// It's one lock for the whole database, so that events' IDs are in the order
+/// they commit and clients resuming from an ID can't miss an event committed
+/// after a later one. The price is that record writes are serialized from the
+/// point they log events until they commit, across every replica, which caps
+/// their throughput at what one transaction at a time can do. Keeping that
+/// stretch short is why events are logged after a transaction's other changes.
+const LOCK_KEY: i64 = 0x7265_636f_7264; // "record"
+
+/// How many events are inserted at once, to stay below the databases' limits on
+/// the number of parameters in a statement.
+const BATCH_SIZE: usize = 500;
+
+/// How many events subscribers may fall behind by before they're caught up from
+/// the table.
+const CAPACITY: usize = 1024;
+
+/// How many events are read from the table at once.
+const PAGE_SIZE: u64 = 100;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long events are kept for clients to resume from.
+const RETENTION: chrono::Duration = chrono::Duration::days(7);
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Logs record changes, announcing them to every replica once `db`, a
+/// transaction, commits.
+///
+/// Other transactions logging events wait for `db` to commit or roll back from
+/// here on, so it should have made its changes by now.
+pub(crate) async fn log<C>(db: &C, events: Vec<record_event::ActiveModel>) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    if events.is_empty() {
+        return Ok(());
+    }
+    let backend = db.get_database_backend();
+    if backend == DatabaseBackend::Postgres {
+        let lock = Query::select()
+            .expr(Func::cust(Alias::new("pg_advisory_xact_lock")).arg(LOCK_KEY))
+            .to_owned();
+        db.execute(backend.build(&lock)).await?;
+    }
+
+    for batch in events.chunks(BATCH_SIZE) {
+        let logged = record_event::Entity::insert_many(batch.to_vec())
+            .exec_with_returning_many(db)
+            .await?;
+        if backend == DatabaseBackend::Postgres {
+            notify(db, &logged).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Announces `events` on the channel once `db` commits.
+async fn notify<C>(db: &C, events: &[record_event::Model]) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let notify = Query::select()
+        .expr(
+            Func::cust(Alias::new("pg_notify"))
+                .arg(CHANNEL)
+                .arg(Expr::col(record_event::Column::Id).cast_as(Alias::new("text"))),
+        )
+        .from(record_event::Entity)
+        .and_where(record_event::Column::Id.is_in(events.iter().map(|event| event.id)))
+        .order_by(record_event::Column::Id, Order::Asc)
+        .to_owned();
+    db.execute(db.get_database_backend().build(&notify)).await?;
+    Ok(())
+}
+
+/// Fans committed events out to the streams of this replica.
+#[derive(Clone)]
+pub struct RecordEvents {
+    sender: broadcast::Sender<Arc<record_event::Model>>,
+    closed: CancellationToken,
+}
+
+impl Default for RecordEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecordEvents {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            closed: CancellationToken::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<record_event::Model>> {
+        self.sender.subscribe()
+    }
+
+    /// Cancelled once the relay stops, ending the streams.
+    pub fn closed(&self) -> CancellationToken {
+        self.closed.clone()
+    }
+
+    /// Relays committed events to subscribers, purging old ones, until `token` is
+    /// cancelled.
+    pub async fn relay(self, db: DatabaseConnection, token: CancellationToken) {
+        let relay = async {
+            match db.get_database_backend() {
+                DatabaseBackend::Postgres => self.listen(&db).await,
+                _ => self.poll(&db).await,
+            }
+        };
+        let purge = async {
+            let mut interval = time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let before = Utc::now().naive_utc() - RETENTION;
+                if let Err(e) = record_event::Entity::delete_many()
+                    .filter(record_event::Column::CreatedAt.lt(before))
+                    .exec(&db)
+                    .await
+                {
+                    tracing::warn!(error = %e, "failed to purge record events");
+                }
+            }
+        };
+        tokio::select! {
+            () = token.cancelled() => {}
+            () = relay => {}
+            () = purge => {}
+        }
+        self.closed.cancel();
+    }
+
+    async fn listen(&self, db: &DatabaseConnection) {
+        let listener = async {
+            let mut listener = PgListener::connect_with(db.get_postgres_connection_pool()).await?;
+            listener.listen(CHANNEL).await?;
+            Ok::<_, sqlx::Error>(listener)
+        };
+        let mut listener = match listener.await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to listen for record events");
+                return;
+            }
+        };
+
+        loop {
+            // The listener reconnects by itself, but events announced in the
+            // meantime only reach streams that resume from the table.
+            let id = match listener.recv().await {
+                Ok(notification) => notification.payload().parse::<i64>(),
+                Err(e) => {
+                    tracing::warn!(error = %e, "lost record event notifications");
+                    time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+            let Ok(id) = id else {
+                continue;
+            };
+            match record_event::Entity::find_by_id(id).one(db).await {
+                Ok(Some(event)) => {
+                    let _ = self.sender.send(Arc::new(event));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!(error = %e, "failed to load record event"),
+            }
+        }
+    }
+
+    async fn poll(&self, db: &DatabaseConnection) {
+        let mut last = None;
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(id) = last else {
+                // Only events committed from now on are new.
+                match latest(db).await {
+                    Ok(id) => last = Some(id),
+                    Err(e) => tracing::warn!(error = %e, "failed to poll record events"),
+                }
+                continue;
+            };
+            match since(db, id, Condition::all()).await {
+                Ok(events) => {
+                    for event in events {
+                        last = Some(event.id);
+                        let _ = self.sender.send(Arc::new(event));
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "failed to poll record events"),
+            }
+        }
+    }
+}
+
+/// The ID of the latest event, 0 if there are none.
+pub(crate) async fn latest(db: &DatabaseConnection) -> Result<i64, DbErr> {
+    let id = record_event::Entity::find()
+        .select_only()
+        .expr(record_event::Column::Id.max())
+        .into_tuple::<Option<i64>>()
+        .one(db)
+        .await?;
+    Ok(id.flatten().unwrap_or_default())
+}
+
+/// A page of the events after `id` matching `condition`, oldest first.
+pub(crate) async fn since(
+    db: &DatabaseConnection,
+    id: i64,
+    condition: Condition,
+) -> Result<Vec<record_event::Model>, DbErr> {
+    record_event::Entity::find()
+        .filter(record_event::Column::Id.gt(id))
+        .filter(condition)
+        .order_by_asc(record_event::Column::Id)
+        .limit(PAGE_SIZE)
+        .all(db)
+        .await
+}
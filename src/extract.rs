@@ -0,0 +1,182 @@
+//! Extractors that reject malformed input with [`AppError`] responses.
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request, rejection::PathRejection},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::{AppError, FieldError};
+
+/// JSON request body, and a JSON response.
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(req.headers()) {
+            return Err(AppError::UnsupportedMediaType);
+        }
+
+        let bytes = Bytes::from_request(req, state).await.map_err(|rejection| {
+            match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+                _ => AppError::bad_request([FieldError::new(
+                    "body",
+                    "unreadable",
+                    rejection.body_text(),
+                )]),
+            }
+        })?;
+
+        let mut de = serde_json::Deserializer::from_slice(&bytes);
+        let value = serde_path_to_error::deserialize(&mut de).map_err(|e| {
+            let path = e.path().to_string();
+            let e = e.into_inner();
+            let error = if e.is_data() {
+                deserialize_error("body", &path, e.to_string())
+            } else {
+                FieldError::new("body", "malformed_json", e.to_string())
+            };
+            AppError::bad_request([error])
+        })?;
+        de.end().map_err(|e| {
+            AppError::bad_request([FieldError::new("body", "malformed_json", e.to_string())])
+        })?;
+
+        Ok(Self(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Points a deserialization error at the offending field, `location` if there's none.
+fn deserialize_error(location: &'static str, path: &str, message: String) -> FieldError {
+    if let Some(name) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+    {
+        let field = match path {
+            "." => name.to_owned(),
+            path => format!("{path}.{name}"),
+        };
+        return FieldError::new(field, "missing", message);
+    }
+    match path {
+        "." => FieldError::new(location, "invalid_value", message),
+        path => FieldError::new(path.to_owned(), "invalid_value", message),
+    }
+}
+
+/// Path parameters.
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Self(value)),
+            Err(rejection) => Err(path_error(rejection)),
+        }
+    }
+}
+
+fn path_error(rejection: PathRejection) -> AppError {
+    use axum::extract::path::ErrorKind;
+
+    let PathRejection::FailedToDeserializePathParams(e) = rejection else {
+        return AppError::Internal(axum::Error::new(rejection.body_text()));
+    };
+    let error = match e.into_kind() {
+        ErrorKind::ParseErrorAtKey {
+            key,
+            value,
+            expected_type,
+        } => FieldError::new(
+            key,
+            "invalid_value",
+            format!("`{value}` is not a valid value"),
+        )
+        .param("expected_type", expected_type),
+        ErrorKind::DeserializeError {
+            key,
+            value,
+            message,
+        } => FieldError::new(key, "invalid_value", format!("`{value}`: {message}")),
+        ErrorKind::InvalidUtf8InPathParam { key } => {
+            FieldError::new(key, "invalid_utf8", "value isn't valid UTF-8")
+        }
+        ErrorKind::ParseError {
+            value,
+            expected_type,
+        }
+        | ErrorKind::ParseErrorAtIndex {
+            value,
+            expected_type,
+            ..
+        } => FieldError::new(
+            "path",
+            "invalid_value",
+            format!("`{value}` is not a valid value"),
+        )
+        .param("expected_type", expected_type),
+        kind => return AppError::Internal(axum::Error::new(kind.to_string())),
+    };
+    AppError::bad_request([error])
+}
+
+/// Query string parameters.
+pub struct Query<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+        let de = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        serde_path_to_error::deserialize(de).map(Self).map_err(|e| {
+            let path = e.path().to_string();
+            AppError::bad_request([deserialize_error(
+                "query",
+                &path,
+                e.into_inner().to_string(),
+            )])
+        })
+    }
+}
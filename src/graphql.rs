@@ -0,0 +1,505 @@
+//! A GraphQL API over users, categories and records, for clients that want
+//! related objects in one round trip.
+//!
+//! Resolvers share their logic and authorization with the REST handlers, and
+//! fail with the same problems, as errors whose `code` and `status` extensions
+//! match the problem's. Related objects are batched with [`loaders`], and queries
+//! are limited in depth and complexity so nesting can't grow unbounded.
+
+use async_graphql::{
+    ComplexObject, Context, EmptySubscription, Enum, InputObject, Object, Schema, SimpleObject,
+    dataloader::DataLoader,
+};
+use chrono::NaiveDateTime;
+use entity::{category, household_member, record, user};
+use rust_decimal::Decimal;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use uuid::Uuid;
+
+use crate::{
+    auth::Caller,
+    error::AppError,
+    precondition::IfMatch,
+    routers::{categories, records, users},
+};
+
+mod loaders;
+
+use loaders::{Categories, RecordsByUser, Users};
+
+/// How deeply selections may nest.
+const MAX_DEPTH: usize = 8;
+
+/// How many fields a query may select in total, counting nested ones.
+const MAX_COMPLEXITY: usize = 250;
+
+pub type AppSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// The schema, with introspection only if `introspection` is set.
+pub fn schema(db: DatabaseConnection, introspection: bool) -> AppSchema {
+    let schema = Schema::build(Query, Mutation, EmptySubscription)
+        .data(db)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY);
+    match introspection {
+        true => schema.finish(),
+        false => schema.disable_introspection().finish(),
+    }
+}
+
+/// Prepares `request` to run on behalf of `caller`, with loaders of its own so
+/// nothing is cached across callers.
+pub fn request(
+    request: async_graphql::Request,
+    db: &DatabaseConnection,
+    caller: Caller,
+) -> async_graphql::Request {
+    request
+        .data(caller)
+        .data(DataLoader::new(Users(db.clone()), tokio::spawn))
+        .data(DataLoader::new(Categories(db.clone()), tokio::spawn))
+        .data(DataLoader::new(
+            RecordsByUser {
+                db: db.clone(),
+                caller,
+            },
+            tokio::spawn,
+        ))
+}
+
+fn db<'c>(ctx: &Context<'c>) -> &'c DatabaseConnection {
+    ctx.data_unchecked()
+}
+
+fn caller(ctx: &Context<'_>) -> Caller {
+    *ctx.data_unchecked::<Caller>()
+}
+
+/// Turns a missing object into `null`, as GraphQL clients expect.
+fn found<T>(result: Result<T, AppError>) -> async_graphql::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(AppError::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What a user is allowed to do.
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "crate::auth::Role")]
+enum Role {
+    /// Manages users and categories, and sees every record.
+    Admin,
+    /// Manages their own account and records.
+    Member,
+}
+
+/// A user owning records.
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct User {
+    id: Uuid,
+    name: String,
+    role: Role,
+    /// Changes with every update, pass it to mutations to make them conditional.
+    version: i32,
+}
+
+impl From<user::Model> for User {
+    fn from(value: user::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            role: crate::auth::Role::from(value.role).into(),
+            version: value.version,
+        }
+    }
+}
+
+#[ComplexObject]
+impl User {
+    /// The user's records that the caller may see.
+    async fn records(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Record>> {
+        records_of(ctx, self.id).await
+    }
+}
+
+/// A user as seen by whoever shares records with them, without their role or
+/// version, which only they and admins may see.
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct UserSummary {
+    id: Uuid,
+    name: String,
+}
+
+impl From<user::Model> for UserSummary {
+    fn from(value: user::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+        }
+    }
+}
+
+#[ComplexObject]
+impl UserSummary {
+    /// The user's records that the caller may see.
+    async fn records(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Record>> {
+        records_of(ctx, self.id).await
+    }
+}
+
+/// The records of the user `id` that the caller may see.
+async fn records_of(ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Vec<Record>> {
+    let records = ctx
+        .data_unchecked::<DataLoader<RecordsByUser>>()
+        .load_one(id)
+        .await?
+        .unwrap_or_default();
+    Ok(records.into_iter().map(Into::into).collect())
+}
+
+/// A record category.
+#[derive(SimpleObject)]
+struct Category {
+    id: Uuid,
+    name: String,
+    /// The household the category belongs to, global categories have none.
+    household_id: Option<Uuid>,
+    /// Changes with every update, pass it to mutations to make them conditional.
+    version: i32,
+}
+
+impl From<category::Model> for Category {
+    fn from(value: category::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            household_id: value.household_id,
+            version: value.version,
+        }
+    }
+}
+
+/// An expense record.
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct Record {
+    id: Uuid,
+    user_id: Uuid,
+    category_id: Uuid,
+    created_at: NaiveDateTime,
+    sum: Decimal,
+    /// The household whose ledger the record is in, personal records have none.
+    household_id: Option<Uuid>,
+    /// Changes with every update, pass it to mutations to make them conditional.
+    version: i32,
+}
+
+impl From<record::Model> for Record {
+    fn from(value: record::Model) -> Self {
+        Self {
+            id: value.id,
+            user_id: value.user_id,
+            category_id: value.category_id,
+            created_at: value.created_at,
+            sum: value.sum,
+            household_id: value.household_id,
+            version: value.version,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Record {
+    /// The user the record belongs to.
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<UserSummary> {
+        ctx.data_unchecked::<DataLoader<Users>>()
+            .load_one(self.user_id)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| AppError::NotFound.into())
+    }
+
+    async fn category(&self, ctx: &Context<'_>) -> async_graphql::Result<Category> {
+        ctx.data_unchecked::<DataLoader<Categories>>()
+            .load_one(self.category_id)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| AppError::NotFound.into())
+    }
+}
+
+/// A created user along with the token it authenticates with, which is only ever shown once.
+#[derive(SimpleObject)]
+struct CreatedUser {
+    user: User,
+    token: String,
+}
+
+/// Which records to list, the same filters `GET /records` takes.
+#[derive(Default, InputObject)]
+struct RecordFilter {
+    user_id: Option<Uuid>,
+    category_id: Option<Uuid>,
+    /// Only records in the household's ledger, by any of its members.
+    household_id: Option<Uuid>,
+}
+
+#[derive(InputObject)]
+struct UserCreateInput {
+    name: String,
+    /// Only admins may create users other than members.
+    role: Option<Role>,
+}
+
+#[derive(InputObject)]
+struct UserUpdateInput {
+    name: String,
+    /// Only admins may change roles.
+    role: Option<Role>,
+}
+
+#[derive(InputObject)]
+struct CategoryCreateInput {
+    name: String,
+    /// Creates a category of the household rather than a global one.
+    household_id: Option<Uuid>,
+}
+
+#[derive(InputObject)]
+struct CategoryUpdateInput {
+    name: String,
+}
+
+#[derive(InputObject)]
+struct RecordCreateInput {
+    user_id: Uuid,
+    category_id: Uuid,
+    sum: Decimal,
+    /// Adds the record to the household's ledger, `userId` must be one of its members.
+    household_id: Option<Uuid>,
+}
+
+#[derive(InputObject)]
+struct RecordUpdateInput {
+    category_id: Uuid,
+    sum: Decimal,
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The caller.
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        user::Entity::find_by_id(caller(ctx).id)
+            .one(db(ctx))
+            .await
+            .map_err(AppError::from)?
+            .map(Into::into)
+            .ok_or_else(|| AppError::Unauthorized.into())
+    }
+
+    /// Members may only see themselves.
+    async fn user(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<User>> {
+        caller(ctx).require_user(id)?;
+        let user = user::Entity::find_by_id(id)
+            .one(db(ctx))
+            .await
+            .map_err(AppError::from)?;
+        Ok(user.map(Into::into))
+    }
+
+    /// Every user, only for admins.
+    async fn users(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<User>> {
+        caller(ctx).require_admin()?;
+        let users = user::Entity::find()
+            .all(db(ctx))
+            .await
+            .map_err(AppError::from)?;
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    async fn category(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<Option<Category>> {
+        let category =
+            categories::find(db(ctx), caller(ctx), id, household_member::Role::Viewer).await;
+        Ok(found(category)?.map(Into::into))
+    }
+
+    /// The household's categories if `householdId` is given, otherwise global
+    /// ones and those of the caller's households.
+    async fn categories(
+        &self,
+        ctx: &Context<'_>,
+        household_id: Option<Uuid>,
+    ) -> async_graphql::Result<Vec<Category>> {
+        let categories = categories::list(db(ctx), caller(ctx), household_id).await?;
+        Ok(categories.into_iter().map(Into::into).collect())
+    }
+
+    async fn record(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<Record>> {
+        let record = records::find(db(ctx), caller(ctx), id, household_member::Role::Viewer).await;
+        Ok(found(record)?.map(Into::into))
+    }
+
+    /// The records matching `filter` that the caller may see. Members see their
+    /// own records, and those of households they belong to when filtering by household.
+    async fn records(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: RecordFilter,
+    ) -> async_graphql::Result<Vec<Record>> {
+        let params = records::RecordFilterParams {
+            user_id: filter.user_id,
+            category_id: filter.category_id,
+            household_id: filter.household_id,
+        };
+        let records = params
+            .scope(db(ctx), caller(ctx))
+            .await?
+            .all(db(ctx))
+            .await
+            .map_err(AppError::from)?;
+        Ok(records.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Changes mirroring the REST API's. Mutations of existing objects apply only if
+/// `version` is still current, as the REST API requires `If-Match`.
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn create_user(
+        &self,
+        ctx: &Context<'_>,
+        input: UserCreateInput,
+    ) -> async_graphql::Result<CreatedUser> {
+        let new = users::UserCreate {
+            name: input.name,
+            role: input.role.map(Into::into),
+        };
+        let (user, token) = users::create(db(ctx), Some(caller(ctx)), new).await?;
+        Ok(CreatedUser {
+            user: user.into(),
+            token,
+        })
+    }
+
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        input: UserUpdateInput,
+        version: i32,
+    ) -> async_graphql::Result<User> {
+        let changes = users::UserUpdate {
+            name: input.name,
+            role: input.role.map(Into::into),
+        };
+        let if_match = IfMatch::version(version);
+        Ok(users::update(db(ctx), caller(ctx), id, &if_match, changes)
+            .await?
+            .into())
+    }
+
+    /// Deletes the user along with their records.
+    async fn delete_user(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+    ) -> async_graphql::Result<bool> {
+        users::delete(db(ctx), caller(ctx), id, &IfMatch::version(version)).await?;
+        Ok(true)
+    }
+
+    async fn create_category(
+        &self,
+        ctx: &Context<'_>,
+        input: CategoryCreateInput,
+    ) -> async_graphql::Result<Category> {
+        let new = categories::CategoryCreate {
+            name: input.name,
+            household_id: input.household_id,
+        };
+        Ok(categories::create(db(ctx), caller(ctx), new).await?.into())
+    }
+
+    async fn update_category(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        input: CategoryUpdateInput,
+        version: i32,
+    ) -> async_graphql::Result<Category> {
+        let changes = categories::CategoryUpdate { name: input.name };
+        let if_match = IfMatch::version(version);
+        Ok(
+            categories::update(db(ctx), caller(ctx), id, &if_match, changes)
+                .await?
+                .into(),
+        )
+    }
+
+    /// Deletes the category along with its records.
+    async fn delete_category(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+    ) -> async_graphql::Result<bool> {
+        categories::delete(db(ctx), caller(ctx), id, &IfMatch::version(version)).await?;
+        Ok(true)
+    }
+
+    async fn create_record(
+        &self,
+        ctx: &Context<'_>,
+        input: RecordCreateInput,
+    ) -> async_graphql::Result<Record> {
+        let new = records::RecordCreate {
+            user_id: input.user_id,
+            category_id: input.category_id,
+            sum: input.sum,
+            household_id: input.household_id,
+        };
+        Ok(records::create(db(ctx), caller(ctx), new).await?.into())
+    }
+
+    /// Changing a shared record's sum rescales its shares.
+    async fn update_record(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        input: RecordUpdateInput,
+        version: i32,
+    ) -> async_graphql::Result<Record> {
+        let changes = records::RecordUpdate {
+            category_id: input.category_id,
+            sum: input.sum,
+        };
+        let if_match = IfMatch::version(version);
+        Ok(
+            records::update(db(ctx), caller(ctx), id, &if_match, changes)
+                .await?
+                .into(),
+        )
+    }
+
+    async fn delete_record(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+    ) -> async_graphql::Result<bool> {
+        records::delete(db(ctx), caller(ctx), id, &IfMatch::version(version)).await?;
+        Ok(true)
+    }
+}
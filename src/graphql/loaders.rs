@@ -0,0 +1,82 @@
+//! Batches the lookups of related objects within a request into one query each.
+
+use std::collections::HashMap;
+
+use async_graphql::dataloader::Loader;
+use entity::{category, record, user};
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use crate::{auth::Caller, error::AppError};
+
+/// Users by ID.
+pub struct Users(pub DatabaseConnection);
+
+impl Loader<Uuid> for Users {
+    type Value = user::Model;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let users = user::Entity::find()
+            .filter(user::Column::Id.is_in(keys.iter().copied()))
+            .all(&self.0)
+            .await
+            .map_err(AppError::from)?;
+        Ok(users.into_iter().map(|user| (user.id, user)).collect())
+    }
+}
+
+/// Categories by ID.
+pub struct Categories(pub DatabaseConnection);
+
+impl Loader<Uuid> for Categories {
+    type Value = category::Model;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let categories = category::Entity::find()
+            .filter(category::Column::Id.is_in(keys.iter().copied()))
+            .all(&self.0)
+            .await
+            .map_err(AppError::from)?;
+        Ok(categories
+            .into_iter()
+            .map(|category| (category.id, category))
+            .collect())
+    }
+}
+
+/// The records of users by user ID, limited to those the caller may see: their
+/// own and those of their households, or every record for admins.
+pub struct RecordsByUser {
+    pub db: DatabaseConnection,
+    pub caller: Caller,
+}
+
+impl Loader<Uuid> for RecordsByUser {
+    type Value = Vec<record::Model>;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut query =
+            record::Entity::find().filter(record::Column::UserId.is_in(keys.iter().copied()));
+        if !self.caller.is_admin() {
+            let households: Vec<_> = self
+                .caller
+                .memberships(&self.db)
+                .await?
+                .households()
+                .collect();
+            query = query.filter(
+                Condition::any()
+                    .add(record::Column::UserId.eq(self.caller.id))
+                    .add(record::Column::HouseholdId.is_in(households)),
+            );
+        }
+        let mut records: HashMap<_, Vec<_>> = HashMap::new();
+        for record in query.all(&self.db).await.map_err(AppError::from)? {
+            records.entry(record.user_id).or_default().push(record);
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,307 @@
+use std::{error::Error, net::SocketAddr};
+
+use axum::{
+    body::{self, Body},
+    extract::{ConnectInfo, OptionalFromRequestParts, Request, State},
+    http::{HeaderName, HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{Duration, Utc};
+use entity::idempotency_key;
+use http_body_util::LengthLimitError;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DeleteMany, EntityTrait,
+    QueryFilter, sea_query::OnConflict,
+};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError},
+};
+
+pub static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+pub static IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+pub const DEFAULT_TTL_SECS: u32 = 24 * 60 * 60;
+const MAX_KEY_LEN: usize = 255;
+/// How long a reservation may wait for its request before it's taken to be
+/// abandoned, for requests whose reservation couldn't be released, e.g. because
+/// the server stopped.
+const PENDING_TIMEOUT: Duration = Duration::minutes(5);
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+/// Response headers replayed along with the body.
+static STORED_HEADERS: [HeaderName; 3] = [header::CONTENT_TYPE, header::ETAG, header::LOCATION];
+
+/// Makes `POST` requests carrying an `Idempotency-Key` header safe to retry.
+///
+/// Keys belong to the caller, or to the client's address for anonymous requests.
+/// A key is reserved before its request is handled, so a retry arriving while the
+/// first attempt still runs gets `409 Conflict` instead of repeating it. The
+/// response is then stored together with a hash of the request: retries of the
+/// same request get it back, while reusing the key for a different request is
+/// rejected. Server errors are not stored, and requests that time out or whose
+/// client goes away release their key, so such requests can be retried with the
+/// same key.
+pub(crate) async fn idempotency(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if req.method() != Method::POST {
+        return Ok(next.run(req).await);
+    }
+    let Some(key) = req.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(req).await);
+    };
+    let key = parse_key(key)?;
+
+    let (mut parts, body) = req.into_parts();
+    let scope = scope(&mut parts, &state).await?;
+    let body = body::to_bytes(body, state.max_body_size)
+        .await
+        .map_err(|e| match e.source() {
+            Some(source) if source.is::<LengthLimitError>() => AppError::PayloadTooLarge,
+            _ => AppError::Internal(e),
+        })?;
+    let request_hash = hash_request(&parts.method, &parts.uri.to_string(), &body);
+
+    let db = &state.db;
+    let entry = idempotency_key::ActiveModel {
+        scope: Set(scope.clone()),
+        key: Set(key.clone()),
+        request_hash: Set(request_hash.clone()),
+        ..Default::default()
+    };
+    if let Some(stored) = reserve(db, entry, state.idempotency_ttl).await? {
+        let Some(status) = stored.response_status else {
+            return Err(AppError::Conflict);
+        };
+        if stored.request_hash != request_hash {
+            return Err(AppError::unprocessable_entity([FieldError::new(
+                "Idempotency-Key",
+                "reused",
+                "idempotency key was used for a different request",
+            )]));
+        }
+        return Ok(replay(status, stored));
+    }
+
+    let reservation = Reservation {
+        db: db.clone(),
+        scope: scope.clone(),
+        key: key.clone(),
+        held: true,
+    };
+    let res = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if res.status().is_server_error() {
+        reservation.release().await?;
+        return Ok(res);
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            reservation.release().await?;
+            return Err(AppError::Internal(e));
+        }
+    };
+    let headers = STORED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = parts.headers.get(name)?.to_str().ok()?;
+            Some((name.as_str().to_owned(), Value::from(value)))
+        })
+        .collect::<Map<_, _>>();
+    idempotency_key::Entity::update_many()
+        .set(idempotency_key::ActiveModel {
+            response_status: Set(Some(parts.status.as_u16() as i16)),
+            response_headers: Set(Some(Value::Object(headers))),
+            response_body: Set(Some(body.to_vec())),
+            ..ActiveModelTrait::default()
+        })
+        .filter(idempotency_key::Column::Scope.eq(scope))
+        .filter(idempotency_key::Column::Key.eq(key))
+        .exec(db)
+        .await?;
+    reservation.keep();
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// A key reserved for a request, released if the request is dropped before its
+/// response is stored, e.g. when it times out, so retries aren't turned away
+/// until the reservation is taken to be abandoned.
+struct Reservation {
+    db: DatabaseConnection,
+    scope: String,
+    key: String,
+    held: bool,
+}
+
+impl Reservation {
+    fn delete(&self) -> DeleteMany<idempotency_key::Entity> {
+        idempotency_key::Entity::delete_many()
+            .filter(idempotency_key::Column::Scope.eq(&self.scope))
+            .filter(idempotency_key::Column::Key.eq(&self.key))
+    }
+
+    async fn release(mut self) -> Result<(), AppError> {
+        self.held = false;
+        self.delete().exec(&self.db).await?;
+        Ok(())
+    }
+
+    /// Keeps the key, now that the response is stored.
+    fn keep(mut self) {
+        self.held = false;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+        let (db, delete) = (self.db.clone(), self.delete());
+        tokio::spawn(async move {
+            if let Err(e) = delete.exec(&db).await {
+                tracing::warn!(error = %e, "failed to release idempotency key");
+            }
+        });
+    }
+}
+
+/// Whom the key belongs to: the caller, or the client's address for anonymous
+/// requests, so that one client can't replay another's response.
+async fn scope(parts: &mut Parts, state: &AppState) -> Result<String, AppError> {
+    let caller =
+        <Caller as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state).await?;
+    Ok(
+        match (caller, parts.extensions.get::<ConnectInfo<SocketAddr>>()) {
+            (Some(caller), _) => format!("user:{}", caller.id),
+            (None, Some(ConnectInfo(addr))) => format!("ip:{}", addr.ip()),
+            (None, None) => "anonymous".to_owned(),
+        },
+    )
+}
+
+/// Reserves the key for a request, or returns the entry that already holds it.
+///
+/// Expired entries, and reservations whose request must have been abandoned, are
+/// replaced instead.
+async fn reserve(
+    db: &DatabaseConnection,
+    entry: idempotency_key::ActiveModel,
+    ttl: Duration,
+) -> Result<Option<idempotency_key::Model>, AppError> {
+    let id = (entry.scope.clone().unwrap(), entry.key.clone().unwrap());
+    for _ in 0..2 {
+        let inserted = idempotency_key::Entity::insert(entry.clone())
+            .on_conflict(
+                OnConflict::columns([idempotency_key::Column::Scope, idempotency_key::Column::Key])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+        if inserted > 0 {
+            return Ok(None);
+        }
+
+        let Some(stored) = idempotency_key::Entity::find_by_id(id.clone())
+            .one(db)
+            .await?
+        else {
+            continue;
+        };
+        let now = Utc::now().naive_utc();
+        let abandoned =
+            stored.response_status.is_none() && stored.created_at < now - PENDING_TIMEOUT;
+        if stored.created_at >= now - ttl && !abandoned {
+            return Ok(Some(stored));
+        }
+        // Only if no other request replaced it in the meantime.
+        idempotency_key::Entity::delete_many()
+            .filter(idempotency_key::Column::Scope.eq(&id.0))
+            .filter(idempotency_key::Column::Key.eq(&id.1))
+            .filter(idempotency_key::Column::CreatedAt.eq(stored.created_at))
+            .exec(db)
+            .await?;
+    }
+
+    Err(AppError::Conflict)
+}
+
+fn parse_key(value: &HeaderValue) -> Result<String, AppError> {
+    let error = match value.to_str() {
+        Ok("") => FieldError::new("Idempotency-Key", "empty", "idempotency key is empty"),
+        Ok(key) if key.len() > MAX_KEY_LEN => {
+            FieldError::new("Idempotency-Key", "too_long", "idempotency key is too long")
+                .param("max_length", MAX_KEY_LEN)
+        }
+        Ok(key) => return Ok(key.to_owned()),
+        Err(_) => FieldError::new(
+            "Idempotency-Key",
+            "invalid_characters",
+            "idempotency key is not visible ASCII",
+        ),
+    };
+
+    Err(AppError::bad_request([error]))
+}
+
+fn hash_request(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b"\n");
+    hasher.update(uri);
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Deletes expired keys every hour until `token` is cancelled.
+pub async fn purge(db: DatabaseConnection, ttl: Duration, token: CancellationToken) {
+    let mut interval = time::interval(PURGE_INTERVAL);
+    loop {
+        tokio::select! {
+            () = token.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        let before = Utc::now().naive_utc() - ttl;
+        if let Err(e) = idempotency_key::Entity::delete_many()
+            .filter(idempotency_key::Column::CreatedAt.lt(before))
+            .exec(&db)
+            .await
+        {
+            tracing::warn!(error = %e, "failed to purge idempotency keys");
+        }
+    }
+}
+
+fn replay(status: i16, stored: idempotency_key::Model) -> Response {
+    let status = StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK);
+    let mut res = Response::new(Body::from(stored.response_body.unwrap_or_default()));
+    *res.status_mut() = status;
+    if let Some(Value::Object(headers)) = stored.response_headers {
+        for (name, value) in headers {
+            let (Ok(name), Some(Ok(value))) = (
+                HeaderName::try_from(name),
+                value.as_str().map(HeaderValue::try_from),
+            ) else {
+                continue;
+            };
+            res.headers_mut().insert(name, value);
+        }
+    }
+    res.headers_mut()
+        .insert(&IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    res
+}
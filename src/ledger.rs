@@ -0,0 +1,191 @@
+//! Splitting shared expenses and settling the debts they leave.
+
+use std::collections::BTreeMap;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use uuid::Uuid;
+
+/// `sum` owed by one user to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Debt {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub sum: Decimal,
+}
+
+/// Splits `total` in proportion to `weights`, which must be positive.
+///
+/// Shares are rounded down to cents, or to the precision of `total` if finer, and the
+/// remaining units go to the first shares so that they always add up to `total`.
+pub fn split(total: Decimal, weights: &[Decimal]) -> Vec<Decimal> {
+    let scale = total.scale().max(2);
+    let unit = Decimal::new(1, scale);
+    let weight: Decimal = weights.iter().sum();
+    let mut shares: Vec<_> = weights
+        .iter()
+        .map(|w| (total * w / weight).round_dp_with_strategy(scale, RoundingStrategy::ToZero))
+        .collect();
+    // Rounding down loses less than a unit per share, so one pass hands out the rest.
+    let mut remainder = total - shares.iter().sum::<Decimal>();
+    for share in shares.iter_mut() {
+        if remainder < unit {
+            break;
+        }
+        *share += unit;
+        remainder -= unit;
+    }
+    shares
+}
+
+/// Nets `debts` between each pair of users, leaving at most one debt per pair.
+pub fn pairwise(debts: impl IntoIterator<Item = Debt>) -> Vec<Debt> {
+    let mut pairs: BTreeMap<(Uuid, Uuid), Decimal> = BTreeMap::new();
+    for debt in debts.into_iter().filter(|debt| debt.from != debt.to) {
+        match debt.from < debt.to {
+            true => *pairs.entry((debt.from, debt.to)).or_default() += debt.sum,
+            false => *pairs.entry((debt.to, debt.from)).or_default() -= debt.sum,
+        }
+    }
+    pairs
+        .into_iter()
+        .filter(|(_, sum)| !sum.is_zero())
+        .map(|((a, b), sum)| match sum.is_sign_positive() {
+            true => Debt {
+                from: a,
+                to: b,
+                sum,
+            },
+            false => Debt {
+                from: b,
+                to: a,
+                sum: -sum,
+            },
+        })
+        .collect()
+}
+
+/// Transfers that settle all of `debts`, at most one fewer than the users involved.
+///
+/// Repeatedly pays the largest creditor from the largest debtor, so each transfer
+/// settles at least one of them.
+pub fn transfers(debts: &[Debt]) -> Vec<Debt> {
+    let mut balances: BTreeMap<Uuid, Decimal> = BTreeMap::new();
+    for debt in debts {
+        *balances.entry(debt.from).or_default() -= debt.sum;
+        *balances.entry(debt.to).or_default() += debt.sum;
+    }
+
+    let mut transfers = Vec::new();
+    loop {
+        let debtor = balances.iter().min_by_key(|(_, balance)| **balance);
+        // Ties go to the first user either way, keeping suggestions stable.
+        let creditor = balances.iter().rev().max_by_key(|(_, balance)| **balance);
+        let (Some((&from, &owed)), Some((&to, &due))) = (debtor, creditor) else {
+            break;
+        };
+        if !owed.is_sign_negative() || owed.is_zero() || due.is_zero() {
+            break;
+        }
+        let sum = due.min(-owed);
+        *balances.get_mut(&from).unwrap() += sum;
+        *balances.get_mut(&to).unwrap() -= sum;
+        transfers.push(Debt { from, to, sum });
+    }
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn user(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    #[test]
+    fn splits_remainder_across_first_shares() {
+        assert_eq!(
+            split(dec("100"), &[dec("1"), dec("1"), dec("1")]),
+            [dec("33.34"), dec("33.33"), dec("33.33")]
+        );
+        assert_eq!(
+            split(dec("10.005"), &[dec("50"), dec("50")]),
+            [dec("5.003"), dec("5.002")]
+        );
+        assert_eq!(split(dec("9"), &[dec("2"), dec("1")]), [dec("6"), dec("3")]);
+    }
+
+    #[test]
+    fn nets_debts_between_pairs() {
+        let debts = [
+            Debt {
+                from: user(1),
+                to: user(2),
+                sum: dec("10"),
+            },
+            Debt {
+                from: user(2),
+                to: user(1),
+                sum: dec("4"),
+            },
+            Debt {
+                from: user(2),
+                to: user(3),
+                sum: dec("5"),
+            },
+            Debt {
+                from: user(3),
+                to: user(2),
+                sum: dec("5"),
+            },
+        ];
+        assert_eq!(
+            pairwise(debts),
+            [Debt {
+                from: user(1),
+                to: user(2),
+                sum: dec("6"),
+            }]
+        );
+    }
+
+    #[test]
+    fn settles_chains_with_fewer_transfers() {
+        let debts = [
+            Debt {
+                from: user(1),
+                to: user(2),
+                sum: dec("10"),
+            },
+            Debt {
+                from: user(2),
+                to: user(3),
+                sum: dec("10"),
+            },
+            Debt {
+                from: user(3),
+                to: user(4),
+                sum: dec("5"),
+            },
+        ];
+        assert_eq!(
+            transfers(&debts),
+            [
+                Debt {
+                    from: user(1),
+                    to: user(3),
+                    sum: dec("5"),
+                },
+                Debt {
+                    from: user(1),
+                    to: user(4),
+                    sum: dec("5"),
+                },
+            ]
+        );
+    }
+}
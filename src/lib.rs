@@ -0,0 +1,242 @@
+//! The expense tracker API.
+
+pub mod auth;
+pub mod config;
+pub mod database;
+mod error;
+pub mod events;
+mod extract;
+mod graphql;
+pub mod idempotency;
+mod ledger;
+pub mod limits;
+pub mod metrics;
+pub mod migrate;
+mod openapi;
+pub mod outbox;
+mod precondition;
+mod routers;
+pub mod services;
+pub mod shutdown;
+pub mod telemetry;
+pub mod webhooks;
+
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    http::{HeaderName, HeaderValue, header},
+    middleware,
+};
+use chrono::Duration;
+use config::Config;
+use events::RecordEvents;
+use limits::{RateLimiter, RateLimits};
+use metrics_exporter_prometheus::PrometheusHandle;
+use sea_orm::DatabaseConnection;
+use services::{Check, Services};
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+
+#[derive(Clone)]
+struct AppState {
+    db: DatabaseConnection,
+    idempotency_ttl: Duration,
+    max_body_size: usize,
+    events: RecordEvents,
+    services: Services,
+    graphql: graphql::AppSchema,
+    webhooks_private_networks: bool,
+}
+
+/// Builds the app's router around a database connection.
+pub struct App {
+    db: DatabaseConnection,
+    idempotency_ttl: Duration,
+    docs: bool,
+    metrics: Option<PrometheusHandle>,
+    allowed_origins: Vec<HeaderValue>,
+    rate_limits: RateLimits,
+    max_body_size: usize,
+    request_timeout: std::time::Duration,
+    compression: bool,
+    events: RecordEvents,
+    webhooks_private_networks: bool,
+    services: Services,
+}
+
+impl App {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            services: Services::with_db(db.clone()),
+            db,
+            idempotency_ttl: Duration::seconds(idempotency::DEFAULT_TTL_SECS.into()),
+            docs: true,
+            metrics: None,
+            allowed_origins: Vec::new(),
+            rate_limits: RateLimits::default(),
+            max_body_size: limits::DEFAULT_MAX_BODY_SIZE,
+            request_timeout: std::time::Duration::from_secs(limits::DEFAULT_REQUEST_TIMEOUT_SECS),
+            compression: true,
+            events: RecordEvents::new(),
+            webhooks_private_networks: false,
+        }
+    }
+
+    pub fn from_config(db: DatabaseConnection, config: &Config) -> Self {
+        Self::new(db)
+            .idempotency_ttl(config.idempotency_key_ttl)
+            .docs(config.features.docs)
+            .allowed_origins(config.cors.allowed_origins.clone())
+            .rate_limits(config.rate_limit.clone())
+            .max_body_size(config.server.max_body_size)
+            .request_timeout(config.server.request_timeout)
+            .compression(config.features.compression)
+            .webhooks_private_networks(config.webhooks_private_networks)
+    }
+
+    /// How long responses to requests with an `Idempotency-Key` are kept.
+    pub fn idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    /// Whether to serve the OpenAPI document, Swagger UI, GraphiQL and the GraphQL
+    /// schema's introspection.
+    pub fn docs(mut self, enabled: bool) -> Self {
+        self.docs = enabled;
+        self
+    }
+
+    /// Serves metrics at `/metrics` along with the API.
+    pub fn metrics(mut self, handle: PrometheusHandle) -> Self {
+        self.metrics = Some(handle);
+        self
+    }
+
+    /// Origins allowed to make cross-origin requests.
+    pub fn allowed_origins(mut self, origins: Vec<HeaderValue>) -> Self {
+        self.allowed_origins = origins;
+        self
+    }
+
+    /// Quotas on how many requests each client may make, none by default.
+    pub fn rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// The largest request body accepted, in bytes.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = bytes;
+        self
+    }
+
+    /// How long a request may take before it fails with `504 Gateway Timeout`.
+    pub fn request_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Whether to compress responses for clients that accept it.
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Where the record event streams get their events from, which only carry
+    /// events resumed from the table unless its [`relay`](RecordEvents::relay) runs.
+    pub fn events(mut self, events: RecordEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Whether webhooks may be pointed at loopback, private and link-local
+    /// addresses, which the [`Deliveries`](webhooks::Deliveries) subscriber must
+    /// allow too.
+    pub fn webhooks_private_networks(mut self, allowed: bool) -> Self {
+        self.webhooks_private_networks = allowed;
+        self
+    }
+
+    /// Checks another service the app depends on, e.g. a cache, along with the
+    /// database, reporting it in `GET /health` and in the probes it's
+    /// [`critical`](Check::critical) or a [`startup`](Check::startup) check for.
+    pub fn health_check(mut self, check: impl Check + 'static) -> Self {
+        self.services = self.services.register(check);
+        self
+    }
+
+    pub fn build(self) -> Router {
+        let state = AppState {
+            services: self.services,
+            db: self.db.clone(),
+            idempotency_ttl: self.idempotency_ttl,
+            max_body_size: self.max_body_size,
+            events: self.events,
+            graphql: graphql::schema(self.db.clone(), self.docs),
+            webhooks_private_networks: self.webhooks_private_networks,
+        };
+        let mut router = routers::router(self.docs)
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                idempotency::idempotency,
+            ))
+            .layer(DefaultBodyLimit::max(self.max_body_size))
+            .layer(middleware::from_fn_with_state(
+                self.request_timeout,
+                limits::timeout,
+            ))
+            .layer(middleware::from_fn_with_state(
+                (RateLimiter::new(self.rate_limits), state.clone()),
+                limits::rate_limit,
+            ))
+            .layer(middleware::from_fn(metrics::track))
+            .with_state(state);
+
+        if let Some(handle) = self.metrics {
+            router = router.merge(metrics::router(self.db, handle));
+        }
+
+        router = router.layer(middleware::from_fn(error::problem_instance));
+        if self.compression {
+            router = router.layer(CompressionLayer::new());
+        }
+
+        router.layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::make_span)
+                        .on_response(telemetry::on_response),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(cors(self.allowed_origins)),
+        )
+    }
+}
+
+/// Allows cross-origin requests from `origins`, letting them read the headers
+/// clients need besides the safelisted ones.
+fn cors(origins: Vec<HeaderValue>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers([
+            header::ETAG,
+            header::LOCATION,
+            header::RETRY_AFTER,
+            HeaderName::from_static("x-request-id"),
+            idempotency::IDEMPOTENT_REPLAYED.clone(),
+            limits::RATE_LIMIT_LIMIT.clone(),
+            limits::RATE_LIMIT_REMAINING.clone(),
+            limits::RATE_LIMIT_RESET.clone(),
+            limits::RATE_LIMIT_POLICY.clone(),
+        ])
+}
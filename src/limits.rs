@@ -0,0 +1,282 @@
+//! Limits on requests: their rate, body size and how long they may take.
+//!
+//! Rate limiting uses token buckets. Every client gets a bucket per route group,
+//! the first segment of the path (e.g. `records`), holding up to a quota's number
+//! of requests and refilling evenly over its period. Clients are told by the user
+//! they authenticate as, so a user's tokens and API keys share their buckets, or
+//! by their IP address when they don't send valid credentials. Each address also
+//! gets a larger bucket, checked before authenticating, so that floods of
+//! made-up tokens are throttled before they reach the database.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, OptionalFromRequestParts, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use lru::LruCache;
+use serde::Deserialize;
+
+use crate::{AppState, auth::Caller, error::AppError};
+
+/// The default limit on the size of request bodies, in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// The default time limit on handling a request, in seconds.
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Route groups that are never limited, so probes keep working.
+const EXEMPT: &[&str] = &["health"];
+
+/// How many times a group's quota an address may use, across all the users it
+/// authenticates as.
+const ADDRESS_QUOTA_FACTOR: u32 = 10;
+
+/// Buckets kept before the least recently used ones are dropped.
+const MAX_BUCKETS: NonZeroUsize = NonZeroUsize::new(10_000).unwrap();
+
+pub(crate) static RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub(crate) static RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub(crate) static RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+pub(crate) static RATE_LIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// How many requests a client may make in a period.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Quota {
+    #[default]
+    Unlimited,
+    Limited {
+        requests: u32,
+        period: Duration,
+    },
+}
+
+impl Quota {
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self::Limited { requests, period }
+    }
+}
+
+/// `off`, or requests per period in seconds, e.g. `60/60`.
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "off" {
+            return Ok(Self::Unlimited);
+        }
+        let error = || "expected `off` or requests per seconds, e.g. `60/60`".to_owned();
+        let (requests, secs) = s.split_once('/').ok_or_else(error)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| error())?;
+        let secs: u64 = secs.trim().parse().map_err(|_| error())?;
+        if requests == 0 || secs == 0 {
+            return Err("requests and seconds must be at least 1".to_owned());
+        }
+        Ok(Self::new(requests, Duration::from_secs(secs)))
+    }
+}
+
+impl TryFrom<String> for Quota {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// The quotas of every route group.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits {
+    default: Quota,
+    groups: HashMap<String, Quota>,
+}
+
+impl RateLimits {
+    /// Limits every route group to `default`.
+    pub fn new(default: Quota) -> Self {
+        Self {
+            default,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Limits the route group `name`, e.g. `records`, to `quota` instead.
+    pub fn group(mut self, name: impl Into<String>, quota: Quota) -> Self {
+        self.groups.insert(name.into(), quota);
+        self
+    }
+
+    fn quota(&self, group: &str) -> Quota {
+        if EXEMPT.contains(&group) {
+            return Quota::Unlimited;
+        }
+        self.groups.get(group).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: Arc<RateLimits>,
+    buckets: Arc<Mutex<LruCache<(String, String), Bucket>>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The state of a client's bucket after taking a request from it.
+struct Decision {
+    requests: u32,
+    period: Duration,
+    allowed: bool,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next request is allowed.
+    retry_after: u64,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits: Arc::new(limits),
+            buckets: Arc::new(Mutex::new(LruCache::new(MAX_BUCKETS))),
+        }
+    }
+
+    /// Takes a request from `client`'s bucket for `group`, which holds `factor`
+    /// times the group's quota.
+    fn check(&self, group: &str, client: String, factor: u32) -> Option<Decision> {
+        let Quota::Limited { requests, period } = self.limits.quota(group) else {
+            return None;
+        };
+        let requests = requests.saturating_mul(factor);
+        let capacity = f64::from(requests);
+        let rate = capacity / period.as_secs_f64();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert_mut((group.to_owned(), client), || Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        Some(Decision {
+            requests,
+            period,
+            allowed,
+            remaining: bucket.tokens as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / rate).ceil() as u64,
+        })
+    }
+}
+
+/// Rejects requests over their client's quota with `429 Too Many Requests`,
+/// telling clients where they stand with the `RateLimit-*` headers.
+pub(crate) async fn rate_limit(
+    State((limiter, state)): State<(RateLimiter, AppState)>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let group = req.uri().path().split('/').nth(1).unwrap_or_default();
+    if limiter.limits.quota(group) == Quota::Unlimited {
+        return next.run(req).await;
+    }
+    let group = group.to_owned();
+    let (mut parts, body) = req.into_parts();
+    let address = address(&parts);
+    let mut decision = limiter.check(&group, format!("address:{address}"), ADDRESS_QUOTA_FACTOR);
+    if decision.as_ref().is_none_or(|decision| decision.allowed) {
+        let client = client(&mut parts, &state, address).await;
+        decision = limiter.check(&group, client, 1);
+    }
+    let req = Request::from_parts(parts, body);
+    let Some(decision) = decision else {
+        return next.run(req).await;
+    };
+
+    let mut res = if decision.allowed {
+        next.run(req).await
+    } else {
+        AppError::TooManyRequests {
+            retry_after: decision.retry_after,
+        }
+        .into_response()
+    };
+    set_headers(res.headers_mut(), &decision);
+    res
+}
+
+/// The client's IP address, if known.
+fn address(parts: &Parts) -> String {
+    match parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => addr.ip().to_string(),
+        None => "unknown".to_owned(),
+    }
+}
+
+/// Identifies the client by the user it authenticates as, or by its `address`
+/// without valid credentials, so that made-up tokens don't get buckets of their
+/// own. The caller is kept for the handler.
+async fn client(parts: &mut Parts, state: &AppState, address: String) -> String {
+    let caller =
+        <Caller as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state).await;
+    match caller {
+        Ok(Some(caller)) => format!("user:{}", caller.id),
+        _ => format!("ip:{address}"),
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision) {
+    let policy = format!("{};w={}", decision.requests, decision.period.as_secs());
+    for (name, value) in [
+        (&RATE_LIMIT_LIMIT, decision.requests.to_string()),
+        (&RATE_LIMIT_REMAINING, decision.remaining.to_string()),
+        (&RATE_LIMIT_RESET, decision.reset.to_string()),
+        (&RATE_LIMIT_POLICY, policy),
+    ] {
+        headers.insert(name.clone(), HeaderValue::try_from(value).unwrap());
+    }
+}
+
+/// Fails requests that take longer than `timeout` to handle with
+/// `504 Gateway Timeout`.
+pub async fn timeout(State(timeout): State<Duration>, req: Request, next: Next) -> Response {
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(res) => res,
+        Err(_) => AppError::GatewayTimeout.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quotas() {
+        assert_eq!("off".parse(), Ok(Quota::Unlimited));
+        assert_eq!("60/30".parse(), Ok(Quota::new(60, Duration::from_secs(30))));
+        assert!("60".parse::<Quota>().is_err());
+        assert!("0/60".parse::<Quota>().is_err());
+        assert!("60/0".parse::<Quota>().is_err());
+    }
+}
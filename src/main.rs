@@ -1,42 +1,153 @@
-mod database;
-mod error;
-mod routers;
+mod cli;
 
+use backend_lab_3::{
+    App,
+    auth::{self, Role},
+    config::Config,
+    database,
+    events::RecordEvents,
+    idempotency, metrics, migrate,
+    outbox::{self, Dispatcher},
+    shutdown::Shutdown,
+    telemetry, webhooks,
+};
+use clap::Parser;
+use cli::{Cli, Command, MigrateCommand, UsersCommand};
 use dotenvy::dotenv;
-use migration::{Migrator, MigratorTrait};
 use sea_orm::DatabaseConnection;
-use std::env;
-
-#[derive(Clone)]
-struct AppState {
-    db: DatabaseConnection,
-}
+use std::{net::SocketAddr, process};
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
     dotenv().ok();
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let _telemetry = telemetry::init(&config.log)
+        .unwrap_or_else(|e| panic!("failed to initialize telemetry: {e}"));
 
-    eprintln!("connecting to database");
-    let db = database::connect()
+    tracing::info!("connecting to database");
+    let db = database::connect(&config.database)
         .await
         .unwrap_or_else(|e| panic!("failed to connect to database: {e}"));
-    eprintln!("database connection established");
+    tracing::info!("database connection established");
 
-    eprintln!("applying migrations");
-    Migrator::up(&db, None)
-        .await
-        .unwrap_or_else(|e| panic!("failed to apply migrations: {e}"));
-    eprintln!("migrations applied");
+    match cli.command.unwrap_or_default() {
+        Command::Serve { no_migrate } => serve(config, db.clone(), !no_migrate).await,
+        Command::Migrate(command) => migrate_schema(&db, command).await,
+        Command::Users(command) => manage_users(&db, command).await,
+    }
+
+    tracing::info!("closing database connection");
+    if let Err(e) = db.close().await {
+        tracing::warn!(error = %e, "failed to close database connection");
+    }
+}
+
+async fn serve(config: Config, db: DatabaseConnection, apply_migrations: bool) {
+    let shutdown = Shutdown::listen(config.server.shutdown_timeout);
 
-    let state = AppState { db: db };
-    let router = routers::router().with_state(state);
+    if apply_migrations {
+        tracing::info!("applying migrations");
+        migrate::up(&db, None)
+            .await
+            .unwrap_or_else(|e| panic!("failed to apply migrations: {e}"));
+        tracing::info!("migrations applied");
+    } else {
+        match migrate::pending(&db).await {
+            Ok(0) => tracing::info!("no migrations are pending"),
+            Ok(pending) => tracing::warn!(pending, "migrations are pending, run `migrate up`"),
+            Err(e) => tracing::warn!(error = %e, "failed to check for pending migrations"),
+        }
+    }
 
-    let host = env::var("HOST").expect("HOST must be set");
-    let port = env::var("PORT").expect("PORT must be set");
-    let addr = format!("{}:{}", host, port);
+    let events = RecordEvents::new();
+    shutdown.spawn(events.clone().relay(db.clone(), shutdown.token()));
+    shutdown.spawn(idempotency::purge(
+        db.clone(),
+        config.idempotency_key_ttl,
+        shutdown.token(),
+    ));
+    let deliveries =
+        webhooks::Deliveries::new(db.clone()).private_networks(config.webhooks_private_networks);
+    let dispatcher = Dispatcher::new(db.clone())
+        .subscribe(outbox::Audit)
+        .subscribe(deliveries);
+    shutdown.spawn(dispatcher.run(shutdown.token()));
+    let mut app = App::from_config(db.clone(), &config).events(events);
+    if config.features.metrics {
+        let handle =
+            metrics::install().unwrap_or_else(|e| panic!("failed to install metrics: {e}"));
+        match config.server.metrics_addr() {
+            Some(metrics_addr) => {
+                let metrics_router = metrics::router(db, handle);
+                let listener = tokio::net::TcpListener::bind(&metrics_addr).await.unwrap();
+                tracing::info!("serving metrics on http://{}/metrics", &metrics_addr);
+                let token = shutdown.token();
+                shutdown.spawn(async move {
+                    let server = axum::serve(listener, metrics_router)
+                        .with_graceful_shutdown(token.cancelled_owned());
+                    if let Err(e) = server.await {
+                        tracing::error!(error = %e, "metrics server failed");
+                    }
+                });
+            }
+            None => app = app.metrics(handle),
+        }
+    }
+    let router = app.build();
 
-    eprintln!("starting server");
+    let addr = config.server.addr();
+    tracing::info!("starting server");
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    eprintln!("listening on http://{}", &addr);
-    axum::serve(listener, router).await.unwrap();
+    tracing::info!("listening on http://{}", &addr);
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.token().cancelled_owned());
+    shutdown
+        .serve(server)
+        .await
+        .unwrap_or_else(|e| panic!("server failed: {e}"));
+}
+
+async fn migrate_schema(db: &DatabaseConnection, command: MigrateCommand) {
+    match command {
+        MigrateCommand::Up { steps } => {
+            migrate::up(db, steps)
+                .await
+                .unwrap_or_else(|e| panic!("failed to apply migrations: {e}"));
+            tracing::info!("migrations applied");
+        }
+        MigrateCommand::Down { steps } => {
+            migrate::down(db, steps)
+                .await
+                .unwrap_or_else(|e| panic!("failed to revert migrations: {e}"));
+            tracing::info!("migrations reverted");
+        }
+        MigrateCommand::Status => {
+            let migrations = migrate::status(db)
+                .await
+                .unwrap_or_else(|e| panic!("failed to read migration status: {e}"));
+            for (name, status) in migrations {
+                println!("{status:<8} {name}");
+            }
+        }
+    }
+}
+
+async fn manage_users(db: &DatabaseConnection, command: UsersCommand) {
+    match command {
+        UsersCommand::Create { name, admin } => {
+            let role = if admin { Role::Admin } else { Role::Member };
+            let (user, token) = auth::create_user(db, name, role)
+                .await
+                .unwrap_or_else(|e| panic!("failed to create user: {e}"));
+            println!("id:    {}", user.id);
+            println!("token: {token}");
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use std::time::Instant;
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, metric::Info};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Histogram buckets for durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Installs the global metrics recorder.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_owned()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    db: DatabaseConnection,
+    handle: PrometheusHandle,
+}
+
+/// Serves the metrics in the Prometheus text format at `/metrics`.
+pub fn router(db: DatabaseConnection, handle: PrometheusHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(MetricsState { db, handle })
+}
+
+async fn render(State(MetricsState { db, handle }): State<MetricsState>) -> Response {
+    record_pool(&db);
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], handle.render()).into_response()
+}
+
+/// Counts requests and measures their latency, per route and status.
+pub async fn track(req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_owned();
+
+    let res = next.run(req).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", res.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(start.elapsed());
+    res
+}
+
+/// Measures a database query, labelled by its statement kind, e.g. `SELECT`.
+pub fn record_query(info: &Info<'_>) {
+    let operation = info
+        .statement
+        .sql
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let outcome = if info.failed { "error" } else { "ok" };
+    metrics::histogram!(
+        "db_query_duration_seconds",
+        "operation" => operation,
+        "outcome" => outcome,
+    )
+    .record(info.elapsed);
+}
+
+fn record_pool(db: &DatabaseConnection) {
+    let (size, idle, max) = match db.get_database_backend() {
+        DatabaseBackend::Postgres => {
+            let pool = db.get_postgres_connection_pool();
+            (
+                pool.size(),
+                pool.num_idle(),
+                pool.options().get_max_connections(),
+            )
+        }
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite => {
+            let pool = db.get_sqlite_connection_pool();
+            (
+                pool.size(),
+                pool.num_idle(),
+                pool.options().get_max_connections(),
+            )
+        }
+        _ => return,
+    };
+    let idle = u32::try_from(idle).unwrap_or(u32::MAX);
+    metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+    metrics::gauge!("db_pool_connections", "state" => "active").set(size.saturating_sub(idle));
+    metrics::gauge!("db_pool_max_connections").set(max);
+}
+
+/// Counts created records, however they were created.
+pub fn count_created_records(count: usize) {
+    metrics::counter!("records_created_total").increment(count as u64);
+}
@@ -0,0 +1,87 @@
+//! Schema migrations, applied by one instance at a time.
+
+use std::collections::HashSet;
+
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DatabaseTransaction, DbErr, Statement,
+    TransactionTrait,
+};
+
+/// Identifies the advisory lock held while migrating, arbitrary but fixed.
+const LOCK_KEY: i64 = 0x6578_7065_6e73_6573;
+
+/// Applies `steps` pending migrations, or all of them.
+pub async fn up(db: &DatabaseConnection, steps: Option<u32>) -> Result<(), DbErr> {
+    let txn = lock(db).await?;
+    Migrator::up(&txn, steps).await?;
+    txn.commit().await
+}
+
+/// Reverts the last `steps` applied migrations.
+pub async fn down(db: &DatabaseConnection, steps: u32) -> Result<(), DbErr> {
+    let txn = lock(db).await?;
+    Migrator::down(&txn, Some(steps)).await?;
+    txn.commit().await
+}
+
+/// The name of every migration, and whether it has been applied.
+pub async fn status(db: &DatabaseConnection) -> Result<Vec<(String, String)>, DbErr> {
+    let migrations = Migrator::get_migration_with_status(db).await?;
+    Ok(migrations
+        .iter()
+        .map(|migration| (migration.name().to_owned(), migration.status().to_string()))
+        .collect())
+}
+
+/// How far the database's schema is from the migrations this release knows of.
+pub struct Progress {
+    /// Known migrations that have been applied.
+    pub applied: usize,
+    /// Known migrations yet to be applied.
+    pub pending: usize,
+    /// Applied migrations this release doesn't know of, e.g. ones applied by a
+    /// newer release rolling out alongside it.
+    pub unknown: usize,
+}
+
+pub async fn progress(db: &DatabaseConnection) -> Result<Progress, DbErr> {
+    let applied: HashSet<String> = Migrator::get_migration_models(db)
+        .await?
+        .into_iter()
+        .map(|model| model.version)
+        .collect();
+    let known: HashSet<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_owned())
+        .collect();
+    let applied_known = known.intersection(&applied).count();
+    Ok(Progress {
+        applied: applied_known,
+        pending: known.len() - applied_known,
+        unknown: applied.len() - applied_known,
+    })
+}
+
+pub async fn pending(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    Ok(progress(db).await?.pending)
+}
+
+/// Starts a transaction holding the migration lock, so that instances starting
+/// together don't race to apply the same migrations.
+///
+/// The lock is released when the transaction ends. Postgres runs migrations in
+/// a transaction anyway, other backends don't need the lock.
+async fn lock(db: &DatabaseConnection) -> Result<DatabaseTransaction, DbErr> {
+    let txn = db.begin().await?;
+    if txn.get_database_backend() == DatabaseBackend::Postgres {
+        tracing::info!("acquiring migration lock");
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "SELECT pg_advisory_xact_lock($1)",
+            [LOCK_KEY.into()],
+        ))
+        .await?;
+    }
+    Ok(txn)
+}
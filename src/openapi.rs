@@ -0,0 +1,156 @@
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+use crate::{
+    auth::Role,
+    error::{FieldError, Problem},
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Expense tracker",
+        description = "Track expenses by user and category."
+    ),
+    tags(
+        (name = "health", description = "Service health"),
+        (name = "users", description = "Users owning records"),
+        (name = "api-keys", description = "Keys for services acting on a user's behalf"),
+        (name = "households", description = "Shared ledgers and their members"),
+        (name = "categories", description = "Record categories"),
+        (name = "records", description = "Expense records"),
+        (name = "settlements", description = "Debts from shared records and their settlement"),
+        (name = "webhooks", description = "Record events posted to other services"),
+        (name = "graphql", description = "Users, categories and records with their relations"),
+    ),
+    components(schemas(Problem, FieldError, Role), responses(Problem)),
+    modifiers(&BearerAuth),
+    security(("bearer" = []))
+)]
+pub struct ApiDoc;
+
+/// Documents the `Authorization: Bearer <token>` scheme operations require by default.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some(
+                            "A user's token, or an API key with the scopes the operation needs.",
+                        ))
+                        .build(),
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode, header},
+    };
+    use chrono::Duration;
+    use sea_orm::{ConnectOptions, Database};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::{AppState, events::RecordEvents, graphql, limits, routers, services::Services};
+
+    /// The app backed by a database that's never reachable, so handlers fail fast.
+    async fn app() -> Router {
+        let mut options = ConnectOptions::new("postgres://nobody@127.0.0.1:1/nothing");
+        options
+            .connect_lazy(true)
+            .acquire_timeout(std::time::Duration::from_millis(100));
+        let db = Database::connect(options).await.unwrap();
+        routers::router(true).with_state(AppState {
+            services: Services::with_db(db.clone()),
+            graphql: graphql::schema(db.clone(), true),
+            db,
+            idempotency_ttl: Duration::zero(),
+            max_body_size: limits::DEFAULT_MAX_BODY_SIZE,
+            events: RecordEvents::new(),
+            webhooks_private_networks: false,
+        })
+    }
+
+    fn request(method: Method, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap()
+    }
+
+    /// Documented operations must reach a handler rather than the fallback or a 405.
+    #[tokio::test]
+    async fn documented_operations_are_routed() {
+        let spec = routers::api().into_openapi();
+        assert!(!spec.paths.paths.is_empty());
+
+        for (path, item) in spec.paths.paths {
+            let uri = path
+                .split('/')
+                .map(|segment| match segment.starts_with('{') {
+                    true => "00000000-0000-0000-0000-000000000000",
+                    false => segment,
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let operations = [
+                (Method::GET, item.get),
+                (Method::PUT, item.put),
+                (Method::POST, item.post),
+                (Method::DELETE, item.delete),
+                (Method::OPTIONS, item.options),
+                (Method::HEAD, item.head),
+                (Method::PATCH, item.patch),
+                (Method::TRACE, item.trace),
+            ];
+            for (method, _) in operations.into_iter().filter(|(_, op)| op.is_some()) {
+                let res = app()
+                    .await
+                    .oneshot(request(method.clone(), &uri))
+                    .await
+                    .unwrap();
+                assert!(
+                    !matches!(
+                        res.status(),
+                        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                    ),
+                    "{method} {path} is documented but not routed"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_the_router_spec() {
+        let res = app()
+            .await
+            .oneshot(request(Method::GET, "/openapi.json"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let served: Value = serde_json::from_slice(&body).unwrap();
+        let expected = serde_json::to_value(routers::api().into_openapi()).unwrap();
+        assert_eq!(served, expected);
+    }
+}
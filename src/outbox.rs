@@ -0,0 +1,374 @@
+//! Domain events, handed to in-process subscribers once their change commits.
+//!
+//! Handlers [`publish`] events to the `outbox` table in the transaction making
+//! the change, so an event exists if and only if its change commits. The
+//! [`Dispatcher`] then hands each event to every [`Subscriber`], retrying the
+//! ones that fail with exponential backoff until they succeed. Delivery is at
+//! least once: a subscriber may see an event again if the dispatcher stops
+//! midway, so subscribers should be idempotent, e.g. by the event's ID.
+//!
+//! Webhooks are delivered by one of these subscribers,
+//! [`Deliveries`](crate::webhooks::Deliveries).
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use entity::{category, outbox, record, user};
+use futures_util::future;
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, TransactionTrait,
+    sea_query::{Expr, LockBehavior, LockType},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// How many due events are dispatched at once.
+const BATCH_SIZE: u64 = 50;
+
+/// How long a claimed event is held before other dispatchers may take it, in
+/// case its dispatcher dies midway.
+const LEASE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How long dispatched events are kept.
+const RETENTION: chrono::Duration = chrono::Duration::days(7);
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The longest wait between dispatches of an event.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Subscribers' errors are kept up to this many characters.
+const MAX_ERROR_LEN: usize = 500;
+
+/// Something that happened to the app's data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum DomainEvent {
+    #[serde(rename = "user.created")]
+    UserCreated {
+        user_id: Uuid,
+        name: String,
+        role: user::Role,
+    },
+    #[serde(rename = "user.deleted")]
+    UserDeleted { user_id: Uuid },
+    #[serde(rename = "category.created")]
+    CategoryCreated(category::Model),
+    #[serde(rename = "category.deleted")]
+    CategoryDeleted(category::Model),
+    #[serde(rename = "record.created")]
+    RecordCreated(record::Model),
+    #[serde(rename = "record.updated")]
+    RecordUpdated(record::Model),
+    #[serde(rename = "record.deleted")]
+    RecordDeleted(record::Model),
+    /// A webhook delivery was queued to be attempted again.
+    #[serde(rename = "webhook_delivery.retried")]
+    WebhookDeliveryRetried { webhook_id: Uuid, delivery_id: Uuid },
+}
+
+impl DomainEvent {
+    /// The event's type, e.g. `record.created`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UserCreated { .. } => "user.created",
+            Self::UserDeleted { .. } => "user.deleted",
+            Self::CategoryCreated(_) => "category.created",
+            Self::CategoryDeleted(_) => "category.deleted",
+            Self::RecordCreated(_) => "record.created",
+            Self::RecordUpdated(_) => "record.updated",
+            Self::RecordDeleted(_) => "record.deleted",
+            Self::WebhookDeliveryRetried { .. } => "webhook_delivery.retried",
+        }
+    }
+
+    fn to_model(&self) -> outbox::ActiveModel {
+        let mut value = serde_json::to_value(self).expect("domain events serialize to JSON");
+        outbox::ActiveModel {
+            kind: Set(self.kind().to_owned()),
+            payload: Set(value["data"].take()),
+            ..Default::default()
+        }
+    }
+
+    fn from_model(model: &outbox::Model) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(json!({ "type": model.kind, "data": model.payload }))
+    }
+}
+
+/// A published event, as handed to subscribers.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    /// Unique to the event, and the same every time it's handed over.
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub event: DomainEvent,
+}
+
+/// Publishes `events` once `db`, a transaction, commits.
+pub(crate) async fn publish<C>(
+    db: &C,
+    events: impl IntoIterator<Item = DomainEvent>,
+) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let events: Vec<_> = events.into_iter().map(|event| event.to_model()).collect();
+    if events.is_empty() {
+        return Ok(());
+    }
+    outbox::Entity::insert_many(events)
+        .exec_without_returning(db)
+        .await?;
+    Ok(())
+}
+
+/// Reacts to domain events, e.g. by auditing them or invalidating caches.
+#[async_trait]
+pub trait Subscriber: Send + Sync {
+    /// The name the subscriber's progress is tracked under, which must be
+    /// unique and should stay the same across releases.
+    fn name(&self) -> &'static str;
+
+    /// Handles an event, failing if it should be handed over again later.
+    async fn handle(&self, envelope: &Envelope) -> Result<(), String>;
+}
+
+/// Logs every event, as an audit trail.
+pub struct Audit;
+
+#[async_trait]
+impl Subscriber for Audit {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    async fn handle(&self, envelope: &Envelope) -> Result<(), String> {
+        let data = serde_json::to_value(&envelope.event).map_err(|e| e.to_string())?;
+        tracing::info!(
+            event_id = envelope.id,
+            event = envelope.event.kind(),
+            data = %data["data"],
+            "domain event",
+        );
+        Ok(())
+    }
+}
+
+/// Hands published events to subscribers until cancelled.
+pub struct Dispatcher {
+    db: DatabaseConnection,
+    subscribers: Vec<Arc<dyn Subscriber>>,
+    poll_interval: Duration,
+    retry_base: Duration,
+}
+
+impl Dispatcher {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            subscribers: Vec::new(),
+            poll_interval: Duration::from_secs(1),
+            retry_base: Duration::from_secs(5),
+        }
+    }
+
+    pub fn subscribe(mut self, subscriber: impl Subscriber + 'static) -> Self {
+        self.subscribers.push(Arc::new(subscriber));
+        self
+    }
+
+    /// How often to check for new events.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How long to wait after an event's first failed dispatch, doubling with
+    /// each one after it up to an hour.
+    pub fn retry_base(mut self, base: Duration) -> Self {
+        self.retry_base = base;
+        self
+    }
+
+    pub async fn run(self, token: CancellationToken) {
+        let mut interval = time::interval(self.poll_interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let mut purge = time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                () = token.cancelled() => return,
+                _ = interval.tick() => self.dispatch_due().await,
+                _ = purge.tick() => self.purge().await,
+            }
+        }
+    }
+
+    async fn dispatch_due(&self) {
+        let events = match self.claim().await {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to claim domain events");
+                return;
+            }
+        };
+        future::join_all(events.into_iter().map(|event| self.dispatch(event))).await;
+    }
+
+    /// Claims a batch of due events, oldest first, leasing them so other
+    /// dispatchers skip them.
+    async fn claim(&self) -> Result<Vec<outbox::Model>, DbErr> {
+        let now = Utc::now().naive_utc();
+        let txn = self.db.begin().await?;
+        let due = outbox::Entity::find()
+            .filter(outbox::Column::DispatchedAt.is_null())
+            .filter(outbox::Column::NextAttemptAt.lte(now))
+            .order_by_asc(outbox::Column::Id)
+            .limit(BATCH_SIZE)
+            .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+            .all(&txn)
+            .await?;
+        if !due.is_empty() {
+            outbox::Entity::update_many()
+                .col_expr(outbox::Column::NextAttemptAt, Expr::value(now + LEASE))
+                .filter(outbox::Column::Id.is_in(due.iter().map(|event| event.id)))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(due)
+    }
+
+    /// Hands `event` to the subscribers yet to handle it, recording which of them
+    /// failed so only they get it again.
+    async fn dispatch(&self, event: outbox::Model) {
+        let envelope = match DomainEvent::from_model(&event) {
+            Ok(domain_event) => Envelope {
+                id: event.id,
+                created_at: event.created_at,
+                event: domain_event,
+            },
+            Err(e) => {
+                // Published by a newer release, which will dispatch it.
+                tracing::warn!(error = %e, event_id = event.id, "failed to read domain event");
+                return;
+            }
+        };
+        let pending: Vec<_> = self
+            .subscribers
+            .iter()
+            .filter(|subscriber| {
+                event.pending_subscribers.as_ref().is_none_or(|pending| {
+                    pending
+                        .split_whitespace()
+                        .any(|name| name == subscriber.name())
+                })
+            })
+            .collect();
+        let outcomes = future::join_all(
+            pending
+                .iter()
+                .map(|subscriber| subscriber.handle(&envelope)),
+        )
+        .await;
+
+        let mut failed = Vec::new();
+        let mut errors = Vec::new();
+        for (subscriber, outcome) in pending.iter().zip(outcomes) {
+            if let Err(e) = outcome {
+                tracing::warn!(
+                    error = %e,
+                    event_id = event.id,
+                    subscriber = subscriber.name(),
+                    "subscriber failed to handle domain event",
+                );
+                failed.push(subscriber.name());
+                errors.push(format!("{}: {e}", subscriber.name()));
+            }
+        }
+
+        let now = Utc::now().naive_utc();
+        let attempts = event.attempts + 1;
+        let mut update = outbox::ActiveModel {
+            id: Set(event.id),
+            attempts: Set(attempts),
+            ..Default::default()
+        };
+        if failed.is_empty() {
+            update.dispatched_at = Set(Some(now));
+            update.pending_subscribers = Set(None);
+            update.last_error = Set(None);
+        } else {
+            let wait = backoff(self.retry_base, attempts as u32);
+            update.next_attempt_at =
+                Set(now + chrono::Duration::from_std(wait).unwrap_or(chrono::Duration::MAX));
+            update.pending_subscribers = Set(Some(failed.join(" ")));
+            update.last_error = Set(Some(
+                errors.join("; ").chars().take(MAX_ERROR_LEN).collect(),
+            ));
+        }
+        if let Err(e) = outbox::Entity::update(update).exec(&self.db).await {
+            tracing::warn!(error = %e, event_id = event.id, "failed to record domain event dispatch");
+        }
+    }
+
+    async fn purge(&self) {
+        let before = Utc::now().naive_utc() - RETENTION;
+        if let Err(e) = outbox::Entity::delete_many()
+            .filter(outbox::Column::DispatchedAt.lt(before))
+            .exec(&self.db)
+            .await
+        {
+            tracing::warn!(error = %e, "failed to purge domain events");
+        }
+    }
+}
+
+/// How long to wait after the `attempts`th failed dispatch.
+fn backoff(base: Duration, attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    base.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let base = Duration::from_secs(30);
+        assert_eq!(backoff(base, 1), Duration::from_secs(30));
+        assert_eq!(backoff(base, 2), Duration::from_secs(60));
+        assert_eq!(backoff(base, 4), Duration::from_secs(240));
+        assert_eq!(backoff(base, 20), MAX_BACKOFF);
+        assert_eq!(backoff(base, u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn round_trips_through_the_outbox() {
+        let event = DomainEvent::UserDeleted {
+            user_id: Uuid::new_v4(),
+        };
+        let model = event.to_model();
+        let model = outbox::Model {
+            id: 1,
+            kind: model.kind.unwrap(),
+            payload: model.payload.unwrap(),
+            created_at: Utc::now().naive_utc(),
+            attempts: 0,
+            next_attempt_at: Utc::now().naive_utc(),
+            pending_subscribers: None,
+            last_error: None,
+            dispatched_at: None,
+        };
+        assert_eq!(model.kind, "user.deleted");
+        assert!(model.payload.get("user_id").is_some());
+        assert_eq!(DomainEvent::from_model(&model).unwrap(), event);
+    }
+}
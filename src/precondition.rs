@@ -0,0 +1,194 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, PrimaryKeyTrait};
+use utoipa::{
+    IntoParams,
+    openapi::{
+        Object, Required, Type,
+        path::{Parameter, ParameterBuilder, ParameterIn},
+    },
+};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// Formats an entity version as a strong entity tag.
+pub fn etag(version: i32) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{version}\"")).expect("entity tag is a valid header value")
+}
+
+/// Formats the versions of a resource and of the objects inlined in it as a weak
+/// entity tag, which changes whenever any of them does.
+pub fn weak_etag(versions: &[i32]) -> HeaderValue {
+    HeaderValue::from_str(&format!("W/\"{}\"", combined(versions)))
+        .expect("entity tag is a valid header value")
+}
+
+fn combined(versions: &[i32]) -> String {
+    let versions: Vec<_> = versions.iter().map(i32::to_string).collect();
+    versions.join("-")
+}
+
+/// Attaches the `ETag` header for `version` to a response.
+pub fn tagged(version: i32, res: impl IntoResponse) -> Response {
+    ([(header::ETAG, etag(version))], res).into_response()
+}
+
+enum EntityTags {
+    Any,
+    /// The opaque tags listed, without their quotes.
+    Tags(Vec<String>),
+}
+
+impl EntityTags {
+    fn from_headers(headers: &HeaderMap, name: header::HeaderName, weak: bool) -> Option<Self> {
+        let mut tags = Vec::new();
+        for value in headers.get_all(name) {
+            let value = value.to_str().ok()?;
+            for tag in value.split(',').map(str::trim) {
+                if tag == "*" {
+                    return Some(Self::Any);
+                }
+                let tag = match tag.strip_prefix("W/") {
+                    Some(tag) if weak => tag,
+                    Some(_) => continue,
+                    None => tag,
+                };
+                if let Some(tag) = tag.strip_prefix('"').and_then(|tag| tag.strip_suffix('"')) {
+                    tags.push(tag.to_owned());
+                }
+            }
+        }
+        Some(Self::Tags(tags))
+    }
+
+    fn matches(&self, tag: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|listed| listed == tag),
+        }
+    }
+}
+
+/// The `If-Match` header, required on requests that modify a resource.
+pub struct IfMatch(EntityTags);
+
+impl IfMatch {
+    /// Matches `version` alone, for clients that pass it outside the header,
+    /// e.g. as a GraphQL argument.
+    pub fn version(version: i32) -> Self {
+        Self(EntityTags::Tags(vec![version.to_string()]))
+    }
+
+    /// A condition restricting a statement to rows whose version matches the header.
+    pub fn condition(&self, column: impl ColumnTrait) -> Condition {
+        match &self.0 {
+            EntityTags::Any => Condition::all(),
+            EntityTags::Tags(tags) => {
+                let versions = tags.iter().filter_map(|tag| tag.parse::<i32>().ok());
+                Condition::all().add(column.is_in(versions))
+            }
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if !parts.headers.contains_key(header::IF_MATCH) {
+            return Err(AppError::PreconditionRequired);
+        }
+        EntityTags::from_headers(&parts.headers, header::IF_MATCH, false)
+            .map(Self)
+            .ok_or(AppError::PreconditionFailed)
+    }
+}
+
+impl IntoParams for IfMatch {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![header_param(
+            "If-Match",
+            Required::True,
+            "Entity tag of the version being modified, or `*` for any version",
+        )]
+    }
+}
+
+/// The optional `If-None-Match` header, honoured by requests that read a resource.
+pub struct IfNoneMatch(Option<EntityTags>);
+
+impl IfNoneMatch {
+    /// Responds with `304 Not Modified` if the client's copy is current.
+    pub fn respond(self, version: i32, res: impl IntoResponse) -> Response {
+        match self.0 {
+            Some(tags) if tags.matches(&version.to_string()) => {
+                tagged(version, StatusCode::NOT_MODIFIED)
+            }
+            _ => tagged(version, res),
+        }
+    }
+
+    /// Responds with `304 Not Modified` if the client's copy of a resource with
+    /// other objects inlined is current, by the weak tag of all their `versions`.
+    pub fn respond_weak(self, versions: &[i32], res: impl IntoResponse) -> Response {
+        let etag = weak_etag(versions);
+        match self.0 {
+            Some(tags) if tags.matches(&combined(versions)) => {
+                ([(header::ETAG, etag)], StatusCode::NOT_MODIFIED).into_response()
+            }
+            _ => ([(header::ETAG, etag)], res).into_response(),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if !parts.headers.contains_key(header::IF_NONE_MATCH) {
+            return Ok(Self(None));
+        }
+        Ok(Self(EntityTags::from_headers(
+            &parts.headers,
+            header::IF_NONE_MATCH,
+            true,
+        )))
+    }
+}
+
+impl IntoParams for IfNoneMatch {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![header_param(
+            "If-None-Match",
+            Required::False,
+            "Entity tags of cached versions",
+        )]
+    }
+}
+
+fn header_param(name: &str, required: Required, description: &str) -> Parameter {
+    ParameterBuilder::new()
+        .name(name)
+        .parameter_in(ParameterIn::Header)
+        .required(required)
+        .description(Some(description))
+        .schema(Some(Object::with_type(Type::String)))
+        .build()
+}
+
+/// Explains why a conditional statement on the entity with `id` affected no rows.
+pub async fn mismatch<E>(db: &DatabaseConnection, id: Uuid) -> AppError
+where
+    E: EntityTrait,
+    <E::PrimaryKey as PrimaryKeyTrait>::ValueType: From<Uuid>,
+{
+    match E::find_by_id(id).one(db).await {
+        Ok(Some(_)) => AppError::PreconditionFailed,
+        Ok(None) => AppError::NotFound,
+        Err(e) => e.into(),
+    }
+}
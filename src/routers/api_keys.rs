@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{NaiveDateTime, Utc};
+use entity::api_key;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::name_errors;
+use crate::{
+    AppState,
+    auth::{self, Caller, Scope},
+    error::{AppError, FieldError, Problem},
+    extract::{Json, Path},
+};
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_api_keys, create_api_key))
+        .routes(routes!(delete_api_key))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct ApiKeyBody<T> {
+    api_key: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ApiKeysBody<T> {
+    api_keys: Vec<T>,
+}
+
+/// A created API key along with its secret, which is only ever shown once.
+#[derive(Debug, Serialize, ToSchema)]
+struct CreatedApiKeyBody {
+    api_key: ApiKey,
+    key: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ApiKey {
+    id: Uuid,
+    /// The user the key acts for.
+    user_id: Uuid,
+    name: String,
+    scopes: Vec<Scope>,
+    created_at: NaiveDateTime,
+    expires_at: Option<NaiveDateTime>,
+    /// When the key was last used, to the minute.
+    last_used_at: Option<NaiveDateTime>,
+}
+
+impl From<api_key::Model> for ApiKey {
+    fn from(value: api_key::Model) -> Self {
+        Self {
+            id: value.id,
+            user_id: value.user_id,
+            name: value.name,
+            scopes: Scope::parse_all(&value.scopes),
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            last_used_at: value.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ApiKeyCreate {
+    /// What the key is for, e.g. the service using it.
+    name: String,
+    scopes: Vec<Scope>,
+    /// The key never expires if not set.
+    #[serde(default)]
+    expires_at: Option<NaiveDateTime>,
+}
+
+impl ApiKeyCreate {
+    fn validate(&self) -> Result<(), AppError> {
+        let mut errors = name_errors(&self.name);
+
+        if self.scopes.is_empty() {
+            errors.push(FieldError::new("scopes", "empty", "scopes are empty"));
+        }
+
+        if self
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+        {
+            errors.push(FieldError::new(
+                "expires_at",
+                "in_past",
+                "expiry is in the past",
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::unprocessable_entity(errors))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "api-keys",
+    responses(
+        (status = OK, description = "The caller's API keys, or every key for admins", body = ApiKeysBody<ApiKey>),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_api_keys(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+) -> Result<Json<ApiKeysBody<ApiKey>>, AppError> {
+    let mut query = api_key::Entity::find().order_by_asc(api_key::Column::CreatedAt);
+    if !caller.is_admin() {
+        query = query.filter(api_key::Column::UserId.eq(caller.id));
+    }
+    let api_keys = query.all(&db).await?.into_iter().map(Into::into).collect();
+    Ok(Json(ApiKeysBody { api_keys }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "api-keys",
+    request_body = ApiKeyBody<ApiKeyCreate>,
+    responses(
+        (status = CREATED, description = "The created API key and its secret", body = CreatedApiKeyBody),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_api_key(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<ApiKeyBody<ApiKeyCreate>>,
+) -> Result<(StatusCode, Json<CreatedApiKeyBody>), AppError> {
+    body.api_key.validate()?;
+    let key = auth::generate_api_key();
+    let scopes: BTreeSet<_> = body.api_key.scopes.iter().map(|s| s.as_str()).collect();
+    let api_key = api_key::ActiveModel {
+        user_id: Set(caller.id),
+        name: Set(body.api_key.name),
+        secret_hash: Set(auth::hash_token(&key)),
+        scopes: Set(scopes.into_iter().collect::<Vec<_>>().join(" ")),
+        expires_at: Set(body.api_key.expires_at),
+        ..Default::default()
+    };
+    let api_key = ApiKey::from(api_key.insert(&db).await?);
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKeyBody { api_key, key }),
+    ))
+}
+
+/// Revokes the API key, so it stops working at once.
+#[utoipa::path(
+    delete,
+    path = "/{api_key_id}",
+    tag = "api-keys",
+    params(("api_key_id" = Uuid, Path, description = "API key ID")),
+    responses(
+        (status = NO_CONTENT, description = "The API key was revoked"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_api_key(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let api_key = api_key::Entity::find_by_id(id)
+        .one(&db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    caller.require_user(api_key.user_id)?;
+    api_key::Entity::delete_by_id(id).exec(&db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,36 +1,47 @@
-use axum::{
-    Json, Router,
-    extract::{Path, State},
-    http::StatusCode,
-    routing::get,
+use axum::{extract::State, http::StatusCode, response::Response};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QuerySelect, TransactionTrait, sea_query::Expr,
 };
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
-use crate::{AppState, error::AppError};
-use entity::category;
+use super::validate_name;
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    extract::{Json, Path, Query},
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+    routers::records,
+};
+use entity::{category, household, household_member, record};
 
-pub fn router() -> Router<AppState> {
-    Router::new()
-        .route("/", get(get_categories).post(create_category))
-        .route("/{category_id}", get(get_category).delete(delete_category))
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_categories, create_category))
+        .routes(routes!(get_category, update_category, delete_category))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct CategoryBody<T> {
     category: T,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct CategoriesBody<T> {
     categories: Vec<T>,
 }
 
-#[derive(Debug, Serialize)]
-struct Category {
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Category {
     id: Uuid,
     name: String,
+    /// The household the category belongs to, global categories have none.
+    household_id: Option<Uuid>,
 }
 
 impl From<category::Model> for Category {
@@ -38,72 +49,343 @@ impl From<category::Model> for Category {
         Self {
             id: value.id,
             name: value.name,
+            household_id: value.household_id,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct CategoryCreate {
-    name: String,
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct CategoryCreate {
+    pub(crate) name: String,
+    /// Creates a category of the household rather than a global one.
+    #[serde(default)]
+    pub(crate) household_id: Option<Uuid>,
 }
 
 impl CategoryCreate {
     fn validate(&self) -> Result<(), AppError> {
-        let mut errors = Vec::new();
+        validate_name(&self.name)
+    }
+}
 
-        if self.name.is_empty() {
-            errors.push(("name", "name is empty"));
-        }
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct CategoryUpdate {
+    pub(crate) name: String,
+}
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(AppError::unprocessable_entity(errors))
-        }
+impl CategoryUpdate {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_name(&self.name)
     }
 }
 
-async fn get_category(
-    State(AppState { db }): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<CategoryBody<Category>>, AppError> {
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CategoryFilterParams {
+    /// Only the household's categories, rather than global ones and those of the caller's households.
+    household_id: Option<Uuid>,
+}
+
+/// Global categories are managed by admins, household ones by the household's editors.
+async fn authorize(
+    db: &DatabaseConnection,
+    caller: Caller,
+    household_id: Option<Uuid>,
+    min: household_member::Role,
+) -> Result<(), AppError> {
+    match household_id {
+        Some(household_id) => caller.require_household(db, household_id, min).await,
+        None if min == household_member::Role::Viewer => Ok(()),
+        None => caller.require_admin(),
+    }
+}
+
+/// Finds the category with `id`, if the caller may access it with at least the `min` role.
+pub(crate) async fn find(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    min: household_member::Role,
+) -> Result<category::Model, AppError> {
     let category = category::Entity::find_by_id(id)
-        .one(&db)
+        .one(db)
         .await?
-        .ok_or(AppError::NotFound)?
-        .into();
-    Ok(Json(CategoryBody { category }))
+        .ok_or(AppError::NotFound)?;
+    authorize(db, caller, category.household_id, min).await?;
+    Ok(category)
 }
 
-async fn create_category(
-    State(AppState { db }): State<AppState>,
-    Json(body): Json<CategoryBody<CategoryCreate>>,
-) -> Result<(StatusCode, Json<CategoryBody<Category>>), AppError> {
-    body.category.validate()?;
+/// The household's categories, or global ones and those of the caller's households.
+pub(crate) async fn list(
+    db: &DatabaseConnection,
+    caller: Caller,
+    household_id: Option<Uuid>,
+) -> Result<Vec<category::Model>, AppError> {
+    let mut query = category::Entity::find();
+    if let Some(household_id) = household_id {
+        caller
+            .require_household(db, household_id, household_member::Role::Viewer)
+            .await?;
+        query = query.filter(category::Column::HouseholdId.eq(household_id));
+    } else if !caller.is_admin() {
+        let households: Vec<_> = caller.memberships(db).await?.households().collect();
+        query = query.filter(
+            Condition::any()
+                .add(category::Column::HouseholdId.is_null())
+                .add(category::Column::HouseholdId.is_in(households)),
+        );
+    }
+    Ok(query.all(db).await?)
+}
+
+pub(crate) async fn create(
+    db: &DatabaseConnection,
+    caller: Caller,
+    new: CategoryCreate,
+) -> Result<category::Model, AppError> {
+    authorize(db, caller, new.household_id, household_member::Role::Editor).await?;
+    new.validate()?;
+    if let Some(household_id) = new.household_id
+        && household::Entity::find_by_id(household_id)
+            .one(db)
+            .await?
+            .is_none()
+    {
+        return Err(AppError::unprocessable_entity([FieldError::new(
+            "household_id",
+            "not_found",
+            "household doesn't exist",
+        )]));
+    }
     let category = category::ActiveModel {
-        name: Set(body.category.name),
+        name: Set(new.name),
+        household_id: Set(new.household_id),
         ..Default::default()
     };
-    let category = category.insert(&db).await?.into();
-    Ok((StatusCode::CREATED, Json(CategoryBody { category })))
+    let txn = db.begin().await?;
+    let category = category.insert(&txn).await?;
+    outbox::publish(&txn, [DomainEvent::CategoryCreated(category.clone())]).await?;
+    txn.commit().await?;
+    Ok(category)
 }
 
+pub(crate) async fn update(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+    changes: CategoryUpdate,
+) -> Result<category::Model, AppError> {
+    find(db, caller, id, household_member::Role::Editor).await?;
+    changes.validate()?;
+    let Some(category) = category::Entity::update_many()
+        .col_expr(category::Column::Name, Expr::value(changes.name))
+        .col_expr(
+            category::Column::Version,
+            Expr::col(category::Column::Version).add(1),
+        )
+        .filter(category::Column::Id.eq(id))
+        .filter(if_match.condition(category::Column::Version))
+        .exec_with_returning(db)
+        .await?
+        .pop()
+    else {
+        return Err(precondition::mismatch::<category::Entity>(db, id).await);
+    };
+    Ok(category)
+}
+
+pub(crate) async fn delete(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<(), AppError> {
+    find(db, caller, id, household_member::Role::Editor).await?;
+    let txn = db.begin().await?;
+    category::Entity::find_by_id(id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    let deleted = records::delete_where(&txn, record::Column::CategoryId.eq(id)).await?;
+    let Some(category) = category::Entity::delete_many()
+        .filter(category::Column::Id.eq(id))
+        .filter(if_match.condition(category::Column::Version))
+        .exec_with_returning(&txn)
+        .await?
+        .pop()
+    else {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<category::Entity>(db, id).await);
+    };
+    records::log_deleted(&txn, &deleted).await?;
+    outbox::publish(&txn, [DomainEvent::CategoryDeleted(category)]).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/{category_id}",
+    tag = "categories",
+    params(
+        ("category_id" = Uuid, Path, description = "Category ID"),
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The category",
+            body = CategoryBody<Category>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (
+            status = NOT_MODIFIED,
+            description = "The cached category is current",
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_category(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    let category = find(&db, caller, id, household_member::Role::Viewer).await?;
+    let version = category.version;
+    let category = Category::from(category);
+    Ok(if_none_match.respond(version, Json(CategoryBody { category })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "categories",
+    request_body = CategoryBody<CategoryCreate>,
+    responses(
+        (
+            status = CREATED,
+            description = "The created category",
+            body = CategoryBody<Category>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_category(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<CategoryBody<CategoryCreate>>,
+) -> Result<Response, AppError> {
+    let category = create(&db, caller, body.category).await?;
+    let version = category.version;
+    let category = Category::from(category);
+    Ok(precondition::tagged(
+        version,
+        (StatusCode::CREATED, Json(CategoryBody { category })),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{category_id}",
+    tag = "categories",
+    params(
+        ("category_id" = Uuid, Path, description = "Category ID"),
+        IfMatch,
+    ),
+    request_body = CategoryBody<CategoryUpdate>,
+    responses(
+        (
+            status = OK,
+            description = "The updated category",
+            body = CategoryBody<Category>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_category(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<CategoryBody<CategoryUpdate>>,
+) -> Result<Response, AppError> {
+    let category = update(&db, caller, id, &if_match, body.category).await?;
+    let version = category.version;
+    let category = Category::from(category);
+    Ok(precondition::tagged(
+        version,
+        Json(CategoryBody { category }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{category_id}",
+    tag = "categories",
+    params(
+        ("category_id" = Uuid, Path, description = "Category ID"),
+        IfMatch,
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The category was deleted"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 async fn delete_category(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
     Path(id): Path<Uuid>,
+    if_match: IfMatch,
 ) -> Result<StatusCode, AppError> {
-    let res = category::Entity::delete_by_id(id).exec(&db).await?;
-    match res.rows_affected {
-        0 => Err(AppError::NotFound),
-        _ => Ok(StatusCode::NO_CONTENT),
-    }
+    delete(&db, caller, id, &if_match).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "categories",
+    params(CategoryFilterParams),
+    responses(
+        (status = OK, description = "The categories the caller can use", body = CategoriesBody<Category>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 async fn get_categories(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Query(params): Query<CategoryFilterParams>,
 ) -> Result<Json<CategoriesBody<Category>>, AppError> {
-    let categories = category::Entity::find()
-        .all(&db)
+    let categories = list(&db, caller, params.household_id)
         .await?
         .into_iter()
         .map(Into::into)
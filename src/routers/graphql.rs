@@ -0,0 +1,46 @@
+use async_graphql::http::GraphiQLSource;
+use axum::{extract::State, response::Html};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{AppState, auth::Caller, error::Problem, extract::Json, graphql};
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(execute))
+}
+
+/// Runs a GraphQL query or mutation, see `GET /graphql` for the schema.
+///
+/// Responds with `200 OK` even if the operation fails, with the problems in its
+/// `errors` member.
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "graphql",
+    request_body(
+        content = Object,
+        description = "A GraphQL request with its `query`, and optionally `variables` and `operationName`"
+    ),
+    responses(
+        (status = OK, description = "The operation's `data` and `errors`", body = Object),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn execute(
+    State(AppState { db, graphql, .. }): State<AppState>,
+    caller: Caller,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(
+        graphql
+            .execute(graphql::request(request, &db, caller))
+            .await,
+    )
+}
+
+/// The GraphiQL IDE, to explore the schema and try out queries.
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
@@ -1,89 +1,179 @@
-use axum::{Json, Router, extract::State, http::StatusCode, response::IntoResponse, routing::get};
-use sea_orm::DatabaseConnection;
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use serde::Serialize;
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
 
-use crate::AppState;
+use crate::{AppState, extract::Json, services::Report};
 
-pub fn router() -> Router<AppState> {
-    Router::new().route("/", get(check_health))
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(check_health))
+        .routes(routes!(check_liveness))
+        .routes(routes!(check_readiness))
+        .routes(routes!(check_startup))
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 enum AppStatus {
     Healthy,
     Unhealthy,
 }
 
-#[derive(Serialize)]
+impl AppStatus {
+    fn of(reports: &[Report]) -> Self {
+        let healthy = reports
+            .iter()
+            .filter(|report| report.critical)
+            .all(|report| report.status.up);
+
+        if healthy {
+            AppStatus::Healthy
+        } else {
+            AppStatus::Unhealthy
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppStatus::Healthy => StatusCode::OK,
+            AppStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 enum ServiceStatus {
     Up,
     Down,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "lowercase")]
-struct Services {
-    db: ServiceStatus,
+#[derive(Serialize, ToSchema)]
+struct ServiceHealth {
+    status: ServiceStatus,
+    /// Whether the app is unhealthy while the service is down.
+    critical: bool,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    #[schema(value_type = Object)]
+    details: Map<String, Value>,
 }
 
-impl Services {
-    async fn now(state: AppState) -> Self {
+impl From<Report> for ServiceHealth {
+    fn from(report: Report) -> Self {
         Self {
-            db: check_db(&state.db).await,
+            status: if report.status.up {
+                ServiceStatus::Up
+            } else {
+                ServiceStatus::Down
+            },
+            critical: report.critical,
+            latency_ms: report.latency.as_secs_f64() * 1000.0,
+            details: report.status.details,
         }
     }
+}
 
-    fn status(&self) -> AppStatus {
-        let critical_services = [&self.db];
-
-        let healthy = critical_services
-            .iter()
-            .all(|status| matches!(status, ServiceStatus::Up));
-
-        if healthy {
-            AppStatus::Healthy
-        } else {
-            AppStatus::Unhealthy
-        }
-    }
+#[derive(Serialize, ToSchema)]
+struct Health {
+    status: AppStatus,
+    version: &'static str,
+    /// The commit the app was built from, if known.
+    commit: Option<&'static str>,
+    uptime_secs: u64,
+    services: BTreeMap<String, ServiceHealth>,
+}
 
-    fn into_health(self) -> Health {
-        Health {
-            status: self.status(),
-            services: self,
-        }
+impl IntoResponse for Health {
+    fn into_response(self) -> axum::response::Response {
+        (self.status.status_code(), Json(self)).into_response()
     }
 }
 
-#[derive(Serialize)]
-struct Health {
+#[derive(Serialize, ToSchema)]
+struct Probe {
     status: AppStatus,
-    services: Services,
 }
 
-impl IntoResponse for Health {
+impl IntoResponse for Probe {
     fn into_response(self) -> axum::response::Response {
-        let status = match self.status {
-            AppStatus::Healthy => StatusCode::OK,
-            AppStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
-        };
-        let body = Json(self);
-        (status, body).into_response()
+        (self.status.status_code(), Json(self)).into_response()
     }
 }
 
-async fn check_health(State(state): State<AppState>) -> Health {
-    Services::now(state).await.into_health()
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "health",
+    security(()),
+    responses(
+        (status = OK, description = "All critical services are up", body = Health),
+        (status = SERVICE_UNAVAILABLE, description = "A critical service is down", body = Health),
+    )
+)]
+async fn check_health(State(AppState { services, .. }): State<AppState>) -> Health {
+    let reports = services.check(|_| true).await;
+    Health {
+        status: AppStatus::of(&reports),
+        version: env!("CARGO_PKG_VERSION"),
+        commit: option_env!("GIT_COMMIT").filter(|commit| !commit.is_empty()),
+        uptime_secs: services.uptime().as_secs(),
+        services: reports
+            .into_iter()
+            .map(|report| (report.name.to_owned(), report.into()))
+            .collect(),
+    }
 }
 
-async fn check_db(db: &DatabaseConnection) -> ServiceStatus {
-    match db.ping().await {
-        Ok(_) => ServiceStatus::Up,
-        Err(e) => {
-            eprintln!("database ping failed: {e}");
-            ServiceStatus::Down
-        }
+#[utoipa::path(
+    get,
+    path = "/live",
+    tag = "health",
+    security(()),
+    responses(
+        (status = OK, description = "The process is up", body = Probe),
+    )
+)]
+async fn check_liveness() -> Probe {
+    Probe {
+        status: AppStatus::Healthy,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    security(()),
+    responses(
+        (status = OK, description = "The app can serve requests", body = Probe),
+        (status = SERVICE_UNAVAILABLE, description = "A critical service is down", body = Probe),
+    )
+)]
+async fn check_readiness(State(AppState { services, .. }): State<AppState>) -> Probe {
+    let reports = services.check(|check| check.critical()).await;
+    Probe {
+        status: AppStatus::of(&reports),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/startup",
+    tag = "health",
+    security(()),
+    responses(
+        (status = OK, description = "The app has finished starting", body = Probe),
+        (status = SERVICE_UNAVAILABLE, description = "The app is still starting", body = Probe),
+    )
+)]
+async fn check_startup(State(AppState { services, .. }): State<AppState>) -> Probe {
+    let reports = services.check(|check| check.startup()).await;
+    Probe {
+        status: AppStatus::of(&reports),
     }
 }
@@ -0,0 +1,665 @@
+use axum::{extract::State, http::StatusCode, response::Response};
+use chrono::{Duration, NaiveDateTime, Utc};
+use entity::{category, household, household_invitation, household_member, record, user};
+use sea_orm::{
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+    sea_query::{Expr, Query},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::validate_name;
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    extract::{Json, Path},
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+    routers::records,
+};
+
+const INVITATION_TTL_DAYS: i64 = 7;
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_households, create_household))
+        .routes(routes!(get_household, update_household, delete_household))
+        .routes(routes!(get_members))
+        .routes(routes!(update_member, delete_member))
+        .routes(routes!(create_invitation))
+        .routes(routes!(join_household))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct HouseholdBody<T> {
+    household: T,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct HouseholdsBody<T> {
+    households: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct MemberBody<T> {
+    member: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MembersBody<T> {
+    members: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct InvitationBody<T> {
+    invitation: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Household {
+    id: Uuid,
+    name: String,
+}
+
+impl From<household::Model> for Household {
+    fn from(value: household::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+        }
+    }
+}
+
+/// What a member may do in a household.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum MemberRole {
+    /// Manages the household, its members and invitations.
+    Owner,
+    /// Manages the household's categories and records.
+    Editor,
+    /// Reads the household's categories and records.
+    Viewer,
+}
+
+impl From<household_member::Role> for MemberRole {
+    fn from(value: household_member::Role) -> Self {
+        match value {
+            household_member::Role::Owner => Self::Owner,
+            household_member::Role::Editor => Self::Editor,
+            household_member::Role::Viewer => Self::Viewer,
+        }
+    }
+}
+
+impl From<MemberRole> for household_member::Role {
+    fn from(value: MemberRole) -> Self {
+        match value {
+            MemberRole::Owner => Self::Owner,
+            MemberRole::Editor => Self::Editor,
+            MemberRole::Viewer => Self::Viewer,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Member {
+    household_id: Uuid,
+    user_id: Uuid,
+    name: String,
+    role: MemberRole,
+}
+
+impl Member {
+    fn new(member: household_member::Model, user: user::Model) -> Self {
+        Self {
+            household_id: member.household_id,
+            user_id: member.user_id,
+            name: user.name,
+            role: member.role.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Invitation {
+    code: String,
+    role: MemberRole,
+    expires_at: NaiveDateTime,
+}
+
+impl From<household_invitation::Model> for Invitation {
+    fn from(value: household_invitation::Model) -> Self {
+        Self {
+            code: value.code,
+            role: value.role.into(),
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct HouseholdCreate {
+    name: String,
+}
+
+impl HouseholdCreate {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_name(&self.name)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct HouseholdUpdate {
+    name: String,
+}
+
+impl HouseholdUpdate {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_name(&self.name)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct MemberUpdate {
+    role: MemberRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct InvitationCreate {
+    /// The role the invited user joins with, `viewer` if not set.
+    #[serde(default)]
+    role: Option<MemberRole>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct InvitationAccept {
+    code: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{household_id}",
+    tag = "households",
+    params(
+        ("household_id" = Uuid, Path, description = "Household ID"),
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The household",
+            body = HouseholdBody<Household>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (
+            status = NOT_MODIFIED,
+            description = "The cached household is current",
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_household(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    caller
+        .require_household(&db, id, household_member::Role::Viewer)
+        .await?;
+    let household = household::Entity::find_by_id(id)
+        .one(&db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let version = household.version;
+    let household = Household::from(household);
+    Ok(if_none_match.respond(version, Json(HouseholdBody { household })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "households",
+    request_body = HouseholdBody<HouseholdCreate>,
+    responses(
+        (
+            status = CREATED,
+            description = "The created household, owned by the caller",
+            body = HouseholdBody<Household>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_household(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<HouseholdBody<HouseholdCreate>>,
+) -> Result<Response, AppError> {
+    body.household.validate()?;
+    let txn = db.begin().await?;
+    let household = household::ActiveModel {
+        name: Set(body.household.name),
+        ..Default::default()
+    };
+    let household = household.insert(&txn).await?;
+    let owner = household_member::ActiveModel {
+        household_id: Set(household.id),
+        user_id: Set(caller.id),
+        role: Set(household_member::Role::Owner),
+    };
+    owner.insert(&txn).await?;
+    txn.commit().await?;
+
+    let version = household.version;
+    let household = Household::from(household);
+    Ok(precondition::tagged(
+        version,
+        (StatusCode::CREATED, Json(HouseholdBody { household })),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{household_id}",
+    tag = "households",
+    params(
+        ("household_id" = Uuid, Path, description = "Household ID"),
+        IfMatch,
+    ),
+    request_body = HouseholdBody<HouseholdUpdate>,
+    responses(
+        (
+            status = OK,
+            description = "The updated household",
+            body = HouseholdBody<Household>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_household(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<HouseholdBody<HouseholdUpdate>>,
+) -> Result<Response, AppError> {
+    caller
+        .require_household(&db, id, household_member::Role::Owner)
+        .await?;
+    body.household.validate()?;
+    let Some(household) = household::Entity::update_many()
+        .col_expr(household::Column::Name, Expr::value(body.household.name))
+        .col_expr(
+            household::Column::Version,
+            Expr::col(household::Column::Version).add(1),
+        )
+        .filter(household::Column::Id.eq(id))
+        .filter(if_match.condition(household::Column::Version))
+        .exec_with_returning(&db)
+        .await?
+        .pop()
+    else {
+        return Err(precondition::mismatch::<household::Entity>(&db, id).await);
+    };
+    let version = household.version;
+    let household = Household::from(household);
+    Ok(precondition::tagged(
+        version,
+        Json(HouseholdBody { household }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{household_id}",
+    tag = "households",
+    params(
+        ("household_id" = Uuid, Path, description = "Household ID"),
+        IfMatch,
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The household was deleted along with its categories and records"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_household(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> Result<StatusCode, AppError> {
+    caller
+        .require_household(&db, id, household_member::Role::Owner)
+        .await?;
+    let txn = db.begin().await?;
+    household::Entity::find_by_id(id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    // The household's records, and any using its categories.
+    let categories = Query::select()
+        .column(category::Column::Id)
+        .from(category::Entity)
+        .and_where(category::Column::HouseholdId.eq(id))
+        .to_owned();
+    let condition = Condition::any()
+        .add(record::Column::HouseholdId.eq(id))
+        .add(record::Column::CategoryId.in_subquery(categories));
+    let deleted = records::delete_where(&txn, condition).await?;
+    // Foreign keys would cascade this on Postgres, but not to the outbox, and
+    // SQLite has none on this column.
+    let categories = category::Entity::delete_many()
+        .filter(category::Column::HouseholdId.eq(id))
+        .exec_with_returning(&txn)
+        .await?;
+    let res = household::Entity::delete_many()
+        .filter(household::Column::Id.eq(id))
+        .filter(if_match.condition(household::Column::Version))
+        .exec(&txn)
+        .await?;
+    if res.rows_affected == 0 {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<household::Entity>(&db, id).await);
+    }
+    records::log_deleted(&txn, &deleted).await?;
+    outbox::publish(
+        &txn,
+        categories.into_iter().map(DomainEvent::CategoryDeleted),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "households",
+    responses(
+        (status = OK, description = "The caller's households, or all of them for admins", body = HouseholdsBody<Household>),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_households(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+) -> Result<Json<HouseholdsBody<Household>>, AppError> {
+    let mut query = household::Entity::find();
+    if !caller.is_admin() {
+        query = query
+            .inner_join(household_member::Entity)
+            .filter(household_member::Column::UserId.eq(caller.id));
+    }
+    let households = query.all(&db).await?.into_iter().map(Into::into).collect();
+    Ok(Json(HouseholdsBody { households }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{household_id}/members",
+    tag = "households",
+    params(("household_id" = Uuid, Path, description = "Household ID")),
+    responses(
+        (status = OK, description = "The household's members", body = MembersBody<Member>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_members(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+) -> Result<Json<MembersBody<Member>>, AppError> {
+    caller
+        .require_household(&db, id, household_member::Role::Viewer)
+        .await?;
+    let members = household_member::Entity::find()
+        .filter(household_member::Column::HouseholdId.eq(id))
+        .find_also_related(user::Entity)
+        .all(&db)
+        .await?
+        .into_iter()
+        .filter_map(|(member, user)| Some(Member::new(member, user?)))
+        .collect();
+    Ok(Json(MembersBody { members }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{household_id}/members/{user_id}",
+    tag = "households",
+    params(
+        ("household_id" = Uuid, Path, description = "Household ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    request_body = MemberBody<MemberUpdate>,
+    responses(
+        (status = OK, description = "The updated member", body = MemberBody<Member>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = CONFLICT, description = "The household would be left without an owner", body = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_member(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path((household_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(body): Json<MemberBody<MemberUpdate>>,
+) -> Result<Json<MemberBody<Member>>, AppError> {
+    caller
+        .require_household(&db, household_id, household_member::Role::Owner)
+        .await?;
+    let txn = db.begin().await?;
+    let (member, user) = household_member::Entity::find_by_id((household_id, user_id))
+        .find_also_related(user::Entity)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let user = user.ok_or(AppError::NotFound)?;
+    let role = household_member::Role::from(body.member.role);
+    if role != household_member::Role::Owner {
+        ensure_other_owner(&txn, &member).await?;
+    }
+    let mut member: household_member::ActiveModel = member.into();
+    member.role = Set(role);
+    let member = member.update(&txn).await?;
+    txn.commit().await?;
+    let member = Member::new(member, user);
+    Ok(Json(MemberBody { member }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{household_id}/members/{user_id}",
+    tag = "households",
+    params(
+        ("household_id" = Uuid, Path, description = "Household ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The member was removed, or left"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = CONFLICT, description = "The household would be left without an owner", body = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_member(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path((household_id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    if caller.id != user_id {
+        caller
+            .require_household(&db, household_id, household_member::Role::Owner)
+            .await?;
+    }
+    let txn = db.begin().await?;
+    let member = household_member::Entity::find_by_id((household_id, user_id))
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    ensure_other_owner(&txn, &member).await?;
+    household_member::Entity::delete_by_id((household_id, user_id))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Rejects taking away `member`'s ownership if nobody else owns the household.
+///
+/// The owners are locked, in order, until the transaction ends, so that owners
+/// demoting or removing each other at once can't both go ahead.
+async fn ensure_other_owner(
+    db: &impl sea_orm::ConnectionTrait,
+    member: &household_member::Model,
+) -> Result<(), AppError> {
+    if member.role != household_member::Role::Owner {
+        return Ok(());
+    }
+    let owners: Vec<Uuid> = household_member::Entity::find()
+        .select_only()
+        .column(household_member::Column::UserId)
+        .filter(household_member::Column::HouseholdId.eq(member.household_id))
+        .filter(household_member::Column::Role.eq(household_member::Role::Owner))
+        .order_by_asc(household_member::Column::UserId)
+        .lock_exclusive()
+        .into_tuple()
+        .all(db)
+        .await?;
+    match owners.iter().any(|owner| *owner != member.user_id) {
+        true => Ok(()),
+        false => Err(AppError::Conflict),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/{household_id}/invitations",
+    tag = "households",
+    params(("household_id" = Uuid, Path, description = "Household ID")),
+    request_body = InvitationBody<InvitationCreate>,
+    responses(
+        (status = CREATED, description = "A single-use invitation code", body = InvitationBody<Invitation>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_invitation(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    Json(body): Json<InvitationBody<InvitationCreate>>,
+) -> Result<(StatusCode, Json<InvitationBody<Invitation>>), AppError> {
+    caller
+        .require_household(&db, id, household_member::Role::Owner)
+        .await?;
+    household::Entity::find_by_id(id)
+        .one(&db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let role = body.invitation.role.unwrap_or(MemberRole::Viewer);
+    let invitation = household_invitation::ActiveModel {
+        code: Set(Uuid::new_v4().simple().to_string()),
+        household_id: Set(id),
+        role: Set(role.into()),
+        expires_at: Set(Utc::now().naive_utc() + Duration::days(INVITATION_TTL_DAYS)),
+    };
+    let invitation = Invitation::from(invitation.insert(&db).await?);
+    Ok((StatusCode::CREATED, Json(InvitationBody { invitation })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/join",
+    tag = "households",
+    request_body = InvitationBody<InvitationAccept>,
+    responses(
+        (status = CREATED, description = "The caller joined the household", body = MemberBody<Member>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = CONFLICT, description = "The caller is already a member", body = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn join_household(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<InvitationBody<InvitationAccept>>,
+) -> Result<(StatusCode, Json<MemberBody<Member>>), AppError> {
+    let now = Utc::now().naive_utc();
+    let txn = db.begin().await?;
+    household_invitation::Entity::delete_many()
+        .filter(household_invitation::Column::ExpiresAt.lt(now))
+        .exec(&txn)
+        .await?;
+    let invitation = household_invitation::Entity::find_by_id(&body.invitation.code)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| {
+            AppError::unprocessable_entity([FieldError::new(
+                "code",
+                "not_found",
+                "invitation doesn't exist or has expired",
+            )])
+        })?;
+    let member = household_member::ActiveModel {
+        household_id: Set(invitation.household_id),
+        user_id: Set(caller.id),
+        role: Set(invitation.role),
+    };
+    let member = member.insert(&txn).await?;
+    household_invitation::Entity::delete_by_id(invitation.code)
+        .exec(&txn)
+        .await?;
+    let user = user::Entity::find_by_id(caller.id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    txn.commit().await?;
+    let member = Member::new(member, user);
+    Ok((StatusCode::CREATED, Json(MemberBody { member })))
+}
@@ -1,28 +1,103 @@
 use axum::{Router, routing::get};
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use utoipa_swagger_ui::SwaggerUi;
 
-use crate::AppState;
+use crate::{
+    AppState,
+    error::{AppError, FieldError},
+    openapi::ApiDoc,
+};
 
+pub mod api_keys;
 pub mod categories;
+pub mod graphql;
 pub mod health;
+pub mod households;
 pub mod records;
+pub mod settlements;
 pub mod users;
+pub mod webhooks;
 
-pub fn router() -> Router<AppState> {
+/// The longest name of a user, category, household or API key, in characters.
+const MAX_NAME_LEN: usize = 100;
+
+/// The API routes, along with their documentation if `docs` is set.
+pub fn router(docs: bool) -> Router<AppState> {
+    let (router, api) = api().split_for_parts();
+    if docs {
+        router
+            .merge(SwaggerUi::new("/docs").url("/openapi.json", api))
+            .route("/graphql", get(graphql::graphiql))
+    } else {
+        router
+    }
+}
+
+/// The API routes together with the OpenAPI document describing them.
+pub fn api() -> OpenApiRouter<AppState> {
     let health_router = health::router();
     let user_router = users::router();
+    let api_key_router = api_keys::router();
+    let household_router = households::router();
     let category_router = categories::router();
     let record_router = records::router();
+    let settlement_router = settlements::router();
+    let webhook_router = webhooks::router();
+    let graphql_router = graphql::router();
 
-    let router = Router::new()
-        .route("/", get(root))
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(root))
         .nest("/health", health_router)
         .nest("/users", user_router)
+        .nest("/api-keys", api_key_router)
+        .nest("/households", household_router)
         .nest("/categories", category_router)
-        .nest("/records", record_router);
-
-    router
+        .nest("/records", record_router)
+        .nest("/settlements", settlement_router)
+        .nest("/webhooks", webhook_router)
+        .nest("/graphql", graphql_router)
+        .fallback(fallback)
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    security(()),
+    responses((status = OK, description = "A welcome message", body = String))
+)]
 async fn root() -> &'static str {
     "Welcome to the expense tracker!"
 }
+
+async fn fallback() -> AppError {
+    AppError::NotFound
+}
+
+/// What's wrong with the name of a user, category, household or API key.
+fn name_errors(name: &str) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if name.is_empty() {
+        errors.push(FieldError::new("name", "empty", "name is empty"));
+    }
+
+    if name.chars().count() > MAX_NAME_LEN {
+        errors.push(
+            FieldError::new("name", "too_long", "name is too long")
+                .param("max_length", MAX_NAME_LEN),
+        )
+    }
+
+    errors
+}
+
+/// Checks the name of a user, category or household.
+fn validate_name(name: &str) -> Result<(), AppError> {
+    let errors = name_errors(name);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::unprocessable_entity(errors))
+    }
+}
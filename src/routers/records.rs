@@ -1,44 +1,72 @@
-use axum::{
-    Json, Router,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    routing::get,
-};
+use axum::{extract::State, http::StatusCode, response::Response};
 use chrono::NaiveDateTime;
-use entity::{category, record, user};
+use entity::{category, household_member, record, record_event, user};
 use rust_decimal::Decimal;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, LoaderTrait, QueryFilter,
+    Select, TransactionTrait,
+    sea_query::{Expr, IntoCondition},
 };
 use serde::{Deserialize, Serialize};
 use tokio::try_join;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
-use crate::{AppState, error::AppError};
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    events,
+    extract::{Json, Path, Query},
+    metrics,
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+    routers::{categories::Category, users::UserSummary},
+};
+
+mod bulk;
+mod shares;
+mod stream;
+mod summary;
 
-pub fn router() -> Router<AppState> {
-    Router::new()
-        .route("/", get(get_records).post(create_record))
-        .route("/{record_id}", get(get_record).delete(delete_record))
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_records, create_record))
+        .routes(routes!(get_record, update_record, delete_record))
+        .merge(shares::router())
+        .nest("/bulk", bulk::router())
+        .nest("/events", stream::router())
+        .nest("/summary", summary::router())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct RecordBody<T> {
     record: T,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct RecordsBody<T> {
     records: Vec<T>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct Record {
     id: Uuid,
     user_id: Uuid,
     category_id: Uuid,
     created_at: NaiveDateTime,
     sum: Decimal,
+    /// The household whose ledger the record is in, personal records have none.
+    household_id: Option<Uuid>,
+    /// The record's user, with `expand=user`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<UserSummary>,
+    /// The record's category, with `expand=category`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<Category>,
 }
 
 impl From<record::Model> for Record {
@@ -49,36 +77,277 @@ impl From<record::Model> for Record {
             category_id: value.category_id,
             created_at: value.created_at,
             sum: value.sum,
+            household_id: value.household_id,
+            user: None,
+            category: None,
+        }
+    }
+}
+
+/// Related objects to inline in records.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+struct Expand {
+    user: bool,
+    category: bool,
+}
+
+impl TryFrom<String> for Expand {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut expand = Self::default();
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name {
+                "user" => expand.user = true,
+                "category" => expand.category = true,
+                name => {
+                    return Err(format!(
+                        "unknown relation `{name}`, expected `user` or `category`"
+                    ));
+                }
+            }
+        }
+        Ok(expand)
+    }
+}
+
+#[derive(Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExpandParams {
+    /// Related objects to inline, comma-separated: `user`, `category` or both.
+    #[serde(default)]
+    #[param(value_type = Option<String>, example = "user,category")]
+    expand: Expand,
+}
+
+impl Expand {
+    fn any(self) -> bool {
+        self.user || self.category
+    }
+
+    /// Loads the related objects to inline in `records` with one query each.
+    async fn load(
+        self,
+        db: &DatabaseConnection,
+        records: Vec<record::Model>,
+    ) -> Result<Vec<Expanded>, AppError> {
+        let mut users = match self.user {
+            true => records.load_one(user::Entity, db).await?,
+            false => Vec::new(),
+        }
+        .into_iter();
+        let mut categories = match self.category {
+            true => records.load_one(category::Entity, db).await?,
+            false => Vec::new(),
+        }
+        .into_iter();
+        Ok(records
+            .into_iter()
+            .map(|record| Expanded {
+                record,
+                user: users.next().flatten(),
+                category: categories.next().flatten(),
+            })
+            .collect())
+    }
+
+    /// Converts `records`, inlining the related objects.
+    async fn records(
+        self,
+        db: &DatabaseConnection,
+        records: Vec<record::Model>,
+    ) -> Result<Vec<Record>, AppError> {
+        let expanded = self.load(db, records).await?;
+        Ok(expanded.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A record with the related objects to inline in it.
+struct Expanded {
+    record: record::Model,
+    user: Option<user::Model>,
+    category: Option<category::Model>,
+}
+
+impl Expanded {
+    /// The versions of the record and of each object inlined in it.
+    fn versions(&self) -> Vec<i32> {
+        let user = self.user.as_ref().map(|user| user.version);
+        let category = self.category.as_ref().map(|category| category.version);
+        [Some(self.record.version), user, category]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+impl From<Expanded> for Record {
+    fn from(value: Expanded) -> Self {
+        Self {
+            user: value.user.map(Into::into),
+            category: value.category.map(Into::into),
+            ..value.record.into()
         }
     }
 }
 
-#[derive(Deserialize)]
+/// The data of events about `record`, as streamed and posted to webhooks.
+pub(crate) fn event_data(record: &record::Model) -> serde_json::Value {
+    let data = RecordBody {
+        record: Record::from(record.clone()),
+    };
+    serde_json::to_value(data).expect("records serialize to JSON")
+}
+
+/// The event logging that `record` underwent `kind` of change.
+fn event(kind: record_event::Kind, record: &record::Model) -> record_event::ActiveModel {
+    record_event::ActiveModel {
+        kind: Set(kind),
+        record_id: Set(record.id),
+        user_id: Set(record.user_id),
+        category_id: Set(record.category_id),
+        household_id: Set(record.household_id),
+        data: Set(event_data(record)),
+        ..Default::default()
+    }
+}
+
+/// Deletes the records matching `condition` along with the user, category or
+/// household they belong to, which must be locked so that none are added
+/// meanwhile.
+pub(crate) async fn delete_where<C>(
+    db: &C,
+    condition: impl IntoCondition,
+) -> Result<Vec<record::Model>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    record::Entity::delete_many()
+        .filter(condition)
+        .exec_with_returning(db)
+        .await
+}
+
+/// Logs and publishes the deletion of `records` deleted along with what they
+/// belong to, once that's deleted too.
+pub(crate) async fn log_deleted<C>(db: &C, records: &[record::Model]) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    let deleted = records
+        .iter()
+        .map(|record| event(record_event::Kind::Deleted, record))
+        .collect();
+    events::log(db, deleted).await?;
+    outbox::publish(db, records.iter().cloned().map(DomainEvent::RecordDeleted)).await
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct RecordCreate {
-    user_id: Uuid,
-    category_id: Uuid,
-    sum: Decimal,
+    pub(crate) user_id: Uuid,
+    pub(crate) category_id: Uuid,
+    pub(crate) sum: Decimal,
+    /// Adds the record to the household's ledger, `user_id` must be one of its members.
+    #[serde(default)]
+    pub(crate) household_id: Option<Uuid>,
 }
 
 impl RecordCreate {
     async fn validate(&self, db: &DatabaseConnection) -> Result<(), AppError> {
-        let mut errors = Vec::new();
-
-        let (user, category) = try_join!(
+        let (user, category, member) = try_join!(
             user::Entity::find_by_id(self.user_id).one(db),
-            category::Entity::find_by_id(self.category_id).one(db)
+            category::Entity::find_by_id(self.category_id).one(db),
+            async {
+                match self.household_id {
+                    Some(household_id) => {
+                        household_member::Entity::find_by_id((household_id, self.user_id))
+                            .one(db)
+                            .await
+                    }
+                    None => Ok(None),
+                }
+            }
         )?;
 
-        if user.is_none() {
-            errors.push(("user_id", "user doesn't exist"));
+        let errors = self.check(
+            user.is_some(),
+            category.map(|category| category.household_id),
+            member.is_some(),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::unprocessable_entity(errors))
+        }
+    }
+
+    /// `category` is the household of the category if it exists, `is_member` whether the
+    /// user belongs to the record's household.
+    fn check(
+        &self,
+        user_exists: bool,
+        category: Option<Option<Uuid>>,
+        is_member: bool,
+    ) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if !user_exists {
+            errors.push(FieldError::new(
+                "user_id",
+                "not_found",
+                "user doesn't exist",
+            ));
+        } else if self.household_id.is_some() && !is_member {
+            errors.push(FieldError::new(
+                "user_id",
+                "not_member",
+                "user isn't a member of the household",
+            ));
         }
 
-        if category.is_none() {
-            errors.push(("category_id", "category doesn't exist"));
+        errors.extend(check_category(category, self.household_id));
+
+        if self.sum <= Decimal::ZERO {
+            errors.push(
+                FieldError::new("sum", "not_positive", "sum is not positive").param("min", 0),
+            );
         }
 
+        errors
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RecordUpdate {
+    pub(crate) category_id: Uuid,
+    pub(crate) sum: Decimal,
+}
+
+impl RecordUpdate {
+    async fn validate(
+        &self,
+        db: &DatabaseConnection,
+        household_id: Option<Uuid>,
+    ) -> Result<(), AppError> {
+        let category = category::Entity::find_by_id(self.category_id)
+            .one(db)
+            .await?;
+
+        let mut errors: Vec<_> =
+            check_category(category.map(|category| category.household_id), household_id)
+                .into_iter()
+                .collect();
+
         if self.sum <= Decimal::ZERO {
-            errors.push(("sum", "sum is not positive"));
+            errors.push(
+                FieldError::new("sum", "not_positive", "sum is not positive").param("min", 0),
+            );
         }
 
         if errors.is_empty() {
@@ -89,61 +358,384 @@ impl RecordCreate {
     }
 }
 
-#[derive(Deserialize)]
+/// Records may only use global categories and those of their own household.
+fn check_category(
+    category: Option<Option<Uuid>>,
+    household_id: Option<Uuid>,
+) -> Option<FieldError> {
+    match category {
+        None => Some(FieldError::new(
+            "category_id",
+            "not_found",
+            "category doesn't exist",
+        )),
+        Some(Some(category_household_id)) if Some(category_household_id) != household_id => {
+            Some(FieldError::new(
+                "category_id",
+                "other_household",
+                "category belongs to another household",
+            ))
+        }
+        Some(_) => None,
+    }
+}
+
+#[derive(Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct RecordFilterParams {
-    user_id: Option<Uuid>,
-    category_id: Option<Uuid>,
+    pub(crate) user_id: Option<Uuid>,
+    pub(crate) category_id: Option<Uuid>,
+    /// Only records in the household's ledger, by any of its members.
+    pub(crate) household_id: Option<Uuid>,
 }
 
-async fn get_record(
-    State(AppState { db }): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<RecordBody<Record>>, AppError> {
+impl RecordFilterParams {
+    /// The records matching the filters that the caller may see.
+    ///
+    /// Members see their own records, and those of households they belong to when
+    /// filtering by household.
+    pub(crate) async fn scope(
+        &self,
+        db: &DatabaseConnection,
+        caller: Caller,
+    ) -> Result<Select<record::Entity>, AppError> {
+        let user_id = self.user_id(db, caller).await?;
+        let mut query = record::Entity::find();
+        if let Some(user_id) = user_id {
+            query = query.filter(record::Column::UserId.eq(user_id));
+        }
+        if let Some(category_id) = self.category_id {
+            query = query.filter(record::Column::CategoryId.eq(category_id));
+        }
+        if let Some(household_id) = self.household_id {
+            query = query.filter(record::Column::HouseholdId.eq(household_id));
+        }
+        Ok(query)
+    }
+
+    /// The user whose records the caller may see, if restricted to one.
+    async fn user_id(
+        &self,
+        db: &DatabaseConnection,
+        caller: Caller,
+    ) -> Result<Option<Uuid>, AppError> {
+        match (self.household_id, caller.is_admin()) {
+            (Some(household_id), _) => {
+                caller
+                    .require_household(db, household_id, household_member::Role::Viewer)
+                    .await?;
+                Ok(self.user_id)
+            }
+            (None, true) => Ok(self.user_id),
+            (None, false) => {
+                let user_id = self.user_id.unwrap_or(caller.id);
+                caller.require_user(user_id)?;
+                Ok(Some(user_id))
+            }
+        }
+    }
+}
+
+/// Checks that the caller may access `record`: admins and the record's user always may,
+/// members of its household need at least the `min` role.
+///
+/// Household records stay in the household's ledger when their user leaves it, so
+/// the user may still read them but only change them while a member.
+async fn authorize(
+    db: &DatabaseConnection,
+    caller: Caller,
+    record: &record::Model,
+    min: household_member::Role,
+) -> Result<(), AppError> {
+    if caller.is_admin() {
+        return Ok(());
+    }
+    let own = caller.id == record.user_id;
+    match record.household_id {
+        Some(_) if own && min == household_member::Role::Viewer => Ok(()),
+        Some(household_id) if own => {
+            caller
+                .require_household(db, household_id, household_member::Role::Viewer)
+                .await
+        }
+        Some(household_id) => caller.require_household(db, household_id, min).await,
+        None if own => Ok(()),
+        None => Err(AppError::Forbidden),
+    }
+}
+
+/// Finds the record with `id`, if the caller may access it with at least the `min` role.
+pub(crate) async fn find(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    min: household_member::Role,
+) -> Result<record::Model, AppError> {
     let record = record::Entity::find_by_id(id)
-        .one(&db)
+        .one(db)
         .await?
-        .ok_or(AppError::NotFound)?
-        .into();
-    Ok(Json(RecordBody { record }))
+        .ok_or(AppError::NotFound)?;
+    authorize(db, caller, &record, min).await?;
+    Ok(record)
 }
 
-async fn create_record(
-    State(AppState { db }): State<AppState>,
-    Json(body): Json<RecordBody<RecordCreate>>,
-) -> Result<(StatusCode, Json<RecordBody<Record>>), AppError> {
-    body.record.validate(&db).await?;
+pub(crate) async fn create(
+    db: &DatabaseConnection,
+    caller: Caller,
+    new: RecordCreate,
+) -> Result<record::Model, AppError> {
+    match new.household_id {
+        Some(household_id) => {
+            caller
+                .require_household(db, household_id, household_member::Role::Editor)
+                .await?
+        }
+        None => caller.require_user(new.user_id)?,
+    }
+    new.validate(db).await?;
     let record = record::ActiveModel {
-        user_id: Set(body.record.user_id),
-        category_id: Set(body.record.category_id),
-        sum: Set(body.record.sum),
+        user_id: Set(new.user_id),
+        category_id: Set(new.category_id),
+        sum: Set(new.sum),
+        household_id: Set(new.household_id),
         ..Default::default()
     };
-    let record = record.insert(&db).await?.into();
-    Ok((StatusCode::CREATED, Json(RecordBody { record })))
+    let txn = db.begin().await?;
+    let record = record.insert(&txn).await?;
+    events::log(&txn, vec![event(record_event::Kind::Created, &record)]).await?;
+    outbox::publish(&txn, [DomainEvent::RecordCreated(record.clone())]).await?;
+    txn.commit().await?;
+    metrics::count_created_records(1);
+    Ok(record)
 }
 
+pub(crate) async fn update(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+    changes: RecordUpdate,
+) -> Result<record::Model, AppError> {
+    let record = find(db, caller, id, household_member::Role::Editor).await?;
+    changes.validate(db, record.household_id).await?;
+    let txn = db.begin().await?;
+    let Some(record) = record::Entity::update_many()
+        .col_expr(record::Column::CategoryId, Expr::value(changes.category_id))
+        .col_expr(record::Column::Sum, Expr::value(changes.sum))
+        .col_expr(
+            record::Column::Version,
+            Expr::col(record::Column::Version).add(1),
+        )
+        .filter(record::Column::Id.eq(id))
+        .filter(if_match.condition(record::Column::Version))
+        .exec_with_returning(&txn)
+        .await?
+        .pop()
+    else {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<record::Entity>(db, id).await);
+    };
+    shares::rescale(&txn, id, record.sum).await?;
+    events::log(&txn, vec![event(record_event::Kind::Updated, &record)]).await?;
+    outbox::publish(&txn, [DomainEvent::RecordUpdated(record.clone())]).await?;
+    txn.commit().await?;
+    Ok(record)
+}
+
+pub(crate) async fn delete(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<(), AppError> {
+    find(db, caller, id, household_member::Role::Editor).await?;
+    let txn = db.begin().await?;
+    let Some(record) = record::Entity::delete_many()
+        .filter(record::Column::Id.eq(id))
+        .filter(if_match.condition(record::Column::Version))
+        .exec_with_returning(&txn)
+        .await?
+        .pop()
+    else {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<record::Entity>(db, id).await);
+    };
+    events::log(&txn, vec![event(record_event::Kind::Deleted, &record)]).await?;
+    outbox::publish(&txn, [DomainEvent::RecordDeleted(record)]).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// An expanded record's `ETag` is weak and covers the versions of the objects
+/// inlined too, so it changes whenever any of them does.
+#[utoipa::path(
+    get,
+    path = "/{record_id}",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        ExpandParams,
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The record",
+            body = RecordBody<Record>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (
+            status = NOT_MODIFIED,
+            description = "The cached record is current",
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_record(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    Query(ExpandParams { expand }): Query<ExpandParams>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    let record = find(&db, caller, id, household_member::Role::Viewer).await?;
+    let version = record.version;
+    if expand.any() {
+        let expanded = expand.load(&db, vec![record]).await?.remove(0);
+        let versions = expanded.versions();
+        let record = Record::from(expanded);
+        return Ok(if_none_match.respond_weak(&versions, Json(RecordBody { record })));
+    }
+    let record = Record::from(record);
+    Ok(if_none_match.respond(version, Json(RecordBody { record })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "records",
+    request_body = RecordBody<RecordCreate>,
+    responses(
+        (
+            status = CREATED,
+            description = "The created record",
+            body = RecordBody<Record>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_record(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<RecordBody<RecordCreate>>,
+) -> Result<Response, AppError> {
+    let record = create(&db, caller, body.record).await?;
+    let version = record.version;
+    let record = Record::from(record);
+    Ok(precondition::tagged(
+        version,
+        (StatusCode::CREATED, Json(RecordBody { record })),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{record_id}",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        IfMatch,
+    ),
+    request_body = RecordBody<RecordUpdate>,
+    responses(
+        (
+            status = OK,
+            description = "The updated record",
+            body = RecordBody<Record>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_record(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<RecordBody<RecordUpdate>>,
+) -> Result<Response, AppError> {
+    let record = update(&db, caller, id, &if_match, body.record).await?;
+    let version = record.version;
+    let record = Record::from(record);
+    Ok(precondition::tagged(version, Json(RecordBody { record })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{record_id}",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        IfMatch,
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The record was deleted"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 async fn delete_record(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
     Path(id): Path<Uuid>,
+    if_match: IfMatch,
 ) -> Result<StatusCode, AppError> {
-    let res = record::Entity::delete_by_id(id).exec(&db).await?;
-    match res.rows_affected {
-        0 => Err(AppError::NotFound),
-        _ => Ok(StatusCode::NO_CONTENT),
-    }
+    delete(&db, caller, id, &if_match).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "records",
+    params(RecordFilterParams, ExpandParams),
+    responses(
+        (status = OK, description = "All records", body = RecordsBody<Record>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 async fn get_records(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
     Query(params): Query<RecordFilterParams>,
+    Query(ExpandParams { expand }): Query<ExpandParams>,
 ) -> Result<Json<RecordsBody<Record>>, AppError> {
-    let mut query = record::Entity::find();
-    if let Some(user_id) = params.user_id {
-        query = query.filter(record::Column::UserId.eq(user_id));
-    }
-    if let Some(category_id) = params.category_id {
-        query = query.filter(record::Column::CategoryId.eq(category_id));
-    }
-    let records = query.all(&db).await?.into_iter().map(Into::into).collect();
+    let records = params.scope(&db, caller).await?.all(&db).await?;
+    let records = expand.records(&db, records).await?;
     Ok(Json(RecordsBody { records }))
 }
@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{extract::State, http::StatusCode};
+use entity::{category, household_member, record, record_event, user};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use tokio::try_join;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::{Record, RecordCreate, event};
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    events,
+    extract::Json,
+    metrics,
+    outbox::{self, DomainEvent},
+};
+
+const MAX_BATCH_SIZE: usize = 1000;
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(create_records))
+        .routes(routes!(delete_records))
+}
+
+/// How a batch reacts to items that can't be processed.
+#[derive(Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum BulkMode {
+    /// Nothing is applied unless every item succeeds.
+    #[default]
+    AllOrNothing,
+    /// Valid items are applied, invalid ones are reported.
+    BestEffort,
+}
+
+impl BulkMode {
+    fn success_status(self, status: StatusCode) -> StatusCode {
+        match self {
+            Self::AllOrNothing => status,
+            Self::BestEffort => StatusCode::MULTI_STATUS,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RecordsCreate {
+    #[serde(default)]
+    mode: BulkMode,
+    records: Vec<RecordCreate>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RecordsDelete {
+    #[serde(default)]
+    mode: BulkMode,
+    records: Vec<RecordVersion>,
+}
+
+/// A record to delete, as long as it's still at `version`.
+#[derive(Deserialize, ToSchema)]
+struct RecordVersion {
+    id: Uuid,
+    version: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BulkBody<T> {
+    results: Vec<ItemResult<T>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ItemResult<T> {
+    index: usize,
+    status: u16,
+    #[serde(flatten)]
+    outcome: Outcome<T>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Outcome<T> {
+    Record(T),
+    Id(Uuid),
+    Errors(Vec<FieldError>),
+}
+
+/// Why the caller may not create `record`, reported in its result in best-effort
+/// mode.
+fn forbidden(record: &RecordCreate) -> FieldError {
+    match record.household_id {
+        Some(_) => FieldError::new(
+            "household_id",
+            "forbidden",
+            "caller can't add records to the household",
+        ),
+        None => FieldError::new(
+            "user_id",
+            "forbidden",
+            "caller can't add records for the user",
+        ),
+    }
+}
+
+fn validate_size(field: &'static str, len: usize) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if len == 0 {
+        errors.push(FieldError::new(field, "empty", "batch is empty"));
+    }
+
+    if len > MAX_BATCH_SIZE {
+        errors.push(
+            FieldError::new(field, "too_many_items", "batch is too large")
+                .param("max_items", MAX_BATCH_SIZE),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::unprocessable_entity(errors))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "records",
+    request_body = RecordsCreate,
+    responses(
+        (status = CREATED, description = "All records were created", body = BulkBody<Record>),
+        (status = MULTI_STATUS, description = "Per-record results", body = BulkBody<Record>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (
+            status = UNPROCESSABLE_ENTITY,
+            description = "The batch or some of its records are invalid",
+            content(
+                (BulkBody<Record> = "application/json"),
+                (Problem = "application/problem+json"),
+            )
+        ),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_records(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<RecordsCreate>,
+) -> Result<(StatusCode, Json<BulkBody<Record>>), AppError> {
+    validate_size("records", body.records.len())?;
+    let memberships = caller.memberships(&db).await?;
+    let authorize = |record: &RecordCreate| match record.household_id {
+        Some(household_id) => memberships.require(household_id, household_member::Role::Editor),
+        None => caller.require_user(record.user_id),
+    };
+    if body.mode == BulkMode::AllOrNothing {
+        body.records.iter().try_for_each(authorize)?;
+    }
+
+    let user_ids: HashSet<Uuid> = body.records.iter().map(|r| r.user_id).collect();
+    let category_ids: HashSet<Uuid> = body.records.iter().map(|r| r.category_id).collect();
+    let household_ids: HashSet<Uuid> = body.records.iter().filter_map(|r| r.household_id).collect();
+    let (users, categories, members) = try_join!(
+        user::Entity::find()
+            .select_only()
+            .column(user::Column::Id)
+            .filter(user::Column::Id.is_in(user_ids))
+            .into_tuple::<Uuid>()
+            .all(&db),
+        category::Entity::find()
+            .select_only()
+            .columns([category::Column::Id, category::Column::HouseholdId])
+            .filter(category::Column::Id.is_in(category_ids))
+            .into_tuple::<(Uuid, Option<Uuid>)>()
+            .all(&db),
+        household_member::Entity::find()
+            .select_only()
+            .columns([
+                household_member::Column::HouseholdId,
+                household_member::Column::UserId,
+            ])
+            .filter(household_member::Column::HouseholdId.is_in(household_ids))
+            .into_tuple::<(Uuid, Uuid)>()
+            .all(&db)
+    )?;
+    let users: HashSet<Uuid> = users.into_iter().collect();
+    let categories: HashMap<Uuid, Option<Uuid>> = categories.into_iter().collect();
+    let members: HashSet<(Uuid, Uuid)> = members.into_iter().collect();
+
+    let mut results = Vec::new();
+    let mut pending = Vec::new();
+    for (index, record) in body.records.into_iter().enumerate() {
+        if authorize(&record).is_err() {
+            results.push(ItemResult {
+                index,
+                status: StatusCode::FORBIDDEN.as_u16(),
+                outcome: Outcome::Errors(vec![forbidden(&record)]),
+            });
+            continue;
+        }
+        let errors = record.check(
+            users.contains(&record.user_id),
+            categories.get(&record.category_id).copied(),
+            record
+                .household_id
+                .is_some_and(|household_id| members.contains(&(household_id, record.user_id))),
+        );
+        if errors.is_empty() {
+            pending.push((index, record));
+        } else {
+            results.push(ItemResult {
+                index,
+                status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                outcome: Outcome::Errors(errors),
+            });
+        }
+    }
+
+    if body.mode == BulkMode::AllOrNothing && !results.is_empty() {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(BulkBody { results })));
+    }
+
+    if !pending.is_empty() {
+        let (indices, models): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .map(|(index, record)| {
+                let model = record::ActiveModel {
+                    user_id: Set(record.user_id),
+                    category_id: Set(record.category_id),
+                    sum: Set(record.sum),
+                    household_id: Set(record.household_id),
+                    ..Default::default()
+                };
+                ((index, *model.id.as_ref()), model)
+            })
+            .unzip();
+        let txn = db.begin().await?;
+        let inserted = record::Entity::insert_many(models)
+            .exec_with_returning_many(&txn)
+            .await?;
+        let created = inserted
+            .iter()
+            .map(|record| event(record_event::Kind::Created, record))
+            .collect();
+        events::log(&txn, created).await?;
+        outbox::publish(
+            &txn,
+            inserted.iter().cloned().map(DomainEvent::RecordCreated),
+        )
+        .await?;
+        txn.commit().await?;
+        metrics::count_created_records(inserted.len());
+        let mut inserted: HashMap<Uuid, record::Model> = inserted
+            .into_iter()
+            .map(|record| (record.id, record))
+            .collect();
+        results.extend(indices.into_iter().filter_map(|(index, id)| {
+            inserted.remove(&id).map(|record| ItemResult {
+                index,
+                status: StatusCode::CREATED.as_u16(),
+                outcome: Outcome::Record(record.into()),
+            })
+        }));
+        results.sort_by_key(|result| result.index);
+    }
+
+    let status = body.mode.success_status(StatusCode::CREATED);
+    Ok((status, Json(BulkBody { results })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/delete",
+    tag = "records",
+    request_body = RecordsDelete,
+    responses(
+        (status = OK, description = "All records were deleted", body = BulkBody<Record>),
+        (status = MULTI_STATUS, description = "Per-record results", body = BulkBody<Record>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (
+            status = UNPROCESSABLE_ENTITY,
+            description = "The batch is invalid, or some of its records don't exist or changed",
+            content(
+                (BulkBody<Record> = "application/json"),
+                (Problem = "application/problem+json"),
+            )
+        ),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_records(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<RecordsDelete>,
+) -> Result<(StatusCode, Json<BulkBody<Record>>), AppError> {
+    validate_size("records", body.records.len())?;
+    let memberships = caller.memberships(&db).await?;
+
+    let txn = db.begin().await?;
+    let existing: HashMap<Uuid, (Uuid, Option<Uuid>, i32)> = record::Entity::find()
+        .select_only()
+        .columns([
+            record::Column::Id,
+            record::Column::UserId,
+            record::Column::HouseholdId,
+            record::Column::Version,
+        ])
+        .filter(record::Column::Id.is_in(body.records.iter().map(|record| record.id)))
+        .lock_exclusive()
+        .into_tuple::<(Uuid, Uuid, Option<Uuid>, i32)>()
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|(id, user_id, household_id, version)| (id, (user_id, household_id, version)))
+        .collect();
+
+    let mut results = Vec::new();
+    let mut deletable = HashSet::new();
+    for (index, item) in body.records.iter().enumerate() {
+        let status = match existing.get(&item.id) {
+            None => StatusCode::NOT_FOUND,
+            Some((user_id, household_id, version)) => {
+                // Like deleting one record, users only delete their household
+                // records while they're members.
+                let allowed = match household_id {
+                    Some(household_id) => {
+                        let min = match *user_id == caller.id {
+                            true => household_member::Role::Viewer,
+                            false => household_member::Role::Editor,
+                        };
+                        memberships.require(*household_id, min).is_ok()
+                    }
+                    None => caller.require_user(*user_id).is_ok(),
+                };
+                if !allowed && body.mode == BulkMode::AllOrNothing {
+                    return Err(AppError::Forbidden);
+                }
+                if !allowed {
+                    StatusCode::FORBIDDEN
+                } else if *version != item.version {
+                    StatusCode::PRECONDITION_FAILED
+                } else {
+                    deletable.insert(item.id);
+                    StatusCode::NO_CONTENT
+                }
+            }
+        };
+        results.push(ItemResult {
+            index,
+            status: status.as_u16(),
+            outcome: Outcome::Id(item.id),
+        });
+    }
+
+    let deleted = StatusCode::NO_CONTENT.as_u16();
+    if body.mode == BulkMode::AllOrNothing && results.iter().any(|result| result.status != deleted)
+    {
+        results.retain(|result| result.status != deleted);
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(BulkBody { results })));
+    }
+
+    if !deletable.is_empty() {
+        let deleted = record::Entity::delete_many()
+            .filter(record::Column::Id.is_in(deletable))
+            .exec_with_returning(&txn)
+            .await?;
+        let events = deleted
+            .iter()
+            .map(|record| event(record_event::Kind::Deleted, record))
+            .collect();
+        events::log(&txn, events).await?;
+        outbox::publish(&txn, deleted.into_iter().map(DomainEvent::RecordDeleted)).await?;
+    }
+    txn.commit().await?;
+
+    let status = body.mode.success_status(StatusCode::OK);
+    Ok((status, Json(BulkBody { results })))
+}
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use axum::{extract::State, http::StatusCode, response::Response};
+use entity::{household_member, record, record_event, record_share};
+use rust_decimal::Decimal;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::{event, find};
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    events,
+    extract::{Json, Path},
+    ledger,
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+};
+
+const MAX_SHARES: usize = 100;
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(get_shares, update_shares, delete_shares))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SplitBody {
+    split: Split,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SharesBody {
+    shares: Vec<Share>,
+}
+
+/// The part of a record's sum a user owes to the user who paid it.
+#[derive(Serialize, Deserialize, ToSchema)]
+struct Share {
+    user_id: Uuid,
+    sum: Decimal,
+}
+
+impl From<record_share::Model> for Share {
+    fn from(value: record_share::Model) -> Self {
+        Self {
+            user_id: value.user_id,
+            sum: value.sum,
+        }
+    }
+}
+
+/// How a record's sum is divided between users.
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Split {
+    /// Everyone pays the same, give or take a cent.
+    Equal { user_ids: Vec<Uuid> },
+    /// Everyone pays a percentage of the sum, adding up to 100.
+    Percentage { shares: Vec<PercentageShare> },
+    /// Everyone pays an exact amount, adding up to the sum.
+    Exact { shares: Vec<Share> },
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PercentageShare {
+    user_id: Uuid,
+    percentage: Decimal,
+}
+
+impl Split {
+    /// Each user with the weight of their share.
+    fn weights(&self) -> Vec<(Uuid, Decimal)> {
+        match self {
+            Self::Equal { user_ids } => user_ids.iter().map(|id| (*id, Decimal::ONE)).collect(),
+            Self::Percentage { shares } => shares
+                .iter()
+                .map(|share| (share.user_id, share.percentage))
+                .collect(),
+            Self::Exact { shares } => shares
+                .iter()
+                .map(|share| (share.user_id, share.sum))
+                .collect(),
+        }
+    }
+
+    /// Checks the split against `record`, returning each user's share of its sum.
+    ///
+    /// Only household records can be split, between members of the household, so
+    /// no one can put a debt on a user who can't see the record behind it.
+    async fn validate(
+        &self,
+        db: &DatabaseConnection,
+        record: &record::Model,
+    ) -> Result<Vec<(Uuid, Decimal)>, AppError> {
+        let weights = self.weights();
+        let user_ids: HashSet<Uuid> = weights.iter().map(|(id, _)| *id).collect();
+        let mut errors = Vec::new();
+
+        if weights.is_empty() {
+            errors.push(FieldError::new("split", "empty", "split has no shares"));
+        }
+
+        if weights.len() > MAX_SHARES {
+            errors.push(
+                FieldError::new("split", "too_many_items", "split has too many shares")
+                    .param("max_items", MAX_SHARES),
+            );
+        }
+
+        if user_ids.len() < weights.len() {
+            errors.push(FieldError::new(
+                "split",
+                "duplicate_user",
+                "a user has more than one share",
+            ));
+        }
+
+        if weights.iter().any(|(_, weight)| *weight <= Decimal::ZERO) {
+            errors.push(
+                FieldError::new("split", "not_positive", "a share is not positive").param("min", 0),
+            );
+        }
+
+        let total: Decimal = weights.iter().map(|(_, weight)| weight).sum();
+        let expected = match self {
+            Self::Equal { .. } => None,
+            Self::Percentage { .. } => Some(Decimal::ONE_HUNDRED),
+            Self::Exact { .. } => Some(record.sum),
+        };
+        if let Some(expected) = expected.filter(|expected| *expected != total) {
+            errors.push(
+                FieldError::new("split", "total_mismatch", "shares don't add up")
+                    .param("total", expected.to_string()),
+            );
+        }
+
+        match record.household_id {
+            Some(household_id) => {
+                let members = household_member::Entity::find()
+                    .select_only()
+                    .column(household_member::Column::UserId)
+                    .filter(household_member::Column::HouseholdId.eq(household_id))
+                    .filter(household_member::Column::UserId.is_in(user_ids.iter().copied()))
+                    .into_tuple::<Uuid>()
+                    .all(db)
+                    .await?;
+                if members.len() < user_ids.len() {
+                    errors.push(FieldError::new(
+                        "split",
+                        "not_member",
+                        "a user isn't a member of the household",
+                    ));
+                }
+            }
+            None => errors.push(FieldError::new(
+                "split",
+                "personal_record",
+                "only household records can be split",
+            )),
+        }
+
+        if !errors.is_empty() {
+            return Err(AppError::unprocessable_entity(errors));
+        }
+
+        let sums = match self {
+            Self::Exact { .. } => weights.iter().map(|(_, sum)| *sum).collect(),
+            _ => {
+                let weights: Vec<_> = weights.iter().map(|(_, weight)| *weight).collect();
+                ledger::split(record.sum, &weights)
+            }
+        };
+        Ok(weights
+            .into_iter()
+            .map(|(user_id, _)| user_id)
+            .zip(sums)
+            .collect())
+    }
+}
+
+/// Keeps the record's shares in proportion when its sum changes to `sum`.
+pub(super) async fn rescale(
+    db: &impl ConnectionTrait,
+    record_id: Uuid,
+    sum: Decimal,
+) -> Result<(), AppError> {
+    let shares = record_share::Entity::find()
+        .filter(record_share::Column::RecordId.eq(record_id))
+        .order_by_asc(record_share::Column::UserId)
+        .all(db)
+        .await?;
+    if shares.is_empty() {
+        return Ok(());
+    }
+    let weights: Vec<_> = shares.iter().map(|share| share.sum).collect();
+    for (share, sum) in shares.into_iter().zip(ledger::split(sum, &weights)) {
+        record_share::Entity::update_many()
+            .col_expr(record_share::Column::Sum, Expr::value(sum))
+            .filter(record_share::Column::RecordId.eq(share.record_id))
+            .filter(record_share::Column::UserId.eq(share.user_id))
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Bumps the version of the record `id`, whose shares are changing, if it
+/// matches `if_match`.
+async fn bump_version(
+    db: &impl ConnectionTrait,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<Option<record::Model>, AppError> {
+    Ok(record::Entity::update_many()
+        .col_expr(
+            record::Column::Version,
+            Expr::col(record::Column::Version).add(1),
+        )
+        .filter(record::Column::Id.eq(id))
+        .filter(if_match.condition(record::Column::Version))
+        .exec_with_returning(db)
+        .await?
+        .pop())
+}
+
+/// Logs and publishes that `record`'s shares changed, along with its version.
+async fn log_updated(db: &impl ConnectionTrait, record: record::Model) -> Result<(), AppError> {
+    events::log(db, vec![event(record_event::Kind::Updated, &record)]).await?;
+    outbox::publish(db, [DomainEvent::RecordUpdated(record)]).await?;
+    Ok(())
+}
+
+async fn find_shares(db: &impl ConnectionTrait, id: Uuid) -> Result<Vec<Share>, AppError> {
+    Ok(record_share::Entity::find()
+        .filter(record_share::Column::RecordId.eq(id))
+        .order_by_asc(record_share::Column::UserId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/{record_id}/shares",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "How the record is shared, empty if it isn't",
+            body = SharesBody,
+            headers(("ETag" = String, description = "Entity tag of the record's current version"))
+        ),
+        (status = NOT_MODIFIED, description = "The record hasn't changed"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_shares(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    let record = find(&db, caller, id, household_member::Role::Viewer).await?;
+    let shares = find_shares(&db, id).await?;
+    Ok(if_none_match.respond(record.version, Json(SharesBody { shares })))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{record_id}/shares",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        IfMatch,
+    ),
+    request_body = SplitBody,
+    responses(
+        (
+            status = OK,
+            description = "The record's new shares",
+            body = SharesBody,
+            headers(("ETag" = String, description = "Entity tag of the record's new version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_shares(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<SplitBody>,
+) -> Result<Response, AppError> {
+    let record = find(&db, caller, id, household_member::Role::Editor).await?;
+    let sums = body.split.validate(&db, &record).await?;
+
+    let txn = db.begin().await?;
+    let Some(record) = bump_version(&txn, id, &if_match).await? else {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<record::Entity>(&db, id).await);
+    };
+    record_share::Entity::delete_many()
+        .filter(record_share::Column::RecordId.eq(id))
+        .exec(&txn)
+        .await?;
+    record_share::Entity::insert_many(sums.into_iter().map(|(user_id, sum)| {
+        record_share::ActiveModel {
+            record_id: Set(id),
+            user_id: Set(user_id),
+            sum: Set(sum),
+        }
+    }))
+    .exec(&txn)
+    .await?;
+    let shares = find_shares(&txn, id).await?;
+    let version = record.version;
+    log_updated(&txn, record).await?;
+    txn.commit().await?;
+    Ok(precondition::tagged(version, Json(SharesBody { shares })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{record_id}/shares",
+    tag = "records",
+    params(
+        ("record_id" = Uuid, Path, description = "Record ID"),
+        IfMatch,
+    ),
+    responses(
+        (
+            status = NO_CONTENT,
+            description = "The record is no longer shared",
+            headers(("ETag" = String, description = "Entity tag of the record's new version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_shares(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> Result<Response, AppError> {
+    find(&db, caller, id, household_member::Role::Editor).await?;
+    let txn = db.begin().await?;
+    let Some(record) = bump_version(&txn, id, &if_match).await? else {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<record::Entity>(&db, id).await);
+    };
+    record_share::Entity::delete_many()
+        .filter(record_share::Column::RecordId.eq(id))
+        .exec(&txn)
+        .await?;
+    let version = record.version;
+    log_updated(&txn, record).await?;
+    txn.commit().await?;
+    Ok(precondition::tagged(version, StatusCode::NO_CONTENT))
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use entity::{household_member, record_event, user};
+use futures_util::{Stream, stream};
+use sea_orm::{ActiveEnum, ColumnTrait, Condition, DatabaseConnection, EntityTrait};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::{Record, RecordBody, RecordFilterParams};
+use crate::{
+    AppState,
+    auth::{Caller, Memberships},
+    error::{AppError, FieldError, Problem},
+    events,
+    extract::Query,
+};
+
+/// How long the caller's role and households are relied on before they're
+/// loaded again, so that events stop soon after the caller's access does.
+const ACCESS_TTL: Duration = Duration::from_secs(1);
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(stream_records))
+}
+
+/// The records the caller may see, as of when it was loaded.
+struct Access {
+    caller: Caller,
+    memberships: Memberships,
+    loaded: Instant,
+}
+
+impl Access {
+    /// Loads the caller's current role and households, `None` if they've been deleted.
+    async fn load(db: &DatabaseConnection, id: Uuid) -> Result<Option<Self>, AppError> {
+        let Some(user) = user::Entity::find_by_id(id).one(db).await? else {
+            return Ok(None);
+        };
+        let caller = Caller {
+            id,
+            role: user.role.into(),
+        };
+        Ok(Some(Self {
+            memberships: caller.memberships(db).await?,
+            caller,
+            loaded: Instant::now(),
+        }))
+    }
+
+    fn allows(&self, event: &record_event::Model) -> bool {
+        self.caller.is_admin()
+            || self.caller.id == event.user_id
+            || event.household_id.is_some_and(|id| {
+                self.memberships
+                    .require(id, household_member::Role::Viewer)
+                    .is_ok()
+            })
+    }
+}
+
+/// The records a stream is about.
+struct Filter {
+    user_id: Option<Uuid>,
+    category_id: Option<Uuid>,
+    household_id: Option<Uuid>,
+}
+
+impl Filter {
+    fn matches(&self, event: &record_event::Model) -> bool {
+        self.user_id.is_none_or(|id| id == event.user_id)
+            && self.category_id.is_none_or(|id| id == event.category_id)
+            && self
+                .household_id
+                .is_none_or(|id| Some(id) == event.household_id)
+    }
+
+    fn condition(&self) -> Condition {
+        Condition::all()
+            .add_option(self.user_id.map(|id| record_event::Column::UserId.eq(id)))
+            .add_option(
+                self.category_id
+                    .map(|id| record_event::Column::CategoryId.eq(id)),
+            )
+            .add_option(
+                self.household_id
+                    .map(|id| record_event::Column::HouseholdId.eq(id)),
+            )
+    }
+}
+
+/// A client's stream: events from the table until it's caught up, then those
+/// relayed as they're committed.
+struct Subscription {
+    db: DatabaseConnection,
+    filter: Filter,
+    access: Access,
+    receiver: broadcast::Receiver<Arc<record_event::Model>>,
+    closed: CancellationToken,
+    /// The last event sent.
+    cursor: i64,
+    catching_up: bool,
+    pending: VecDeque<record_event::Model>,
+}
+
+impl Subscription {
+    async fn next(&mut self) -> Option<Arc<record_event::Model>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.cursor = event.id;
+                if self.allows(&event).await? {
+                    return Some(Arc::new(event));
+                }
+                continue;
+            }
+
+            if self.catching_up {
+                match events::since(&self.db, self.cursor, self.filter.condition()).await {
+                    Ok(events) if events.is_empty() => self.catching_up = false,
+                    Ok(events) => self.pending.extend(events),
+                    Err(e) => {
+                        // The client resumes from the last event once it reconnects.
+                        tracing::warn!(error = %e, "failed to read record events");
+                        return None;
+                    }
+                }
+                continue;
+            }
+
+            let received = tokio::select! {
+                () = self.closed.cancelled() => return None,
+                received = self.receiver.recv() => received,
+            };
+            match received {
+                // Events already sent from the table come through the relay too.
+                Ok(event) if event.id > self.cursor && self.filter.matches(&event) => {
+                    self.cursor = event.id;
+                    if self.allows(&event).await? {
+                        return Some(event);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => self.catching_up = true,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Whether the caller may still see `event`, or `None` to end the stream
+    /// once they may see nothing at all.
+    async fn allows(&mut self, event: &record_event::Model) -> Option<bool> {
+        if self.access.loaded.elapsed() >= ACCESS_TTL {
+            self.access = match Access::load(&self.db, self.access.caller.id).await {
+                Ok(access) => access?,
+                // The client resumes from the last event once it reconnects.
+                Err(AppError::Database(e)) => {
+                    tracing::warn!(error = %e, "failed to authorize record events");
+                    return None;
+                }
+                Err(_) => return None,
+            };
+        }
+        Some(self.access.allows(event))
+    }
+}
+
+/// Streams changes to the matching records as server-sent events.
+///
+/// Each event is named after the change, `record.created`, `record.updated` or
+/// `record.deleted`, and carries the record as it is afterwards, or was before
+/// being deleted. Clients reconnecting with `Last-Event-ID` first get the events
+/// they missed, for up to 7 days.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "records",
+    params(
+        RecordFilterParams,
+        ("Last-Event-ID" = Option<i64>, Header, description = "ID of the last event received, to resume after it"),
+    ),
+    responses(
+        (status = OK, description = "A stream of record changes", body = RecordBody<Record>, content_type = "text/event-stream"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn stream_records(
+    State(AppState { db, events, .. }): State<AppState>,
+    caller: Caller,
+    Query(params): Query<RecordFilterParams>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                AppError::bad_request([FieldError::new(
+                    "Last-Event-ID",
+                    "invalid",
+                    "last event ID isn't an event ID",
+                )])
+            })?,
+        None => None,
+    };
+    let filter = Filter {
+        user_id: params.user_id(&db, caller).await?,
+        category_id: params.category_id,
+        household_id: params.household_id,
+    };
+
+    // Whatever is committed after the cursor is read is caught up on from the table.
+    let cursor = match last_event_id {
+        Some(id) => id,
+        None => events::latest(&db).await?,
+    };
+    let access = Access::load(&db, caller.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let subscription = Subscription {
+        db,
+        filter,
+        access,
+        receiver: events.subscribe(),
+        closed: events.closed(),
+        cursor,
+        catching_up: true,
+        pending: VecDeque::new(),
+    };
+
+    let stream = stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+        let sse = Event::default()
+            .id(event.id.to_string())
+            .event(event.kind.to_value())
+            .json_data(&event.data)
+            .expect("events serialize to JSON");
+        Some((Ok(sse), subscription))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
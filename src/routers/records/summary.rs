@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::State;
+use entity::{category, record, user};
+use rust_decimal::Decimal;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+use serde::Serialize;
+use tokio::try_join;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use super::RecordFilterParams;
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, Problem},
+    extract::{Json, Query},
+};
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new().routes(routes!(get_summary))
+}
+
+#[derive(Serialize, ToSchema)]
+struct SummaryBody {
+    summary: Summary,
+}
+
+/// Spending totals of the matching records, overall and broken down.
+#[derive(Serialize, ToSchema)]
+struct Summary {
+    total: Decimal,
+    count: i64,
+    by_user: Vec<UserTotal>,
+    by_category: Vec<CategoryTotal>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UserTotal {
+    user_id: Uuid,
+    name: String,
+    total: Decimal,
+    count: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CategoryTotal {
+    category_id: Uuid,
+    name: String,
+    total: Decimal,
+    count: i64,
+}
+
+#[derive(Default)]
+struct Totals {
+    total: Decimal,
+    count: i64,
+}
+
+impl Totals {
+    fn add(&mut self, total: Decimal, count: i64) {
+        self.total += total;
+        self.count += count;
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "records",
+    params(RecordFilterParams),
+    responses(
+        (status = OK, description = "Totals of the records", body = SummaryBody),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_summary(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Query(params): Query<RecordFilterParams>,
+) -> Result<Json<SummaryBody>, AppError> {
+    let groups: Vec<(Uuid, Uuid, Decimal, i64)> = params
+        .scope(&db, caller)
+        .await?
+        .select_only()
+        .columns([record::Column::UserId, record::Column::CategoryId])
+        .column_as(record::Column::Sum.sum(), "total")
+        .column_as(record::Column::Id.count(), "count")
+        .group_by(record::Column::UserId)
+        .group_by(record::Column::CategoryId)
+        .into_tuple()
+        .all(&db)
+        .await?;
+
+    let mut summary = Totals::default();
+    let mut by_user: BTreeMap<Uuid, Totals> = BTreeMap::new();
+    let mut by_category: BTreeMap<Uuid, Totals> = BTreeMap::new();
+    for (user_id, category_id, total, count) in groups {
+        summary.add(total, count);
+        by_user.entry(user_id).or_default().add(total, count);
+        by_category
+            .entry(category_id)
+            .or_default()
+            .add(total, count);
+    }
+
+    let (users, categories) = try_join!(
+        user::Entity::find()
+            .filter(user::Column::Id.is_in(by_user.keys().copied()))
+            .all(&db),
+        category::Entity::find()
+            .filter(category::Column::Id.is_in(by_category.keys().copied()))
+            .all(&db)
+    )?;
+    let mut users: HashMap<Uuid, String> = users.into_iter().map(|u| (u.id, u.name)).collect();
+    let mut categories: HashMap<Uuid, String> =
+        categories.into_iter().map(|c| (c.id, c.name)).collect();
+
+    let by_user = by_user
+        .into_iter()
+        .map(|(user_id, totals)| UserTotal {
+            user_id,
+            name: users.remove(&user_id).unwrap_or_default(),
+            total: totals.total,
+            count: totals.count,
+        })
+        .collect();
+    let by_category = by_category
+        .into_iter()
+        .map(|(category_id, totals)| CategoryTotal {
+            category_id,
+            name: categories.remove(&category_id).unwrap_or_default(),
+            total: totals.total,
+            count: totals.count,
+        })
+        .collect();
+
+    Ok(Json(SummaryBody {
+        summary: Summary {
+            total: summary.total,
+            count: summary.count,
+            by_user,
+            by_category,
+        },
+    }))
+}
@@ -0,0 +1,347 @@
+use axum::{extract::State, http::StatusCode};
+use chrono::NaiveDateTime;
+use entity::{household_member, record, record_share, settlement, user};
+use rust_decimal::Decimal;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::{Deserialize, Serialize};
+use tokio::try_join;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    auth::Caller,
+    error::{AppError, FieldError, Problem},
+    extract::{Json, Path, Query},
+    ledger::{self, Debt},
+};
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_settlements, create_settlement))
+        .routes(routes!(delete_settlement))
+        .routes(routes!(get_balances))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct SettlementBody<T> {
+    settlement: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SettlementsBody<T> {
+    settlements: Vec<T>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BalancesBody {
+    /// What each user owes another, after netting their shares and settlements.
+    balances: Vec<Balance>,
+    /// Transfers that would settle all of the balances, at most one fewer than the
+    /// users involved.
+    transfers: Vec<Balance>,
+}
+
+/// A payment from one user to another, settling what they owed.
+#[derive(Debug, Serialize, ToSchema)]
+struct Settlement {
+    id: Uuid,
+    from_user_id: Uuid,
+    to_user_id: Uuid,
+    sum: Decimal,
+    household_id: Option<Uuid>,
+    created_at: NaiveDateTime,
+}
+
+impl From<settlement::Model> for Settlement {
+    fn from(value: settlement::Model) -> Self {
+        Self {
+            id: value.id,
+            from_user_id: value.from_user_id,
+            to_user_id: value.to_user_id,
+            sum: value.sum,
+            household_id: value.household_id,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// `sum` owed by the user `from_user_id` to `to_user_id`.
+#[derive(Debug, Serialize, ToSchema)]
+struct Balance {
+    from_user_id: Uuid,
+    to_user_id: Uuid,
+    sum: Decimal,
+}
+
+impl From<Debt> for Balance {
+    fn from(value: Debt) -> Self {
+        Self {
+            from_user_id: value.from,
+            to_user_id: value.to,
+            sum: value.sum,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SettlementCreate {
+    /// The user who paid.
+    from_user_id: Uuid,
+    /// The user who was paid, who must be the one recording the settlement.
+    to_user_id: Uuid,
+    sum: Decimal,
+    /// Settles debts within the household, both users must be its members.
+    #[serde(default)]
+    household_id: Option<Uuid>,
+}
+
+impl SettlementCreate {
+    async fn validate(&self, db: &DatabaseConnection) -> Result<(), AppError> {
+        let user_ids = [self.from_user_id, self.to_user_id];
+        let (users, members) = try_join!(
+            user::Entity::find()
+                .select_only()
+                .column(user::Column::Id)
+                .filter(user::Column::Id.is_in(user_ids))
+                .into_tuple::<Uuid>()
+                .all(db),
+            async {
+                match self.household_id {
+                    Some(household_id) => {
+                        household_member::Entity::find()
+                            .select_only()
+                            .column(household_member::Column::UserId)
+                            .filter(household_member::Column::HouseholdId.eq(household_id))
+                            .filter(household_member::Column::UserId.is_in(user_ids))
+                            .into_tuple::<Uuid>()
+                            .all(db)
+                            .await
+                    }
+                    None => Ok(Vec::new()),
+                }
+            }
+        )?;
+
+        let mut errors = Vec::new();
+
+        for (field, id) in [
+            ("from_user_id", self.from_user_id),
+            ("to_user_id", self.to_user_id),
+        ] {
+            if !users.contains(&id) {
+                errors.push(FieldError::new(field, "not_found", "user doesn't exist"));
+            } else if self.household_id.is_some() && !members.contains(&id) {
+                errors.push(FieldError::new(
+                    field,
+                    "not_member",
+                    "user isn't a member of the household",
+                ));
+            }
+        }
+
+        if self.from_user_id == self.to_user_id {
+            errors.push(FieldError::new(
+                "to_user_id",
+                "same_user",
+                "user can't pay themselves",
+            ));
+        }
+
+        if self.sum <= Decimal::ZERO {
+            errors.push(
+                FieldError::new("sum", "not_positive", "sum is not positive").param("min", 0),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::unprocessable_entity(errors))
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LedgerParams {
+    /// Only the household's shared records and settlements, rather than all of the caller's.
+    household_id: Option<Uuid>,
+}
+
+impl LedgerParams {
+    /// Restricts members to the household's ledger, or to what involves them.
+    async fn scope(
+        &self,
+        db: &DatabaseConnection,
+        caller: Caller,
+    ) -> Result<(Condition, Condition), AppError> {
+        let mut shares = Condition::all();
+        let mut settlements = Condition::all();
+        if let Some(household_id) = self.household_id {
+            caller
+                .require_household(db, household_id, household_member::Role::Viewer)
+                .await?;
+            shares = shares.add(record::Column::HouseholdId.eq(household_id));
+            settlements = settlements.add(settlement::Column::HouseholdId.eq(household_id));
+        } else if !caller.is_admin() {
+            shares = shares.add(
+                Condition::any()
+                    .add(record_share::Column::UserId.eq(caller.id))
+                    .add(record::Column::UserId.eq(caller.id)),
+            );
+            settlements = settlements.add(
+                Condition::any()
+                    .add(settlement::Column::FromUserId.eq(caller.id))
+                    .add(settlement::Column::ToUserId.eq(caller.id)),
+            );
+        }
+        Ok((shares, settlements))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "settlements",
+    params(LedgerParams),
+    responses(
+        (status = OK, description = "Settlements, oldest first", body = SettlementsBody<Settlement>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_settlements(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Query(params): Query<LedgerParams>,
+) -> Result<Json<SettlementsBody<Settlement>>, AppError> {
+    let (_, condition) = params.scope(&db, caller).await?;
+    let settlements = settlement::Entity::find()
+        .filter(condition)
+        .order_by_asc(settlement::Column::CreatedAt)
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(Json(SettlementsBody { settlements }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "settlements",
+    request_body = SettlementBody<SettlementCreate>,
+    responses(
+        (status = CREATED, description = "The recorded settlement", body = SettlementBody<Settlement>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_settlement(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<SettlementBody<SettlementCreate>>,
+) -> Result<(StatusCode, Json<SettlementBody<Settlement>>), AppError> {
+    // Only whoever was paid may say so, the payer can't settle their own debt.
+    caller.require_user(body.settlement.to_user_id)?;
+    body.settlement.validate(&db).await?;
+    let settlement = settlement::ActiveModel {
+        from_user_id: Set(body.settlement.from_user_id),
+        to_user_id: Set(body.settlement.to_user_id),
+        sum: Set(body.settlement.sum),
+        household_id: Set(body.settlement.household_id),
+        ..Default::default()
+    };
+    let settlement = Settlement::from(settlement.insert(&db).await?);
+    Ok((StatusCode::CREATED, Json(SettlementBody { settlement })))
+}
+
+/// Voids the settlement, for a payment that didn't go through.
+#[utoipa::path(
+    delete,
+    path = "/{settlement_id}",
+    tag = "settlements",
+    params(("settlement_id" = Uuid, Path, description = "Settlement ID")),
+    responses(
+        (status = NO_CONTENT, description = "The settlement was deleted"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_settlement(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let settlement = settlement::Entity::find_by_id(id)
+        .one(&db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    // Whoever was paid recorded it, so they're the one to take it back.
+    caller.require_user(settlement.to_user_id)?;
+    settlement::Entity::delete_by_id(id).exec(&db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/balances",
+    tag = "settlements",
+    params(LedgerParams),
+    responses(
+        (status = OK, description = "Outstanding balances and how to settle them", body = BalancesBody),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_balances(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Query(params): Query<LedgerParams>,
+) -> Result<Json<BalancesBody>, AppError> {
+    let (shares, settlements) = params.scope(&db, caller).await?;
+    let (shares, settlements) = try_join!(
+        record_share::Entity::find()
+            .join(JoinType::InnerJoin, record_share::Relation::Record.def())
+            .select_only()
+            .columns([record_share::Column::UserId])
+            .column_as(record::Column::UserId, "payer_id")
+            .column(record_share::Column::Sum)
+            .filter(shares)
+            .into_tuple::<(Uuid, Uuid, Decimal)>()
+            .all(&db),
+        settlement::Entity::find().filter(settlements).all(&db)
+    )?;
+
+    // Sharers owe the payer, and a settlement is owed back by whoever received it.
+    let debts = shares
+        .into_iter()
+        .map(|(from, to, sum)| Debt { from, to, sum })
+        .chain(settlements.into_iter().map(|settlement| Debt {
+            from: settlement.to_user_id,
+            to: settlement.from_user_id,
+            sum: settlement.sum,
+        }));
+    let balances = ledger::pairwise(debts);
+    let transfers = ledger::transfers(&balances);
+    Ok(Json(BalancesBody {
+        balances: balances.into_iter().map(Into::into).collect(),
+        transfers: transfers.into_iter().map(Into::into).collect(),
+    }))
+}
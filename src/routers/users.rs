@@ -1,36 +1,59 @@
-use axum::{
-    Json, Router,
-    extract::{Path, State},
-    http::StatusCode,
-    routing::get,
+use axum::{extract::State, http::StatusCode, response::Response};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+    sea_query::Expr,
 };
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
-use crate::{AppState, error::AppError};
-use entity::user;
+use super::validate_name;
+use crate::{
+    AppState,
+    auth::{self, Caller, Role},
+    error::{AppError, Problem},
+    extract::{Json, Path},
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+    routers::records,
+};
+use entity::{record, user};
 
-pub fn router() -> Router<AppState> {
-    Router::new()
-        .route("/", get(get_users).post(create_user))
-        .route("/{user_id}", get(get_user).delete(delete_user))
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_users, create_user))
+        .routes(routes!(get_user, update_user, delete_user))
+        .routes(routes!(issue_token))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserBody<T> {
     user: T,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UsersBody<T> {
     users: Vec<T>,
 }
 
-#[derive(Debug, Serialize)]
+/// A created user along with the token it authenticates with, which is only ever shown once.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedUserBody {
+    user: User,
+    token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenBody {
+    token: String,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
 pub struct User {
     id: Uuid,
     name: String,
+    role: Role,
 }
 
 impl From<user::Model> for User {
@@ -38,70 +61,292 @@ impl From<user::Model> for User {
         Self {
             id: value.id,
             name: value.name,
+            role: value.role.into(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UserCreate {
+/// A user as seen by whoever shares records with them, without their role, which
+/// only they and admins may see.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct UserSummary {
+    id: Uuid,
     name: String,
 }
 
+impl From<user::Model> for UserSummary {
+    fn from(value: user::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UserCreate {
+    pub(crate) name: String,
+    /// Only admins may create users other than members.
+    #[serde(default)]
+    pub(crate) role: Option<Role>,
+}
+
 impl UserCreate {
     fn validate(&self) -> Result<(), AppError> {
-        let mut errors = Vec::new();
+        validate_name(&self.name)
+    }
+}
 
-        if self.name.is_empty() {
-            errors.push(("name", "name is empty"));
-        }
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UserUpdate {
+    pub(crate) name: String,
+    /// Only admins may change roles.
+    #[serde(default)]
+    pub(crate) role: Option<Role>,
+}
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(AppError::unprocessable_entity(errors))
-        }
+impl UserUpdate {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_name(&self.name)
     }
 }
 
+/// Creates a user, returning it along with its token. Anyone may sign up as a member.
+pub(crate) async fn create(
+    db: &DatabaseConnection,
+    caller: Option<Caller>,
+    new: UserCreate,
+) -> Result<(user::Model, String), AppError> {
+    new.validate()?;
+    let role = new.role.unwrap_or(Role::Member);
+    if role != Role::Member {
+        caller.ok_or(AppError::Unauthorized)?.require_admin()?;
+    }
+    Ok(auth::create_user(db, new.name, role).await?)
+}
+
+pub(crate) async fn update(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+    changes: UserUpdate,
+) -> Result<user::Model, AppError> {
+    caller.require_user(id)?;
+    if changes.role.is_some() {
+        caller.require_admin()?;
+    }
+    changes.validate()?;
+    let mut update = user::Entity::update_many()
+        .col_expr(user::Column::Name, Expr::value(changes.name))
+        .col_expr(
+            user::Column::Version,
+            Expr::col(user::Column::Version).add(1),
+        );
+    if let Some(role) = changes.role {
+        update = update.col_expr(user::Column::Role, Expr::value(user::Role::from(role)));
+    }
+    let Some(user) = update
+        .filter(user::Column::Id.eq(id))
+        .filter(if_match.condition(user::Column::Version))
+        .exec_with_returning(db)
+        .await?
+        .pop()
+    else {
+        return Err(precondition::mismatch::<user::Entity>(db, id).await);
+    };
+    Ok(user)
+}
+
+pub(crate) async fn delete(
+    db: &DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+    if_match: &IfMatch,
+) -> Result<(), AppError> {
+    caller.require_user(id)?;
+    let txn = db.begin().await?;
+    user::Entity::find_by_id(id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    let deleted = records::delete_where(&txn, record::Column::UserId.eq(id)).await?;
+    let res = user::Entity::delete_many()
+        .filter(user::Column::Id.eq(id))
+        .filter(if_match.condition(user::Column::Version))
+        .exec(&txn)
+        .await?;
+    if res.rows_affected == 0 {
+        txn.rollback().await?;
+        return Err(precondition::mismatch::<user::Entity>(db, id).await);
+    }
+    records::log_deleted(&txn, &deleted).await?;
+    outbox::publish(&txn, [DomainEvent::UserDeleted { user_id: id }]).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The user",
+            body = UserBody<User>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (
+            status = NOT_MODIFIED,
+            description = "The cached user is current",
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 pub async fn get_user(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
     Path(id): Path<Uuid>,
-) -> Result<Json<UserBody<User>>, AppError> {
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    caller.require_user(id)?;
     let user = user::Entity::find_by_id(id)
         .one(&db)
         .await?
-        .ok_or(AppError::NotFound)?
-        .into();
-    Ok(Json(UserBody { user }))
+        .ok_or(AppError::NotFound)?;
+    let version = user.version;
+    let user = User::from(user);
+    Ok(if_none_match.respond(version, Json(UserBody { user })))
 }
 
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "users",
+    request_body = UserBody<UserCreate>,
+    security((), ("bearer" = [])),
+    responses(
+        (
+            status = CREATED,
+            description = "The created user and its token",
+            body = CreatedUserBody,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 pub async fn create_user(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Option<Caller>,
     Json(body): Json<UserBody<UserCreate>>,
-) -> Result<(StatusCode, Json<UserBody<User>>), AppError> {
-    body.user.validate()?;
-    let user = user::ActiveModel {
-        name: Set(body.user.name),
-        ..Default::default()
-    };
-    let user = user.insert(&db).await?.into();
-    Ok((StatusCode::CREATED, Json(UserBody { user })))
+) -> Result<Response, AppError> {
+    let (user, token) = create(&db, caller, body.user).await?;
+    let version = user.version;
+    let user = User::from(user);
+    Ok(precondition::tagged(
+        version,
+        (StatusCode::CREATED, Json(CreatedUserBody { user, token })),
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        IfMatch,
+    ),
+    request_body = UserBody<UserUpdate>,
+    responses(
+        (
+            status = OK,
+            description = "The updated user",
+            body = UserBody<User>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+pub async fn update_user(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<UserBody<UserUpdate>>,
+) -> Result<Response, AppError> {
+    let user = update(&db, caller, id, &if_match, body.user).await?;
+    let version = user.version;
+    let user = User::from(user);
+    Ok(precondition::tagged(version, Json(UserBody { user })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        IfMatch,
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The user was deleted"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 pub async fn delete_user(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
     Path(id): Path<Uuid>,
+    if_match: IfMatch,
 ) -> Result<StatusCode, AppError> {
-    let res = user::Entity::delete_by_id(id).exec(&db).await?;
-    match res.rows_affected {
-        0 => Err(AppError::NotFound),
-        _ => Ok(StatusCode::NO_CONTENT),
-    }
+    delete(&db, caller, id, &if_match).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "users",
+    responses(
+        (status = OK, description = "All users", body = UsersBody<User>),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
 pub async fn get_users(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
 ) -> Result<Json<UsersBody<User>>, AppError> {
+    caller.require_admin()?;
     let users = user::Entity::find()
         .all(&db)
         .await?
@@ -110,3 +355,39 @@ pub async fn get_users(
         .collect();
     Ok(Json(UsersBody { users }))
 }
+
+/// Replaces the user's token, so the previous one stops working.
+#[utoipa::path(
+    post,
+    path = "/{user_id}/token",
+    tag = "users",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses(
+        (status = CREATED, description = "The new token", body = TokenBody),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+pub async fn issue_token(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<TokenBody>), AppError> {
+    caller.require_user(id)?;
+    let token = auth::generate_token();
+    let res = user::Entity::update_many()
+        .col_expr(
+            user::Column::TokenHash,
+            Expr::value(auth::hash_token(&token)),
+        )
+        .filter(user::Column::Id.eq(id))
+        .exec(&db)
+        .await?;
+    match res.rows_affected {
+        0 => Err(AppError::NotFound),
+        _ => Ok((StatusCode::CREATED, Json(TokenBody { token }))),
+    }
+}
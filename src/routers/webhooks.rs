@@ -0,0 +1,590 @@
+use std::collections::BTreeSet;
+
+use axum::{extract::State, http::StatusCode, response::Response};
+use chrono::NaiveDateTime;
+use entity::{record_event, webhook, webhook_delivery};
+use sea_orm::{
+    ActiveEnum, ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    auth::{self, Caller},
+    error::{AppError, FieldError, Problem},
+    extract::{Json, Path, Query},
+    outbox::{self, DomainEvent},
+    precondition::{self, IfMatch, IfNoneMatch},
+    webhooks,
+};
+
+const SECRET_PREFIX: &str = "whsec_";
+const MAX_URL_LEN: usize = 2000;
+const MAX_DELIVERIES: u64 = 100;
+
+pub fn router() -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(get_webhooks, create_webhook))
+        .routes(routes!(get_webhook, update_webhook, delete_webhook))
+        .routes(routes!(get_deliveries))
+        .routes(routes!(retry_delivery))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct WebhookBody<T> {
+    webhook: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct WebhooksBody<T> {
+    webhooks: Vec<T>,
+}
+
+/// A created webhook along with the secret its deliveries are signed with,
+/// which is only ever shown once.
+#[derive(Debug, Serialize, ToSchema)]
+struct CreatedWebhookBody {
+    webhook: Webhook,
+    secret: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DeliveryBody<T> {
+    delivery: T,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DeliveriesBody<T> {
+    deliveries: Vec<T>,
+}
+
+/// A kind of record event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+enum EventType {
+    #[serde(rename = "record.created")]
+    Created,
+    #[serde(rename = "record.updated")]
+    Updated,
+    #[serde(rename = "record.deleted")]
+    Deleted,
+}
+
+impl From<record_event::Kind> for EventType {
+    fn from(value: record_event::Kind) -> Self {
+        match value {
+            record_event::Kind::Created => Self::Created,
+            record_event::Kind::Updated => Self::Updated,
+            record_event::Kind::Deleted => Self::Deleted,
+        }
+    }
+}
+
+impl From<EventType> for record_event::Kind {
+    fn from(value: EventType) -> Self {
+        match value {
+            EventType::Created => Self::Created,
+            EventType::Updated => Self::Updated,
+            EventType::Deleted => Self::Deleted,
+        }
+    }
+}
+
+/// Formats event types to be stored space-separated.
+fn join_events(events: &[EventType]) -> String {
+    let events: BTreeSet<_> = events.iter().copied().collect();
+    events
+        .into_iter()
+        .map(|event| record_event::Kind::from(event).to_value())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Webhook {
+    id: Uuid,
+    /// The user whose records the webhook is notified of, or every record for admins.
+    user_id: Uuid,
+    url: String,
+    events: Vec<EventType>,
+    created_at: NaiveDateTime,
+}
+
+impl From<webhook::Model> for Webhook {
+    fn from(value: webhook::Model) -> Self {
+        Self {
+            id: value.id,
+            user_id: value.user_id,
+            events: value
+                .events
+                .split_whitespace()
+                .filter_map(|event| record_event::Kind::try_from_value(&event.to_owned()).ok())
+                .map(Into::into)
+                .collect(),
+            url: value.url,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// Where a delivery stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum DeliveryStatus {
+    /// Waiting for its next attempt.
+    Pending,
+    /// The webhook responded with a `2xx` status.
+    Delivered,
+    /// Given up on after too many failed attempts, until it's retried.
+    Dead,
+}
+
+impl From<webhook_delivery::Status> for DeliveryStatus {
+    fn from(value: webhook_delivery::Status) -> Self {
+        match value {
+            webhook_delivery::Status::Pending => Self::Pending,
+            webhook_delivery::Status::Delivered => Self::Delivered,
+            webhook_delivery::Status::Dead => Self::Dead,
+        }
+    }
+}
+
+impl From<DeliveryStatus> for webhook_delivery::Status {
+    fn from(value: DeliveryStatus) -> Self {
+        match value {
+            DeliveryStatus::Pending => Self::Pending,
+            DeliveryStatus::Delivered => Self::Delivered,
+            DeliveryStatus::Dead => Self::Dead,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Delivery {
+    id: Uuid,
+    webhook_id: Uuid,
+    /// The ID of the event, the same as the payload's.
+    event_id: i64,
+    #[serde(rename = "type")]
+    event_type: EventType,
+    /// The body posted to the webhook.
+    #[schema(value_type = Object)]
+    payload: serde_json::Value,
+    status: DeliveryStatus,
+    attempts: i32,
+    /// The status the webhook last responded with.
+    response_status: Option<i32>,
+    /// Why the last attempt failed.
+    last_error: Option<String>,
+    created_at: NaiveDateTime,
+    delivered_at: Option<NaiveDateTime>,
+}
+
+impl From<webhook_delivery::Model> for Delivery {
+    fn from(value: webhook_delivery::Model) -> Self {
+        Self {
+            id: value.id,
+            webhook_id: value.webhook_id,
+            event_id: value.event_id,
+            event_type: value.kind.into(),
+            payload: value.payload,
+            status: value.status.into(),
+            attempts: value.attempts,
+            response_status: value.response_status,
+            last_error: value.last_error,
+            created_at: value.created_at,
+            delivered_at: value.delivered_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct WebhookCreate {
+    /// An `http` or `https` URL that deliveries are posted to.
+    url: String,
+    /// The record events to deliver.
+    events: Vec<EventType>,
+}
+
+impl WebhookCreate {
+    fn validate(&self, private_networks: bool) -> Result<(), AppError> {
+        validate(&self.url, &self.events, private_networks)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct WebhookUpdate {
+    url: String,
+    events: Vec<EventType>,
+}
+
+impl WebhookUpdate {
+    fn validate(&self, private_networks: bool) -> Result<(), AppError> {
+        validate(&self.url, &self.events, private_networks)
+    }
+}
+
+/// Checks the URL's host as far as it can without resolving it, the worker
+/// checks the addresses it resolves to.
+fn validate(url: &str, events: &[EventType], private_networks: bool) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    let parsed = reqwest::Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+    if url.len() > MAX_URL_LEN {
+        errors.push(
+            FieldError::new("url", "too_long", "url is too long").param("max_length", MAX_URL_LEN),
+        );
+    } else if let Some(parsed) = parsed {
+        if let Some(message) = webhooks::forbidden_host(&parsed).filter(|_| !private_networks) {
+            errors.push(FieldError::new("url", "not_public", message));
+        }
+    } else {
+        errors.push(FieldError::new(
+            "url",
+            "invalid",
+            "url is not an http or https URL",
+        ));
+    }
+
+    if events.is_empty() {
+        errors.push(FieldError::new("events", "empty", "events are empty"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::unprocessable_entity(errors))
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeliveryFilterParams {
+    /// Only deliveries with this status.
+    status: Option<DeliveryStatus>,
+}
+
+/// Loads the webhook with `id`, checking that the caller owns it.
+async fn find_webhook(
+    db: &sea_orm::DatabaseConnection,
+    caller: Caller,
+    id: Uuid,
+) -> Result<webhook::Model, AppError> {
+    let webhook = webhook::Entity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    caller.require_user(webhook.user_id)?;
+    Ok(webhook)
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "webhooks",
+    responses(
+        (status = OK, description = "The caller's webhooks, or every webhook for admins", body = WebhooksBody<Webhook>),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_webhooks(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+) -> Result<Json<WebhooksBody<Webhook>>, AppError> {
+    let mut query = webhook::Entity::find().order_by_asc(webhook::Column::CreatedAt);
+    if !caller.is_admin() {
+        query = query.filter(webhook::Column::UserId.eq(caller.id));
+    }
+    let webhooks = query.all(&db).await?.into_iter().map(Into::into).collect();
+    Ok(Json(WebhooksBody { webhooks }))
+}
+
+/// Subscribes a URL to record events the caller may see: their own records and
+/// their households', or every record for admins.
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = "webhooks",
+    request_body = WebhookBody<WebhookCreate>,
+    responses(
+        (
+            status = CREATED,
+            description = "The created webhook and its secret",
+            body = CreatedWebhookBody,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn create_webhook(
+    State(AppState {
+        db,
+        webhooks_private_networks,
+        ..
+    }): State<AppState>,
+    caller: Caller,
+    Json(body): Json<WebhookBody<WebhookCreate>>,
+) -> Result<Response, AppError> {
+    body.webhook.validate(webhooks_private_networks)?;
+    let secret = format!("{SECRET_PREFIX}{}", auth::generate_token());
+    let webhook = webhook::ActiveModel {
+        user_id: Set(caller.id),
+        url: Set(body.webhook.url),
+        events: Set(join_events(&body.webhook.events)),
+        secret: Set(secret.clone()),
+        ..Default::default()
+    };
+    let webhook = webhook.insert(&db).await?;
+    let version = webhook.version;
+    let webhook = Webhook::from(webhook);
+    Ok(precondition::tagged(
+        version,
+        (
+            StatusCode::CREATED,
+            Json(CreatedWebhookBody { webhook, secret }),
+        ),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{webhook_id}",
+    tag = "webhooks",
+    params(
+        ("webhook_id" = Uuid, Path, description = "Webhook ID"),
+        IfNoneMatch,
+    ),
+    responses(
+        (
+            status = OK,
+            description = "The webhook",
+            body = WebhookBody<Webhook>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (
+            status = NOT_MODIFIED,
+            description = "The cached webhook is current",
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_webhook(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, AppError> {
+    let webhook = find_webhook(&db, caller, id).await?;
+    let version = webhook.version;
+    let webhook = Webhook::from(webhook);
+    Ok(if_none_match.respond(version, Json(WebhookBody { webhook })))
+}
+
+#[utoipa::path(
+    put,
+    path = "/{webhook_id}",
+    tag = "webhooks",
+    params(
+        ("webhook_id" = Uuid, Path, description = "Webhook ID"),
+        IfMatch,
+    ),
+    request_body = WebhookBody<WebhookUpdate>,
+    responses(
+        (
+            status = OK,
+            description = "The updated webhook, whose pending deliveries go to its new URL",
+            body = WebhookBody<Webhook>,
+            headers(("ETag" = String, description = "Entity tag of the current version"))
+        ),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = UNPROCESSABLE_ENTITY, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn update_webhook(
+    State(AppState {
+        db,
+        webhooks_private_networks,
+        ..
+    }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    Json(body): Json<WebhookBody<WebhookUpdate>>,
+) -> Result<Response, AppError> {
+    find_webhook(&db, caller, id).await?;
+    body.webhook.validate(webhooks_private_networks)?;
+    let Some(webhook) = webhook::Entity::update_many()
+        .col_expr(webhook::Column::Url, Expr::value(body.webhook.url))
+        .col_expr(
+            webhook::Column::Events,
+            Expr::value(join_events(&body.webhook.events)),
+        )
+        .col_expr(
+            webhook::Column::Version,
+            Expr::col(webhook::Column::Version).add(1),
+        )
+        .filter(webhook::Column::Id.eq(id))
+        .filter(if_match.condition(webhook::Column::Version))
+        .exec_with_returning(&db)
+        .await?
+        .pop()
+    else {
+        return Err(precondition::mismatch::<webhook::Entity>(&db, id).await);
+    };
+    let version = webhook.version;
+    let webhook = Webhook::from(webhook);
+    Ok(precondition::tagged(version, Json(WebhookBody { webhook })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{webhook_id}",
+    tag = "webhooks",
+    params(
+        ("webhook_id" = Uuid, Path, description = "Webhook ID"),
+        IfMatch,
+    ),
+    responses(
+        (status = NO_CONTENT, description = "The webhook was deleted along with its deliveries"),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = PRECONDITION_FAILED, response = Problem),
+        (status = PRECONDITION_REQUIRED, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn delete_webhook(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> Result<StatusCode, AppError> {
+    find_webhook(&db, caller, id).await?;
+    // Its deliveries cascade.
+    let res = webhook::Entity::delete_many()
+        .filter(webhook::Column::Id.eq(id))
+        .filter(if_match.condition(webhook::Column::Version))
+        .exec(&db)
+        .await?;
+    if res.rows_affected == 0 {
+        return Err(precondition::mismatch::<webhook::Entity>(&db, id).await);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The webhook's latest deliveries, newest first.
+#[utoipa::path(
+    get,
+    path = "/{webhook_id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("webhook_id" = Uuid, Path, description = "Webhook ID"),
+        DeliveryFilterParams,
+    ),
+    responses(
+        (status = OK, description = "Up to the latest 100 deliveries", body = DeliveriesBody<Delivery>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn get_deliveries(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    Query(params): Query<DeliveryFilterParams>,
+) -> Result<Json<DeliveriesBody<Delivery>>, AppError> {
+    find_webhook(&db, caller, id).await?;
+    let mut query = webhook_delivery::Entity::find()
+        .filter(webhook_delivery::Column::WebhookId.eq(id))
+        .order_by_desc(webhook_delivery::Column::CreatedAt)
+        .order_by_desc(webhook_delivery::Column::EventId)
+        .limit(MAX_DELIVERIES);
+    if let Some(status) = params.status {
+        query = query
+            .filter(webhook_delivery::Column::Status.eq(webhook_delivery::Status::from(status)));
+    }
+    let deliveries = query.all(&db).await?.into_iter().map(Into::into).collect();
+    Ok(Json(DeliveriesBody { deliveries }))
+}
+
+/// Attempts the delivery again as soon as possible, with a fresh set of attempts
+/// if it's dead.
+#[utoipa::path(
+    post,
+    path = "/{webhook_id}/deliveries/{delivery_id}/retry",
+    tag = "webhooks",
+    params(
+        ("webhook_id" = Uuid, Path, description = "Webhook ID"),
+        ("delivery_id" = Uuid, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = OK, description = "The pending delivery", body = DeliveryBody<Delivery>),
+        (status = BAD_REQUEST, response = Problem),
+        (status = UNAUTHORIZED, response = Problem),
+        (status = FORBIDDEN, response = Problem),
+        (status = NOT_FOUND, response = Problem),
+        (status = CONFLICT, response = Problem),
+        (status = INTERNAL_SERVER_ERROR, response = Problem),
+    )
+)]
+async fn retry_delivery(
+    State(AppState { db, .. }): State<AppState>,
+    caller: Caller,
+    Path((webhook_id, id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<DeliveryBody<Delivery>>, AppError> {
+    find_webhook(&db, caller, webhook_id).await?;
+    let delivery = webhook_delivery::Entity::find_by_id(id)
+        .filter(webhook_delivery::Column::WebhookId.eq(webhook_id))
+        .one(&db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let attempts = match delivery.status {
+        webhook_delivery::Status::Delivered => return Err(AppError::Conflict),
+        webhook_delivery::Status::Dead => 0,
+        webhook_delivery::Status::Pending => delivery.attempts,
+    };
+    let delivery = webhook_delivery::ActiveModel {
+        id: Set(id),
+        status: Set(webhook_delivery::Status::Pending),
+        attempts: Set(attempts),
+        ..Default::default()
+    };
+    let txn = db.begin().await?;
+    let delivery = delivery.update(&txn).await?;
+    let retried = DomainEvent::WebhookDeliveryRetried {
+        webhook_id,
+        delivery_id: id,
+    };
+    outbox::publish(&txn, [retried]).await?;
+    txn.commit().await?;
+    Ok(Json(DeliveryBody {
+        delivery: delivery.into(),
+    }))
+}
@@ -0,0 +1,172 @@
+//! The registry of external services the app depends on, and their health checks.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use serde_json::{Map, Value};
+use tokio::task::JoinSet;
+
+use crate::migrate;
+
+/// A health check of a single service.
+#[async_trait]
+pub trait Check: Send + Sync {
+    /// The name the service is reported under.
+    fn name(&self) -> &'static str;
+
+    /// Whether the app can't serve requests while the service is down.
+    fn critical(&self) -> bool {
+        true
+    }
+
+    /// Whether the app hasn't finished starting while the service is down.
+    fn startup(&self) -> bool {
+        false
+    }
+
+    /// Checks the service, failing if it can't be reached.
+    async fn check(&self) -> Result<Status, String>;
+}
+
+/// Whether a service is up, with details about it.
+pub struct Status {
+    pub up: bool,
+    pub details: Map<String, Value>,
+}
+
+impl Status {
+    pub fn up() -> Self {
+        Self {
+            up: true,
+            details: Map::new(),
+        }
+    }
+
+    pub fn down() -> Self {
+        Self {
+            up: false,
+            details: Map::new(),
+        }
+    }
+
+    pub fn detail(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(name.to_owned(), value.into());
+        self
+    }
+}
+
+/// The outcome of a single check.
+pub struct Report {
+    pub name: &'static str,
+    pub critical: bool,
+    pub latency: Duration,
+    pub status: Status,
+}
+
+/// The checks to run when the app's health is queried.
+#[derive(Clone)]
+pub struct Services {
+    checks: Vec<Arc<dyn Check>>,
+    started_at: Instant,
+}
+
+impl Services {
+    /// Starts the registry with the database and its migrations.
+    pub fn with_db(db: DatabaseConnection) -> Self {
+        Self {
+            checks: Vec::new(),
+            started_at: Instant::now(),
+        }
+        .register(Database(db.clone()))
+        .register(Migrations(db))
+    }
+
+    pub fn register(mut self, check: impl Check + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Runs the checks selected by `filter` concurrently, ordered by name.
+    pub async fn check(&self, filter: impl Fn(&dyn Check) -> bool) -> Vec<Report> {
+        let mut tasks = JoinSet::new();
+        for check in self.checks.iter().filter(|check| filter(check.as_ref())) {
+            let check = check.clone();
+            tasks.spawn(async move {
+                let start = Instant::now();
+                let status = check.check().await.unwrap_or_else(|e| {
+                    tracing::warn!(service = check.name(), error = %e, "health check failed");
+                    Status::down()
+                });
+                Report {
+                    name: check.name(),
+                    critical: check.critical(),
+                    latency: start.elapsed(),
+                    status,
+                }
+            });
+        }
+
+        let mut reports = Vec::new();
+        while let Some(report) = tasks.join_next().await {
+            match report {
+                Ok(report) => reports.push(report),
+                Err(e) => tracing::error!(error = %e, "health check panicked"),
+            }
+        }
+        reports.sort_by_key(|report| report.name);
+        reports
+    }
+}
+
+struct Database(DatabaseConnection);
+
+#[async_trait]
+impl Check for Database {
+    fn name(&self) -> &'static str {
+        "db"
+    }
+
+    async fn check(&self) -> Result<Status, String> {
+        self.0.ping().await.map_err(|e| e.to_string())?;
+        Ok(Status::up())
+    }
+}
+
+/// Up once every migration this release knows of has been applied. Migrations
+/// it doesn't know of were applied by a newer release, whose schema it's expected
+/// to keep working with while they roll out side by side.
+struct Migrations(DatabaseConnection);
+
+#[async_trait]
+impl Check for Migrations {
+    fn name(&self) -> &'static str {
+        "migrations"
+    }
+
+    fn startup(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<Status, String> {
+        let progress = migrate::progress(&self.0)
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = if progress.pending == 0 {
+            Status::up()
+        } else {
+            Status::down()
+        };
+        Ok(status
+            .detail("applied", progress.applied)
+            .detail("pending", progress.pending)
+            .detail("unknown", progress.unknown))
+    }
+}
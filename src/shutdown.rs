@@ -0,0 +1,98 @@
+//! Graceful shutdown on `SIGINT` or `SIGTERM`.
+
+use std::{future::IntoFuture, io, time::Duration};
+
+use tokio::{signal, time};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Coordinates the server and background tasks shutting down together.
+#[derive(Clone)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tracker: TaskTracker,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    /// Starts listening for shutdown signals, giving in-flight work `timeout` to finish.
+    pub fn listen(timeout: Duration) -> Self {
+        let shutdown = Self {
+            token: CancellationToken::new(),
+            tracker: TaskTracker::new(),
+            timeout,
+        };
+        let token = shutdown.token.clone();
+        tokio::spawn(async move {
+            signal().await;
+            tracing::info!("shutdown signal received, draining connections");
+            token.cancel();
+        });
+        shutdown
+    }
+
+    /// Cancelled once shutdown starts.
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Spawns a background task that shutdown waits for, it should stop once the
+    /// [`token`](Self::token) is cancelled.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(task);
+    }
+
+    /// Runs the server until shutdown, then waits for it and the background tasks
+    /// to finish, up to the drain timeout.
+    pub async fn serve<S>(self, server: S) -> io::Result<()>
+    where
+        S: IntoFuture<Output = io::Result<()>>,
+    {
+        let server = server.into_future();
+        tokio::pin!(server);
+        tokio::select! {
+            result = &mut server => return result,
+            () = self.token.cancelled() => {}
+        }
+
+        self.tracker.close();
+        let drain = async {
+            server.await?;
+            self.tracker.wait().await;
+            Ok(())
+        };
+        match time::timeout(self.timeout, drain).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!(
+                    timeout_secs = self.timeout.as_secs(),
+                    "drain timeout elapsed, dropping remaining connections"
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
@@ -0,0 +1,108 @@
+use std::{env, error::Error, time::Duration};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    response::Response,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tower_http::request_id::RequestId;
+use tracing::{Span, field};
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::config::{LogConfig, LogFormat};
+
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Keeps the OpenTelemetry pipeline alive, flushing pending spans when dropped.
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::warn!(error = %e, "failed to shut down tracer provider");
+        }
+    }
+}
+
+/// Installs the global tracing subscriber.
+///
+/// Spans are also exported over OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set,
+/// e.g. to `http://localhost:4318` for a local collector.
+pub fn init(config: &LogConfig) -> Result<Telemetry, Box<dyn Error>> {
+    let filter = EnvFilter::try_new(&config.filter)?;
+
+    let (json_layer, text_layer) = if config.format == LogFormat::Json {
+        let layer = tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false);
+        (Some(layer), None)
+    } else {
+        (None, Some(tracing_subscriber::fmt::layer()))
+    };
+
+    let tracer_provider = match env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Some(_) => {
+            let exporter = SpanExporter::builder().with_http().build()?;
+            let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(resource)
+                    .build(),
+            )
+        }
+        None => None,
+    };
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(json_layer)
+        .with(text_layer)
+        .with(otel_layer)
+        .try_init()?;
+
+    Ok(Telemetry { tracer_provider })
+}
+
+/// Creates the span wrapping a single request.
+pub fn make_span(req: &Request) -> Span {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        route,
+        uri = %req.uri(),
+        request_id,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    )
+}
+
+/// Records the outcome of a request on its span.
+pub fn on_response(res: &Response, latency: Duration, span: &Span) {
+    let status = res.status().as_u16();
+    let latency_ms = latency.as_secs_f64() * 1000.0;
+    span.record("status", status);
+    span.record("latency_ms", latency_ms);
+    tracing::info!(status, latency_ms, "finished processing request");
+}
@@ -0,0 +1,474 @@
+//! Record events delivered to the webhooks subscribed to them.
+//!
+//! Record changes reach webhooks through the outbox, so an event is delivered if
+//! and only if its change commits. The [`Deliveries`] subscriber queues them in
+//! the `webhook_delivery` table and posts them to their webhooks, signed with the
+//! webhook's secret, retrying failures as the outbox hands the event over again
+//! until it gives up on them as dead.
+//!
+//! Webhooks may only receive deliveries on public addresses, so that they can't
+//! be pointed at the services next to the app, such as a cloud's metadata
+//! endpoint. Their URLs are checked when they're saved, and the addresses their
+//! hosts resolve to on every attempt, since those may change.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use entity::{household_member, record, record_event, user, webhook, webhook_delivery};
+use futures_util::future;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+};
+use sea_orm::{
+    ActiveEnum, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QuerySelect, QueryTrait, sea_query::OnConflict,
+};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    outbox::{DomainEvent, Envelope, Subscriber},
+    routers::records,
+};
+
+/// Connection errors are kept up to this many characters.
+const MAX_ERROR_LEN: usize = 500;
+
+fn subscribes(webhook: &webhook::Model, kind: record_event::Kind) -> bool {
+    let kind = kind.to_value();
+    webhook.events.split_whitespace().any(|event| event == kind)
+}
+
+/// The body posted to webhooks for the change to `record` that `envelope` is
+/// about.
+fn payload(
+    envelope: &Envelope,
+    kind: record_event::Kind,
+    record: &record::Model,
+) -> serde_json::Value {
+    json!({
+        "id": envelope.id,
+        "type": kind,
+        "created_at": envelope.created_at,
+        "data": records::event_data(record),
+    })
+}
+
+/// Signs a delivery's `body` sent at `timestamp`, in Unix seconds, with `secret`.
+///
+/// Receivers recompute the HMAC-SHA256 of `{timestamp}.{body}` and compare it
+/// with the `webhook-signature` header.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("v1={:x}", mac.finalize().into_bytes())
+}
+
+/// Whether `ip` is a public address webhooks may receive deliveries on, rather
+/// than a loopback, private, link-local or otherwise reserved one. Cloud metadata
+/// endpoints, e.g. `169.254.169.254`, `fd00:ec2::254` or `100.100.100.200`, fall
+/// in these ranges too.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space, used for carrier-grade NAT.
+                || (a == 100 && b & 0xc0 == 64)
+                // Reserved for future use.
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // NAT64 for local use, which translates to private networks.
+                    || matches!(ip.segments(), [0x64, 0xff9b, 1, ..]))
+            }
+        },
+    }
+}
+
+/// The IPv4 address that `ip` reaches, if it embeds one: IPv4-mapped
+/// (`::ffff:a.b.c.d`), IPv4-compatible (`::a.b.c.d`), NAT64 (`64:ff9b::/96`) and
+/// 6to4 (`2002::/16`) addresses do.
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    let at = |i: usize| Ipv4Addr::new(octets[i], octets[i + 1], octets[i + 2], octets[i + 3]);
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some(at(12)),
+        [0x2002, ..] => Some(at(2)),
+        _ => ip.to_ipv4(),
+    }
+}
+
+/// Why webhooks may not receive deliveries at `url`, as far as can be told
+/// without resolving its host.
+pub(crate) fn forbidden_host(url: &Url) -> Option<&'static str> {
+    let host = url.host_str()?;
+    if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return (!is_public(ip)).then_some("url is not a public address");
+    }
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let local = host == "localhost"
+        || [".localhost", ".local", ".internal"]
+            .iter()
+            .any(|suffix| host.ends_with(suffix));
+    local.then_some("url is not a public host")
+}
+
+/// Resolves hosts to their public addresses only, failing if they have none.
+///
+/// The client connects to the addresses it's given, so a host can't pass the
+/// check and then resolve somewhere else for the connection.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Delivers record events to the webhooks subscribed to them, as the outbox's
+/// `webhooks` subscriber.
+///
+/// Each event is queued as a delivery to every webhook subscribed to it whose
+/// owner may see the record, and the deliveries are attempted at once. The event
+/// is handed over again, with the outbox's backoff, while any of them is pending,
+/// and only those are attempted again.
+pub struct Deliveries {
+    db: DatabaseConnection,
+    client: reqwest::Client,
+    max_attempts: u32,
+    private_networks: bool,
+}
+
+/// What came of attempting a delivery.
+struct Outcome {
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+impl Deliveries {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            client: client(false),
+            max_attempts: 8,
+            private_networks: false,
+        }
+    }
+
+    /// Whether to deliver to loopback, private and link-local addresses too,
+    /// e.g. for receivers running next to the app in development.
+    pub fn private_networks(mut self, allowed: bool) -> Self {
+        self.client = client(allowed);
+        self.private_networks = allowed;
+        self
+    }
+
+    /// How many attempts a delivery gets before it's dead.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Queues a delivery of the change to `record` to every webhook subscribed to
+    /// it whose owner may see the record, returning the event's pending
+    /// deliveries.
+    async fn enqueue(
+        &self,
+        envelope: &Envelope,
+        kind: record_event::Kind,
+        record: &record::Model,
+    ) -> Result<Vec<webhook_delivery::Model>, DbErr> {
+        let mut owners = Condition::any()
+            .add(user::Column::Role.eq(user::Role::Admin))
+            .add(webhook::Column::UserId.eq(record.user_id));
+        if let Some(household_id) = record.household_id {
+            owners = owners.add(
+                webhook::Column::UserId.in_subquery(
+                    household_member::Entity::find()
+                        .select_only()
+                        .column(household_member::Column::UserId)
+                        .filter(household_member::Column::HouseholdId.eq(household_id))
+                        .into_query(),
+                ),
+            );
+        }
+        // Event types aren't part of one another, so this only leaves webhooks
+        // subscribed to the event, which is checked exactly below.
+        let webhooks = webhook::Entity::find()
+            .inner_join(user::Entity)
+            .filter(webhook::Column::Events.contains(kind.to_value()))
+            .filter(owners)
+            .all(&self.db)
+            .await?;
+
+        let payload = payload(envelope, kind, record);
+        let deliveries: Vec<_> = webhooks
+            .iter()
+            .filter(|webhook| subscribes(webhook, kind))
+            .map(|webhook| webhook_delivery::ActiveModel {
+                webhook_id: Set(webhook.id),
+                event_id: Set(envelope.id),
+                kind: Set(kind),
+                payload: Set(payload.clone()),
+                ..Default::default()
+            })
+            .collect();
+        if !deliveries.is_empty() {
+            // Queued already if the event is handed over again.
+            webhook_delivery::Entity::insert_many(deliveries)
+                .on_conflict(
+                    OnConflict::columns([
+                        webhook_delivery::Column::EventId,
+                        webhook_delivery::Column::WebhookId,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec_without_returning(&self.db)
+                .await?;
+        }
+        webhook_delivery::Entity::find()
+            .filter(webhook_delivery::Column::EventId.eq(envelope.id))
+            .filter(webhook_delivery::Column::Status.eq(webhook_delivery::Status::Pending))
+            .all(&self.db)
+            .await
+    }
+
+    /// Attempts `delivery`, returning its status afterwards.
+    async fn attempt(
+        &self,
+        delivery: webhook_delivery::Model,
+        webhook: &webhook::Model,
+    ) -> Result<webhook_delivery::Status, DbErr> {
+        let outcome = self.post(&delivery, webhook).await;
+        let attempts = delivery.attempts + 1;
+        let status = if outcome.error.is_none() {
+            webhook_delivery::Status::Delivered
+        } else if attempts as u32 >= self.max_attempts {
+            tracing::warn!(
+                delivery_id = %delivery.id,
+                webhook_id = %webhook.id,
+                attempts,
+                "webhook delivery is dead",
+            );
+            webhook_delivery::Status::Dead
+        } else {
+            webhook_delivery::Status::Pending
+        };
+        let update = webhook_delivery::ActiveModel {
+            id: Set(delivery.id),
+            status: Set(status),
+            attempts: Set(attempts),
+            response_status: Set(outcome.status.map(i32::from)),
+            last_error: Set(outcome.error),
+            delivered_at: Set(
+                (status == webhook_delivery::Status::Delivered).then(|| Utc::now().naive_utc())
+            ),
+            ..Default::default()
+        };
+        webhook_delivery::Entity::update(update)
+            .exec(&self.db)
+            .await?;
+        Ok(status)
+    }
+
+    async fn post(&self, delivery: &webhook_delivery::Model, webhook: &webhook::Model) -> Outcome {
+        // The resolver only sees hosts that are names, not addresses.
+        if !self.private_networks
+            && let Some(error) = Url::parse(&webhook.url)
+                .ok()
+                .as_ref()
+                .and_then(forbidden_host)
+        {
+            return Outcome {
+                status: None,
+                error: Some(error.to_owned()),
+            };
+        }
+        let body = delivery.payload.to_string();
+        let timestamp = Utc::now().timestamp();
+        let res = self
+            .client
+            .post(&webhook.url)
+            .header("content-type", "application/json")
+            .header("webhook-id", delivery.id.to_string())
+            .header("webhook-timestamp", timestamp.to_string())
+            .header(
+                "webhook-signature",
+                sign(&webhook.secret, timestamp, body.as_bytes()),
+            )
+            .body(body)
+            .send()
+            .await;
+        match res {
+            Ok(res) if res.status().is_success() => Outcome {
+                status: Some(res.status().as_u16()),
+                error: None,
+            },
+            // The receiver's body isn't kept, it's the receiver's business.
+            Ok(res) => Outcome {
+                status: Some(res.status().as_u16()),
+                error: Some(format!("receiver responded with {}", res.status())),
+            },
+            Err(e) => Outcome {
+                status: None,
+                error: Some(e.to_string().chars().take(MAX_ERROR_LEN).collect()),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Subscriber for Deliveries {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, envelope: &Envelope) -> Result<(), String> {
+        let deliveries = match &envelope.event {
+            DomainEvent::RecordCreated(record) => {
+                self.enqueue(envelope, record_event::Kind::Created, record)
+                    .await
+            }
+            DomainEvent::RecordUpdated(record) => {
+                self.enqueue(envelope, record_event::Kind::Updated, record)
+                    .await
+            }
+            DomainEvent::RecordDeleted(record) => {
+                self.enqueue(envelope, record_event::Kind::Deleted, record)
+                    .await
+            }
+            DomainEvent::WebhookDeliveryRetried { delivery_id, .. } => {
+                webhook_delivery::Entity::find_by_id(*delivery_id)
+                    .filter(webhook_delivery::Column::Status.eq(webhook_delivery::Status::Pending))
+                    .all(&self.db)
+                    .await
+            }
+            _ => return Ok(()),
+        }
+        .map_err(|e| e.to_string())?;
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        let webhooks: HashMap<Uuid, webhook::Model> = webhook::Entity::find()
+            .filter(webhook::Column::Id.is_in(deliveries.iter().map(|d| d.webhook_id)))
+            .all(&self.db)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|webhook| (webhook.id, webhook))
+            .collect();
+        let attempts = deliveries.into_iter().filter_map(|delivery| {
+            let webhook = webhooks.get(&delivery.webhook_id)?;
+            Some(self.attempt(delivery, webhook))
+        });
+        let mut pending = 0;
+        for status in future::join_all(attempts).await {
+            if status.map_err(|e| e.to_string())? == webhook_delivery::Status::Pending {
+                pending += 1;
+            }
+        }
+        match pending {
+            0 => Ok(()),
+            pending => Err(format!("{pending} webhook deliveries failed")),
+        }
+    }
+}
+
+/// The client deliveries are posted with, resolving hosts to public addresses
+/// only unless `private_networks` are allowed.
+fn client(private_networks: bool) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!(
+            "backend_lab_3-webhooks/",
+            env!("CARGO_PKG_VERSION")
+        ));
+    if !private_networks {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    builder.build().expect("the HTTP client is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_public_addresses_apart() {
+        for ip in [
+            "93.184.215.14",
+            "2606:2800:21f:cb07:6820:80da:af6b:8b2c",
+            "64:ff9b::5db8:d70e",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::ffff:127.0.0.1",
+            "::10.1.2.3",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::a01:203",
+            "2002:c0a8:101::1",
+            "fd00:ec2::254",
+            "fe80::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        let signature = sign("whsec_test", 1_700_000_000, b"{}");
+        assert!(signature.starts_with("v1="));
+        assert_eq!(signature.len(), 3 + 64);
+        assert_eq!(signature, sign("whsec_test", 1_700_000_000, b"{}"));
+        assert_ne!(signature, sign("whsec_test", 1_700_000_001, b"{}"));
+        assert_ne!(signature, sign("whsec_other", 1_700_000_000, b"{}"));
+    }
+}
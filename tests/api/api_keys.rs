@@ -0,0 +1,164 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::TestApp;
+
+async fn create_key(app: &TestApp, scopes: Value) -> (Value, String) {
+    let res = app
+        .post(
+            "/api-keys",
+            json!({ "api_key": { "name": "Reports", "scopes": scopes } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let key = res.body["key"].as_str().unwrap().to_owned();
+    (res.body["api_key"].clone(), key)
+}
+
+#[tokio::test]
+async fn authenticates_with_api_key_within_its_scopes() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    ann_app.create_record(&ann, &food, "5").await;
+
+    let (api_key, key) = create_key(&ann_app, json!(["records:read"])).await;
+    assert!(key.starts_with("key_"));
+    assert_eq!(api_key["user_id"], ann["id"]);
+    assert_eq!(api_key["scopes"], json!(["records:read"]));
+    assert_eq!(api_key["last_used_at"], Value::Null);
+
+    let reader = app.with_token(&key);
+    let res = reader.get("/records").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["records"].as_array().unwrap().len(), 1);
+    let record =
+        json!({ "record": { "user_id": ann["id"], "category_id": food["id"], "sum": "1" } });
+    assert_eq!(
+        reader.post("/records", record.clone()).await.status,
+        StatusCode::FORBIDDEN
+    );
+    let res = reader
+        .get(&format!("/users/{}", ann["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(reader.get("/api-keys").await.status, StatusCode::FORBIDDEN);
+
+    let (_, key) = create_key(&ann_app, json!(["records:read", "records:write"])).await;
+    let writer = app.with_token(&key);
+    assert_eq!(
+        writer.post("/records", record).await.status,
+        StatusCode::CREATED
+    );
+
+    let res = ann_app.get("/api-keys").await;
+    let keys = res.body["api_keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 2);
+    assert_ne!(keys[0]["last_used_at"], Value::Null);
+    assert!(keys.iter().all(|key| key.get("key").is_none()));
+}
+
+#[tokio::test]
+async fn scopes_api_keys_per_resource() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    app.create_category("Food").await;
+
+    let (api_key, key) = create_key(&ann_app, json!(["categories:read", "users:read"])).await;
+    assert_eq!(api_key["scopes"], json!(["categories:read", "users:read"]));
+    let client = app.with_token(&key);
+    let res = client.get("/categories").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["categories"].as_array().unwrap().len(), 1);
+    let res = client
+        .get(&format!("/users/{}", ann["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = client
+        .post("/categories", json!({ "category": { "name": "Rent" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(client.get("/records").await.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        client.get("/households").await.status,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn cannot_issue_tokens() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+
+    let (_, key) = create_key(&ann_app, json!(["users:read", "users:write"])).await;
+    let client = app.with_token(&key);
+    let uri = format!("/users/{}/token", ann["id"].as_str().unwrap());
+    let res = client.post(&uri, json!({})).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = client
+        .post("/users", json!({ "user": { "name": "Bob" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        ann_app.post(&uri, json!({})).await.status,
+        StatusCode::CREATED
+    );
+}
+
+#[tokio::test]
+async fn revokes_api_key() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_member("Ann").await;
+    let (_, bob_token) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&token);
+    let (api_key, key) = create_key(&ann_app, json!(["records:read"])).await;
+    let uri = format!("/api-keys/{}", api_key["id"].as_str().unwrap());
+
+    let res = app.with_token(&bob_token).delete(&uri, "*").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        ann_app.delete(&uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+
+    let res = app.with_token(&key).get("/records").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        ann_app.delete(&uri, "*").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn rejects_invalid_api_keys() {
+    let app = TestApp::new().await;
+
+    let res = app
+        .post(
+            "/api-keys",
+            json!({
+                "api_key": { "name": "", "scopes": [], "expires_at": "2000-01-01T00:00:00" }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.errors(),
+        [
+            ("name", "empty"),
+            ("scopes", "empty"),
+            ("expires_at", "in_past")
+        ]
+    );
+
+    let res = app
+        .post(
+            "/api-keys",
+            json!({ "api_key": { "name": "Reports", "scopes": ["users:admin"] } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,173 @@
+use axum::http::{StatusCode, header};
+use serde_json::json;
+
+use crate::TestApp;
+
+#[tokio::test]
+async fn rejects_missing_and_invalid_credentials() {
+    let app = TestApp::new().await;
+
+    let res = app.anonymous().get("/records").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers[header::WWW_AUTHENTICATE], "Bearer");
+    assert_eq!(res.body["code"], "unauthorized");
+
+    let res = app.with_token("nope").get("/records").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app.anonymous().get("/health/live").await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn restricts_user_management_to_admins() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let (bob, _) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&token);
+    let bob_uri = format!("/users/{}", bob["id"].as_str().unwrap());
+    let ann_uri = format!("/users/{}", ann["id"].as_str().unwrap());
+
+    let res = ann_app.get("/users").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.body["code"], "forbidden");
+    assert_eq!(ann_app.get(&bob_uri).await.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        ann_app.delete(&bob_uri, "*").await.status,
+        StatusCode::FORBIDDEN
+    );
+
+    assert_eq!(ann_app.get(&ann_uri).await.status, StatusCode::OK);
+    let res = ann_app
+        .put(&ann_uri, "*", json!({ "user": { "name": "Anna" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = ann_app
+        .put(
+            &ann_uri,
+            "*",
+            json!({ "user": { "name": "Anna", "role": "admin" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = ann_app
+        .post(
+            "/users",
+            json!({ "user": { "name": "Eve", "role": "admin" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .put(
+            &ann_uri,
+            "*",
+            json!({ "user": { "name": "Anna", "role": "admin" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["user"]["role"], "admin");
+    assert_eq!(ann_app.get("/users").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn restricts_category_management_to_admins() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let category = app.create_category("Food").await;
+    let uri = format!("/categories/{}", category["id"].as_str().unwrap());
+
+    assert_eq!(ann_app.get("/categories").await.status, StatusCode::OK);
+    assert_eq!(ann_app.get(&uri).await.status, StatusCode::OK);
+    let res = ann_app
+        .post("/categories", json!({ "category": { "name": "Rent" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = ann_app
+        .put(&uri, "*", json!({ "category": { "name": "Rent" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        ann_app.delete(&uri, "*").await.status,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn restricts_records_to_their_owner() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, _) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&ann_token);
+    let category = app.create_category("Food").await;
+    let own = app.create_record(&ann, &category, "1").await;
+    let other = app.create_record(&bob, &category, "2").await;
+    let own_uri = format!("/records/{}", own["id"].as_str().unwrap());
+    let other_uri = format!("/records/{}", other["id"].as_str().unwrap());
+
+    let res = ann_app.get("/records").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["records"], json!([own]));
+    let res = ann_app
+        .get(&format!("/records?user_id={}", bob["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    assert_eq!(ann_app.get(&own_uri).await.status, StatusCode::OK);
+    assert_eq!(ann_app.get(&other_uri).await.status, StatusCode::FORBIDDEN);
+    let res = ann_app
+        .put(
+            &other_uri,
+            "*",
+            json!({ "record": { "category_id": category["id"], "sum": "3" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        ann_app.delete(&other_uri, "*").await.status,
+        StatusCode::FORBIDDEN
+    );
+
+    let res = ann_app
+        .post(
+            "/records",
+            json!({ "record": { "user_id": bob["id"], "category_id": category["id"], "sum": "1" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = ann_app
+        .post(
+            "/records/bulk/delete",
+            json!({ "records": [
+                { "id": own["id"], "version": 1 },
+                { "id": other["id"], "version": 1 },
+            ] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn issuing_token_revokes_previous_one() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let uri = format!("/users/{}/token", ann["id"].as_str().unwrap());
+
+    let res = app.with_token(&token).post(&uri, json!({})).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let new_token = res.body["token"].as_str().unwrap();
+
+    let uri = format!("/users/{}", ann["id"].as_str().unwrap());
+    assert_eq!(
+        app.with_token(&token).get(&uri).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.with_token(new_token).get(&uri).await.status,
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,191 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::{MISSING_ID, TestApp};
+
+fn statuses(body: &Value) -> Vec<(u64, u64)> {
+    body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["index"].as_u64().unwrap(),
+                result["status"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn creates_all_records_or_none() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let category = app.create_category("Food").await;
+    let valid = json!({ "user_id": user["id"], "category_id": category["id"], "sum": "1" });
+    let invalid = json!({ "user_id": user["id"], "category_id": MISSING_ID, "sum": "1" });
+
+    let res = app
+        .post(
+            "/records/bulk",
+            json!({ "records": [valid.clone(), invalid.clone()] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(statuses(&res.body), [(1, 422)]);
+    assert_eq!(res.body["results"][0]["errors"][0]["field"], "category_id");
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"], json!([]));
+
+    let res = app
+        .post(
+            "/records/bulk",
+            json!({ "records": [valid.clone(), valid] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(statuses(&res.body), [(0, 201), (1, 201)]);
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn creates_valid_records_in_best_effort_mode() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let category = app.create_category("Food").await;
+
+    let res = app
+        .post(
+            "/records/bulk",
+            json!({
+                "mode": "best_effort",
+                "records": [
+                    { "user_id": MISSING_ID, "category_id": category["id"], "sum": "1" },
+                    { "user_id": user["id"], "category_id": category["id"], "sum": "2" },
+                ]
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert_eq!(statuses(&res.body), [(0, 422), (1, 201)]);
+    assert_eq!(res.body["results"][1]["record"]["sum"], "2");
+}
+
+#[tokio::test]
+async fn rejects_empty_batches() {
+    let app = TestApp::new().await;
+
+    let res = app.post("/records/bulk", json!({ "records": [] })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("records", "empty")]);
+
+    let res = app
+        .post("/records/bulk/delete", json!({ "records": [] }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("records", "empty")]);
+}
+
+#[tokio::test]
+async fn deletes_all_records_or_none() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let category = app.create_category("Food").await;
+    let record = app.create_record(&user, &category, "1").await;
+
+    let res = app
+        .post(
+            "/records/bulk/delete",
+            json!({ "records": [
+                { "id": record["id"], "version": 1 },
+                { "id": MISSING_ID, "version": 1 },
+            ] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(statuses(&res.body), [(1, 404)]);
+    let res = app
+        .post(
+            "/records/bulk/delete",
+            json!({ "records": [{ "id": record["id"], "version": 2 }] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(statuses(&res.body), [(0, 412)]);
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"], json!([record]));
+
+    let res = app
+        .post(
+            "/records/bulk/delete",
+            json!({ "records": [{ "id": record["id"], "version": 1 }] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(statuses(&res.body), [(0, 204)]);
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"], json!([]));
+}
+
+#[tokio::test]
+async fn deletes_what_it_can_in_best_effort_mode() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let bob = app.create_user("Bob").await;
+    let category = app.create_category("Food").await;
+    let own = app.create_record(&ann, &category, "1").await;
+    let stale = app.create_record(&ann, &category, "2").await;
+    let other = app.create_record(&bob, &category, "3").await;
+
+    let res = app
+        .with_token(&token)
+        .post(
+            "/records/bulk/delete",
+            json!({
+                "mode": "best_effort",
+                "records": [
+                    { "id": MISSING_ID, "version": 1 },
+                    { "id": other["id"], "version": 1 },
+                    { "id": stale["id"], "version": 2 },
+                    { "id": own["id"], "version": 1 },
+                ]
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert_eq!(
+        statuses(&res.body),
+        [(0, 404), (1, 403), (2, 412), (3, 204)]
+    );
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"], json!([stale, other]));
+}
+
+#[tokio::test]
+async fn creates_what_it_may_in_best_effort_mode() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let bob = app.create_user("Bob").await;
+    let category = app.create_category("Food").await;
+    let records = json!([
+        { "user_id": bob["id"], "category_id": category["id"], "sum": "1" },
+        { "user_id": ann["id"], "category_id": category["id"], "sum": "2" },
+    ]);
+
+    let ann_app = app.with_token(&token);
+    let res = ann_app
+        .post("/records/bulk", json!({ "records": records }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = ann_app
+        .post(
+            "/records/bulk",
+            json!({ "mode": "best_effort", "records": records }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert_eq!(statuses(&res.body), [(0, 403), (1, 201)]);
+    assert_eq!(res.body["results"][0]["errors"][0]["field"], "user_id");
+    assert_eq!(res.body["results"][0]["errors"][0]["code"], "forbidden");
+}
@@ -0,0 +1,91 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use crate::{MISSING_ID, TestApp};
+
+#[tokio::test]
+async fn creates_gets_and_lists_categories() {
+    let app = TestApp::new().await;
+
+    let res = app
+        .post("/categories", json!({ "category": { "name": "Food" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.etag(), "\"1\"");
+    let category = res.body["category"].clone();
+
+    let res = app
+        .get(&format!("/categories/{}", category["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["category"], category);
+
+    let res = app.get("/categories").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["categories"], json!([category]));
+}
+
+#[tokio::test]
+async fn rejects_invalid_names() {
+    let app = TestApp::new().await;
+
+    let res = app
+        .post("/categories", json!({ "category": { "name": "" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("name", "empty")]);
+
+    let name = "a".repeat(101);
+    let res = app
+        .post("/categories", json!({ "category": { "name": name } }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("name", "too_long")]);
+}
+
+#[tokio::test]
+async fn returns_not_found_for_missing_category() {
+    let app = TestApp::new().await;
+
+    let uri = format!("/categories/{MISSING_ID}");
+    assert_eq!(app.get(&uri).await.status, StatusCode::NOT_FOUND);
+    let res = app
+        .put(&uri, "*", json!({ "category": { "name": "Food" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(app.delete(&uri, "*").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn updates_category_if_version_matches() {
+    let app = TestApp::new().await;
+    let category = app.create_category("Food").await;
+    let uri = format!("/categories/{}", category["id"].as_str().unwrap());
+    let body = json!({ "category": { "name": "Groceries" } });
+
+    let res = app.put(&uri, "\"1\"", body.clone()).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"2\"");
+    assert_eq!(res.body["category"]["name"], "Groceries");
+
+    let res = app.put(&uri, "\"1\"", body).await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn deleting_category_deletes_its_records() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let food = app.create_category("Food").await;
+    let rent = app.create_category("Rent").await;
+    app.create_record(&user, &food, "12.5").await;
+    let kept = app.create_record(&user, &rent, "500").await;
+
+    let uri = format!("/categories/{}", food["id"].as_str().unwrap());
+    let res = app.delete(&uri, "\"1\"").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert_eq!(app.get(&uri).await.status, StatusCode::NOT_FOUND);
+
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"], json!([kept]));
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use axum::{
+    http::{StatusCode, header},
+    response::Response,
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use tokio::{sync::mpsc, time};
+
+use crate::TestApp;
+
+/// A server-sent event's ID, name and data.
+type Event = (String, String, Value);
+
+/// Reads the events off `res` as they arrive, in the background like a client.
+fn listen(res: Response) -> mpsc::UnboundedReceiver<Event> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut body = res.into_body();
+    tokio::spawn(async move {
+        let mut buffer = String::new();
+        while let Some(Ok(frame)) = body.frame().await {
+            let Ok(data) = frame.into_data() else {
+                continue;
+            };
+            buffer.push_str(std::str::from_utf8(&data).unwrap());
+            while let Some(end) = buffer.find("\n\n") {
+                let message: String = buffer.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    message
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(str::to_owned)
+                };
+                if let (Some(id), Some(event), Some(data)) =
+                    (field("id: "), field("event: "), field("data: "))
+                {
+                    let _ = sender.send((id, event, serde_json::from_str(&data).unwrap()));
+                }
+            }
+        }
+    });
+    receiver
+}
+
+async fn read(events: &mut mpsc::UnboundedReceiver<Event>, count: usize) -> Vec<Event> {
+    let mut read = Vec::new();
+    while read.len() < count {
+        let event = time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("no event arrived in time")
+            .unwrap();
+        read.push(event);
+    }
+    read
+}
+
+fn names(events: &[Event]) -> Vec<&str> {
+    events.iter().map(|(_, name, _)| name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn streams_record_changes() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let food = app.create_category("Food").await;
+    let taxi = app.create_category("Taxi").await;
+
+    let uri = format!(
+        "/records/events?category_id={}",
+        food["id"].as_str().unwrap()
+    );
+    let res = app.open(&uri, &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+    let mut stream = listen(res);
+
+    app.create_record(&user, &taxi, "3").await;
+    let record = app.create_record(&user, &food, "5").await;
+    let mut events = read(&mut stream, 1).await;
+    assert_eq!(names(&events), ["record.created"]);
+    assert_eq!(events[0].2["record"], record);
+
+    // Once caught up, changes arrive as they're committed.
+    let record_uri = format!("/records/{}", record["id"].as_str().unwrap());
+    let body = json!({ "record": { "category_id": food["id"], "sum": "7" } });
+    assert_eq!(app.put(&record_uri, "*", body).await.status, StatusCode::OK);
+    assert_eq!(
+        app.delete(&record_uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    events.extend(read(&mut stream, 2).await);
+    assert_eq!(
+        names(&events),
+        ["record.created", "record.updated", "record.deleted"]
+    );
+    assert_eq!(events[1].2["record"]["sum"], "7");
+    assert_eq!(events[2].2["record"]["id"], record["id"]);
+
+    // Reconnecting resumes after the last event received.
+    let res = app.open(&uri, &[("last-event-id", &events[0].0)]).await;
+    let resumed = read(&mut listen(res), 2).await;
+    assert_eq!(resumed, events[1..]);
+}
+
+#[tokio::test]
+async fn streams_only_the_callers_records() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, bob_token) = app.create_member("Bob").await;
+    let food = app.create_category("Food").await;
+    let ann_app = app.with_token(&ann_token);
+
+    let uri = format!("/records/events?user_id={}", bob["id"].as_str().unwrap());
+    let res = ann_app.open(&uri, &[]).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = ann_app
+        .open("/records/events", &[("last-event-id", "latest")])
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let mut stream = listen(ann_app.open("/records/events", &[]).await);
+    app.with_token(&bob_token)
+        .create_record(&bob, &food, "5")
+        .await;
+    let record = ann_app.create_record(&ann, &food, "7").await;
+    let events = read(&mut stream, 1).await;
+    assert_eq!(events[0].2["record"]["id"], record["id"]);
+}
+
+#[tokio::test]
+async fn streams_records_deleted_with_what_they_belong_to() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    let taxi = app.create_category("Taxi").await;
+    let res = ann_app
+        .post("/households", json!({ "household": { "name": "Home" } }))
+        .await;
+    let household = res.body["household"].clone();
+    let res = ann_app
+        .post(
+            "/records",
+            json!({ "record": {
+                "user_id": ann["id"], "category_id": food["id"], "sum": "3",
+                "household_id": household["id"],
+            } }),
+        )
+        .await;
+    let shared = res.body["record"].clone();
+    let taxi_record = app.create_record(&ann, &taxi, "2").await;
+    let food_record = app.create_record(&ann, &food, "1").await;
+    let mut stream = listen(app.open("/records/events", &[]).await);
+
+    for uri in [
+        format!("/households/{}", household["id"].as_str().unwrap()),
+        format!("/categories/{}", taxi["id"].as_str().unwrap()),
+        format!("/users/{}", ann["id"].as_str().unwrap()),
+    ] {
+        assert_eq!(app.delete(&uri, "*").await.status, StatusCode::NO_CONTENT);
+    }
+    let events = read(&mut stream, 3).await;
+    assert_eq!(
+        names(&events),
+        ["record.deleted", "record.deleted", "record.deleted"]
+    );
+    let ids: Vec<_> = events
+        .iter()
+        .map(|(_, _, data)| &data["record"]["id"])
+        .collect();
+    assert_eq!(ids, [&shared["id"], &taxi_record["id"], &food_record["id"]]);
+}
+
+#[tokio::test]
+async fn stops_streaming_records_the_caller_may_no_longer_see() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, bob_token) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&ann_token);
+    let food = app.create_category("Food").await;
+    let res = ann_app
+        .post("/households", json!({ "household": { "name": "Home" } }))
+        .await;
+    let household_id = res.body["household"]["id"].as_str().unwrap().to_owned();
+    let res = ann_app
+        .post(
+            &format!("/households/{household_id}/invitations"),
+            json!({ "invitation": { "role": "viewer" } }),
+        )
+        .await;
+    let code = res.body["invitation"]["code"].clone();
+    let bob_app = app.with_token(&bob_token);
+    let res = bob_app
+        .post(
+            "/households/join",
+            json!({ "invitation": { "code": code } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let uri = format!("/records/events?household_id={household_id}");
+    let mut stream = listen(bob_app.open(&uri, &[]).await);
+    let body = json!({ "record": {
+        "user_id": ann["id"], "category_id": food["id"], "sum": "3",
+        "household_id": household_id,
+    } });
+    let record = ann_app.post("/records", body.clone()).await.body["record"].clone();
+    assert_eq!(read(&mut stream, 1).await[0].2["record"], record);
+
+    let member = format!(
+        "/households/{household_id}/members/{}",
+        bob["id"].as_str().unwrap()
+    );
+    assert_eq!(
+        ann_app.delete(&member, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    time::sleep(Duration::from_millis(1100)).await;
+    ann_app.post("/records", body).await;
+    let next = time::timeout(Duration::from_millis(500), stream.recv()).await;
+    assert!(next.is_err(), "received {next:?}");
+}
@@ -0,0 +1,224 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::{MISSING_ID, TestApp};
+
+/// Runs a GraphQL operation, returning its `data` and `errors`.
+async fn graphql(app: &TestApp, query: &str, variables: Value) -> (Value, Value) {
+    let res = app
+        .post(
+            "/graphql",
+            json!({ "query": query, "variables": variables }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    (res.body["data"].clone(), res.body["errors"].clone())
+}
+
+/// The `code` extension of each error.
+fn codes(errors: &Value) -> Vec<&str> {
+    errors
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["extensions"]["code"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn queries_records_with_their_relations() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let (bob, _) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    let rent = app.create_category("Rent").await;
+    let record = app.create_record(&ann, &food, "5").await;
+    app.create_record(&ann, &rent, "700").await;
+    let bobs = app.create_record(&bob, &food, "3").await;
+
+    let (data, errors) = graphql(
+        &ann_app,
+        "{ records { id sum user { name } category { name } } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(errors, Value::Null);
+    assert_eq!(
+        data["records"],
+        json!([
+            { "id": record["id"], "sum": "5", "user": { "name": "Ann" }, "category": { "name": "Food" } },
+            { "id": data["records"][1]["id"], "sum": "700", "user": { "name": "Ann" }, "category": { "name": "Rent" } },
+        ])
+    );
+
+    let query = "query ($filter: RecordFilter!) { records(filter: $filter) { user { name } } }";
+    let (data, _) = graphql(
+        &app,
+        query,
+        json!({ "filter": { "categoryId": food["id"] } }),
+    )
+    .await;
+    assert_eq!(
+        data["records"],
+        json!([{ "user": { "name": "Ann" } }, { "user": { "name": "Bob" } }])
+    );
+    let (data, errors) = graphql(
+        &ann_app,
+        query,
+        json!({ "filter": { "userId": bob["id"] } }),
+    )
+    .await;
+    assert_eq!(data, Value::Null);
+    assert_eq!(codes(&errors), ["forbidden"]);
+    assert_eq!(errors[0]["extensions"]["status"], 403);
+
+    // Records only tell who their user is, not the user's role.
+    let (data, errors) = graphql(&ann_app, "{ records { user { role } } }", json!({})).await;
+    assert_eq!(data, Value::Null);
+    assert!(
+        errors[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Unknown field \"role\"")
+    );
+
+    let (data, _) = graphql(
+        &ann_app,
+        "{ me { name role version records { sum category { name } } } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(data["me"]["name"], "Ann");
+    assert_eq!(data["me"]["role"], "MEMBER");
+    assert_eq!(data["me"]["version"], 1);
+    assert_eq!(data["me"]["records"].as_array().unwrap().len(), 2);
+
+    let query = "query ($id: UUID!) { record(id: $id) { sum } }";
+    let (data, errors) = graphql(&ann_app, query, json!({ "id": MISSING_ID })).await;
+    assert_eq!(errors, Value::Null);
+    assert_eq!(data["record"], Value::Null);
+    let (_, errors) = graphql(&ann_app, query, json!({ "id": bobs["id"] })).await;
+    assert_eq!(codes(&errors), ["forbidden"]);
+    let (_, errors) = graphql(&ann_app, "{ users { name } }", json!({})).await;
+    assert_eq!(codes(&errors), ["forbidden"]);
+}
+
+#[tokio::test]
+async fn mutations_mirror_the_rest_api() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+
+    let (data, errors) = graphql(
+        &ann_app,
+        "mutation ($input: RecordCreateInput!) { createRecord(input: $input) { id sum version category { name } } }",
+        json!({ "input": { "userId": ann["id"], "categoryId": food["id"], "sum": "5" } }),
+    )
+    .await;
+    assert_eq!(errors, Value::Null);
+    let record = &data["createRecord"];
+    assert_eq!(record["category"]["name"], "Food");
+    assert_eq!(record["version"], 1);
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+    assert_eq!(ann_app.get(&uri).await.body["record"]["sum"], "5");
+
+    let update = "mutation ($id: UUID!, $input: RecordUpdateInput!, $version: Int!) {
+        updateRecord(id: $id, input: $input, version: $version) { sum version }
+    }";
+    let variables = |sum: &str, version: Value| {
+        json!({
+            "id": record["id"],
+            "input": { "categoryId": food["id"], "sum": sum },
+            "version": version,
+        })
+    };
+    let (data, _) = graphql(&ann_app, update, variables("6", json!(1))).await;
+    assert_eq!(data["updateRecord"], json!({ "sum": "6", "version": 2 }));
+    let (_, errors) = graphql(&ann_app, update, variables("7", json!(1))).await;
+    assert_eq!(codes(&errors), ["precondition_failed"]);
+    let (data, errors) = graphql(&ann_app, update, variables("7", Value::Null)).await;
+    assert_eq!(data, Value::Null);
+    assert!(!errors.as_array().unwrap().is_empty());
+    let (_, errors) = graphql(&ann_app, update, variables("0", json!(2))).await;
+    assert_eq!(codes(&errors), ["validation_failed"]);
+    assert_eq!(errors[0]["extensions"]["errors"][0]["field"], "sum");
+    assert_eq!(errors[0]["extensions"]["errors"][0]["code"], "not_positive");
+
+    let (_, errors) = graphql(
+        &ann_app,
+        "mutation { createCategory(input: { name: \"Travel\" }) { id } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(codes(&errors), ["forbidden"]);
+
+    let (data, _) = graphql(
+        &ann_app,
+        "mutation ($id: UUID!) { deleteRecord(id: $id, version: 2) }",
+        json!({ "id": record["id"] }),
+    )
+    .await;
+    assert_eq!(data["deleteRecord"], true);
+    assert_eq!(ann_app.get(&uri).await.status, StatusCode::NOT_FOUND);
+
+    let (data, _) = graphql(
+        &ann_app,
+        "mutation ($id: UUID!) { updateUser(id: $id, input: { name: \"Anna\" }, version: 1) { name version } }",
+        json!({ "id": ann["id"] }),
+    )
+    .await;
+    assert_eq!(data["updateUser"], json!({ "name": "Anna", "version": 2 }));
+}
+
+#[tokio::test]
+async fn limits_queries() {
+    let app = TestApp::new().await;
+    let (data, errors) = graphql(
+        &app,
+        "{ me { records { user { records { user { records { user { records { user { name } } } } } } } } } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(data, Value::Null);
+    assert!(
+        errors[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nested too deep")
+    );
+
+    let (data, _) = graphql(&app, "{ __schema { queryType { name } } }", json!({})).await;
+    assert_eq!(data["__schema"]["queryType"]["name"], "Query");
+    let res = app.get("/graphql").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(res.body.as_str().unwrap().contains("graphiql"));
+
+    // API keys act on the REST resources their scopes name, never GraphQL.
+    let res = app
+        .post(
+            "/api-keys",
+            json!({ "api_key": { "name": "Reports", "scopes": ["records:read"] } }),
+        )
+        .await;
+    let key = res.body["key"].as_str().unwrap();
+    let res = app
+        .with_token(key)
+        .post("/graphql", json!({ "query": "{ me { name } }" }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app
+        .anonymous()
+        .post("/graphql", json!({ "query": "{ me { name } }" }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let app = TestApp::with(|app| app.docs(false)).await;
+    let (data, _) = graphql(&app, "{ __schema { queryType { name } } }", json!({})).await;
+    assert_eq!(data["__schema"], Value::Null);
+    assert_eq!(
+        app.get("/graphql").await.status,
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+}
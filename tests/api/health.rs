@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use axum::http::{Method, StatusCode};
+use backend_lab_3::services::{Check, Status};
+use sea_orm::ConnectionTrait;
+
+use crate::TestApp;
+
+/// A service that's down.
+struct Down {
+    critical: bool,
+}
+
+#[async_trait]
+impl Check for Down {
+    fn name(&self) -> &'static str {
+        if self.critical { "queue" } else { "cache" }
+    }
+
+    fn critical(&self) -> bool {
+        self.critical
+    }
+
+    async fn check(&self) -> Result<Status, String> {
+        Err("unreachable".to_owned())
+    }
+}
+
+#[tokio::test]
+async fn reports_healthy() {
+    let app = TestApp::new().await;
+
+    for uri in ["/health/live", "/health/ready", "/health/startup"] {
+        let res = app.get(uri).await;
+        assert_eq!(res.status, StatusCode::OK, "{uri}");
+        assert_eq!(res.body["status"], "healthy", "{uri}");
+    }
+
+    let res = app.get("/health").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["services"]["db"]["status"], "up");
+    assert_eq!(res.body["services"]["migrations"]["status"], "up");
+    assert_eq!(res.body["services"]["migrations"]["details"]["pending"], 0);
+}
+
+#[tokio::test]
+async fn reports_extra_checks() {
+    let app = TestApp::with(|app| app.health_check(Down { critical: false })).await;
+    assert_eq!(app.get("/health/ready").await.status, StatusCode::OK);
+    let res = app.get("/health").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["services"]["cache"]["status"], "down");
+    assert_eq!(res.body["services"]["cache"]["critical"], false);
+
+    let app = TestApp::with(|app| app.health_check(Down { critical: true })).await;
+    let res = app.get("/health/ready").await;
+    assert_eq!(res.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.body["status"], "unhealthy");
+    assert_eq!(app.get("/health/live").await.status, StatusCode::OK);
+    assert_eq!(app.get("/health/startup").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn stays_ready_after_a_newer_release_migrates() {
+    let app = TestApp::new().await;
+    app.db
+        .execute_unprepared(
+            "INSERT INTO seaql_migrations (version, applied_at) \
+             VALUES ('m20991231_000000_from_a_newer_release', 0)",
+        )
+        .await
+        .unwrap();
+
+    let res = app.get("/health/ready").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get("/health").await;
+    assert_eq!(res.body["services"]["migrations"]["status"], "up");
+    assert_eq!(res.body["services"]["migrations"]["details"]["unknown"], 1);
+}
+
+#[tokio::test]
+async fn serves_root_and_docs() {
+    let app = TestApp::new().await;
+
+    let res = app.get("/").await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/openapi.json").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["info"]["title"], "Expense tracker");
+}
+
+#[tokio::test]
+async fn responds_with_problem_to_unknown_routes() {
+    let app = TestApp::new().await;
+
+    let res = app.get("/nothing/here").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.content_type(), "application/problem+json");
+    assert_eq!(res.body["code"], "not_found");
+    assert_eq!(res.body["instance"], "/nothing/here");
+    assert_eq!(
+        res.body["request_id"],
+        res.headers["x-request-id"].to_str().unwrap()
+    );
+
+    let res = app
+        .request(
+            Method::GET,
+            "/nothing/here",
+            &[("x-request-id", "abc-123")],
+            None,
+        )
+        .await;
+    assert_eq!(res.headers["x-request-id"], "abc-123");
+    assert_eq!(res.body["request_id"], "abc-123");
+}
@@ -0,0 +1,393 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::TestApp;
+
+/// Creates a household as `owner`, returning it.
+async fn create_household(owner: &TestApp, name: &str) -> Value {
+    let res = owner
+        .post("/households", json!({ "household": { "name": name } }))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    res.body["household"].clone()
+}
+
+/// Invites a user with `role` and has them join as `member`.
+async fn join(owner: &TestApp, member: &TestApp, household: &Value, role: &str) {
+    let res = owner
+        .post(
+            &format!(
+                "/households/{}/invitations",
+                household["id"].as_str().unwrap()
+            ),
+            json!({ "invitation": { "role": role } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let code = res.body["invitation"]["code"].clone();
+
+    let res = member
+        .post(
+            "/households/join",
+            json!({ "invitation": { "code": code } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.body["member"]["role"], role);
+
+    let res = member
+        .post(
+            "/households/join",
+            json!({ "invitation": { "code": code } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("code", "not_found")]);
+}
+
+#[tokio::test]
+async fn creates_and_lists_households() {
+    let app = TestApp::new().await;
+    let (_, ann_token) = app.create_member("Ann").await;
+    let (_, bob_token) = app.create_member("Bob").await;
+    let ann = app.with_token(&ann_token);
+    let bob = app.with_token(&bob_token);
+
+    let household = create_household(&ann, "Home").await;
+    let uri = format!("/households/{}", household["id"].as_str().unwrap());
+
+    let res = ann.get("/households").await;
+    assert_eq!(res.body["households"], json!([household]));
+    assert_eq!(bob.get("/households").await.body["households"], json!([]));
+    assert_eq!(bob.get(&uri).await.status, StatusCode::FORBIDDEN);
+
+    join(&ann, &bob, &household, "viewer").await;
+    let res = bob.get(&uri).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["household"], household);
+
+    let res = bob.get(&format!("{uri}/members")).await;
+    let mut roles: Vec<_> = res.body["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|member| {
+            (
+                member["name"].as_str().unwrap(),
+                member["role"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    roles.sort();
+    assert_eq!(roles, [("Ann", "owner"), ("Bob", "viewer")]);
+}
+
+#[tokio::test]
+async fn restricts_records_by_member_role() {
+    let app = TestApp::new().await;
+    let (ann_user, ann_token) = app.create_member("Ann").await;
+    let (bob_user, bob_token) = app.create_member("Bob").await;
+    let (carol_user, carol_token) = app.create_member("Carol").await;
+    let ann = app.with_token(&ann_token);
+    let bob = app.with_token(&bob_token);
+    let carol = app.with_token(&carol_token);
+    let food = app.create_category("Food").await;
+    let household = create_household(&ann, "Home").await;
+    join(&ann, &bob, &household, "viewer").await;
+    join(&ann, &carol, &household, "editor").await;
+
+    let record = |user: &Value| {
+        json!({
+            "record": {
+                "user_id": user["id"],
+                "category_id": food["id"],
+                "sum": "10",
+                "household_id": household["id"],
+            }
+        })
+    };
+    let res = bob.post("/records", record(&bob_user)).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = carol.post("/records", record(&carol_user)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let carols = res.body["record"].clone();
+    assert_eq!(carols["household_id"], household["id"]);
+    let res = carol.post("/records", record(&ann_user)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let (outsider, _) = app.create_member("Dan").await;
+    let res = carol.post("/records", record(&outsider)).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("user_id", "not_member")]);
+
+    let uri = format!("/records/{}", carols["id"].as_str().unwrap());
+    assert_eq!(bob.get(&uri).await.status, StatusCode::OK);
+    assert_eq!(bob.delete(&uri, "*").await.status, StatusCode::FORBIDDEN);
+    assert_eq!(ann.delete(&uri, "*").await.status, StatusCode::NO_CONTENT);
+
+    let res = bob
+        .get(&format!(
+            "/records?household_id={}",
+            household["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["records"].as_array().unwrap().len(), 1);
+    let res = app
+        .with_token(&app.create_member("Eve").await.1)
+        .get(&format!(
+            "/records?household_id={}",
+            household["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn keeps_household_categories_to_their_household() {
+    let app = TestApp::new().await;
+    let (ann_user, ann_token) = app.create_member("Ann").await;
+    let ann = app.with_token(&ann_token);
+    let home = create_household(&ann, "Home").await;
+    let cabin = create_household(&ann, "Cabin").await;
+
+    let res = ann
+        .post(
+            "/categories",
+            json!({ "category": { "name": "Firewood", "household_id": cabin["id"] } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let firewood = res.body["category"].clone();
+
+    let res = ann
+        .post(
+            "/records",
+            json!({
+                "record": {
+                    "user_id": ann_user["id"],
+                    "category_id": firewood["id"],
+                    "sum": "5",
+                    "household_id": home["id"],
+                }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("category_id", "other_household")]);
+
+    let (_, bob_token) = app.create_member("Bob").await;
+    let res = app.with_token(&bob_token).get("/categories").await;
+    assert_eq!(res.body["categories"], json!([]));
+}
+
+#[tokio::test]
+async fn summarizes_household_spending_by_member() {
+    let app = TestApp::new().await;
+    let (ann_user, ann_token) = app.create_member("Ann").await;
+    let (bob_user, bob_token) = app.create_member("Bob").await;
+    let ann = app.with_token(&ann_token);
+    let bob = app.with_token(&bob_token);
+    let food = app.create_category("Food").await;
+    let rent = app.create_category("Rent").await;
+    let household = create_household(&ann, "Home").await;
+    join(&ann, &bob, &household, "editor").await;
+
+    for (user, category, sum) in [
+        (&ann_user, &food, "10"),
+        (&ann_user, &rent, "500"),
+        (&bob_user, &food, "15.5"),
+    ] {
+        let res = bob
+            .post(
+                "/records",
+                json!({
+                    "record": {
+                        "user_id": user["id"],
+                        "category_id": category["id"],
+                        "sum": sum,
+                        "household_id": household["id"],
+                    }
+                }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+    }
+    app.create_record(&ann_user, &food, "99").await;
+
+    let res = bob
+        .get(&format!(
+            "/records/summary?household_id={}",
+            household["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let summary = &res.body["summary"];
+    assert_eq!(summary["total"], "525.5");
+    assert_eq!(summary["count"], 3);
+    let totals = |key: &str| {
+        let mut totals: Vec<_> = summary[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["name"].as_str().unwrap().to_owned(),
+                    item["total"].as_str().unwrap().to_owned(),
+                    item["count"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        totals.sort();
+        totals
+    };
+    assert_eq!(
+        totals("by_user"),
+        [
+            ("Ann".to_owned(), "510".to_owned(), 2),
+            ("Bob".to_owned(), "15.5".to_owned(), 1),
+        ]
+    );
+    assert_eq!(
+        totals("by_category"),
+        [
+            ("Food".to_owned(), "25.5".to_owned(), 2),
+            ("Rent".to_owned(), "500".to_owned(), 1),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keeps_last_owner() {
+    let app = TestApp::new().await;
+    let (ann_user, ann_token) = app.create_member("Ann").await;
+    let (bob_user, bob_token) = app.create_member("Bob").await;
+    let ann = app.with_token(&ann_token);
+    let bob = app.with_token(&bob_token);
+    let household = create_household(&ann, "Home").await;
+    join(&ann, &bob, &household, "editor").await;
+    let members = format!("/households/{}/members", household["id"].as_str().unwrap());
+    let ann_uri = format!("{members}/{}", ann_user["id"].as_str().unwrap());
+    let bob_uri = format!("{members}/{}", bob_user["id"].as_str().unwrap());
+
+    let res = ann.delete(&ann_uri, "*").await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    let res = bob
+        .put(&bob_uri, "*", json!({ "member": { "role": "owner" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = ann
+        .put(&bob_uri, "*", json!({ "member": { "role": "owner" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        ann.delete(&ann_uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    let res = bob.get(&members).await;
+    assert_eq!(res.body["members"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn keeps_records_of_removed_members_in_the_ledger() {
+    let app = TestApp::new().await;
+    let (_, ann_token) = app.create_member("Ann").await;
+    let (bob_user, bob_token) = app.create_member("Bob").await;
+    let ann = app.with_token(&ann_token);
+    let bob = app.with_token(&bob_token);
+    let food = app.create_category("Food").await;
+    let household = create_household(&ann, "Home").await;
+    join(&ann, &bob, &household, "editor").await;
+    let res = bob
+        .post(
+            "/records",
+            json!({
+                "record": {
+                    "user_id": bob_user["id"],
+                    "category_id": food["id"],
+                    "sum": "10",
+                    "household_id": household["id"],
+                }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let record = res.body["record"].clone();
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+
+    let res = ann
+        .delete(
+            &format!(
+                "/households/{}/members/{}",
+                household["id"].as_str().unwrap(),
+                bob_user["id"].as_str().unwrap()
+            ),
+            "*",
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    // Bob may still read his record, but only the household changes it now.
+    assert_eq!(bob.get(&uri).await.status, StatusCode::OK);
+    let changes = json!({ "record": { "category_id": food["id"], "sum": "20" } });
+    let res = bob.put(&uri, "*", changes.clone()).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(bob.delete(&uri, "*").await.status, StatusCode::FORBIDDEN);
+    let res = bob
+        .post(
+            "/records/bulk/delete",
+            json!({
+                "mode": "best_effort",
+                "records": [{ "id": record["id"], "version": 1 }],
+            }),
+        )
+        .await;
+    assert_eq!(res.body["results"][0]["status"], 403);
+    assert_eq!(ann.put(&uri, "*", changes).await.status, StatusCode::OK);
+    assert_eq!(ann.delete(&uri, "*").await.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn deletes_household_with_its_ledger() {
+    let app = TestApp::new().await;
+    let (ann_user, ann_token) = app.create_member("Ann").await;
+    let ann = app.with_token(&ann_token);
+    let household = create_household(&ann, "Home").await;
+    let res = ann
+        .post(
+            "/categories",
+            json!({ "category": { "name": "Garden", "household_id": household["id"] } }),
+        )
+        .await;
+    let garden = res.body["category"].clone();
+    let res = ann
+        .post(
+            "/records",
+            json!({
+                "record": {
+                    "user_id": ann_user["id"],
+                    "category_id": garden["id"],
+                    "sum": "3",
+                    "household_id": household["id"],
+                }
+            }),
+        )
+        .await;
+    let record = res.body["record"].clone();
+
+    let uri = format!("/households/{}", household["id"].as_str().unwrap());
+    assert_eq!(
+        ann.delete(&uri, "\"1\"").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(ann.get(&uri).await.status, StatusCode::FORBIDDEN);
+    let res = app
+        .get(&format!("/records/{}", record["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app
+        .get(&format!("/categories/{}", garden["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
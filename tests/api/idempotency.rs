@@ -0,0 +1,103 @@
+use axum::http::{Method, StatusCode};
+use entity::idempotency_key;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set};
+use serde_json::json;
+
+use crate::TestApp;
+
+#[tokio::test]
+async fn replays_response_to_retried_request() {
+    let app = TestApp::new().await;
+    let body = json!({ "user": { "name": "Ann" } });
+    let headers = [("idempotency-key", "create-ann")];
+
+    let first = app
+        .request(Method::POST, "/users", &headers, Some(body.clone()))
+        .await;
+    assert_eq!(first.status, StatusCode::CREATED);
+    assert!(!first.headers.contains_key("idempotent-replayed"));
+
+    let retry = app
+        .request(Method::POST, "/users", &headers, Some(body))
+        .await;
+    assert_eq!(retry.status, StatusCode::CREATED);
+    assert_eq!(retry.headers["idempotent-replayed"], "true");
+    assert_eq!(retry.body, first.body);
+    assert_eq!(retry.etag(), first.etag());
+
+    let res = app.get("/users").await;
+    assert_eq!(res.body["users"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn rejects_key_reused_for_different_request() {
+    let app = TestApp::new().await;
+    let headers = [("idempotency-key", "create-user")];
+
+    app.request(
+        Method::POST,
+        "/users",
+        &headers,
+        Some(json!({ "user": { "name": "Ann" } })),
+    )
+    .await;
+    let res = app
+        .request(
+            Method::POST,
+            "/users",
+            &headers,
+            Some(json!({ "user": { "name": "Bob" } })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("Idempotency-Key", "reused")]);
+}
+
+#[tokio::test]
+async fn scopes_keys_to_the_caller() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let food = app.create_category("Food").await;
+    let headers = [("idempotency-key", "lunch")];
+    let body = json!({
+        "record": { "user_id": ann["id"], "category_id": food["id"], "sum": "5" }
+    });
+
+    let first = app
+        .with_token(&token)
+        .request(Method::POST, "/records", &headers, Some(body.clone()))
+        .await;
+    assert_eq!(first.status, StatusCode::CREATED);
+    let other = app
+        .request(Method::POST, "/records", &headers, Some(body))
+        .await;
+    assert_eq!(other.status, StatusCode::CREATED);
+    assert!(!other.headers.contains_key("idempotent-replayed"));
+    assert_ne!(other.body["record"]["id"], first.body["record"]["id"]);
+}
+
+#[tokio::test]
+async fn rejects_retry_while_request_is_handled() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    idempotency_key::ActiveModel {
+        scope: Set(format!("user:{}", ann["id"].as_str().unwrap())),
+        key: Set("rename-ann".to_owned()),
+        request_hash: Set("in flight".to_owned()),
+        ..Default::default()
+    }
+    .insert(&app.db)
+    .await
+    .unwrap();
+
+    let res = app
+        .with_token(&token)
+        .request(
+            Method::POST,
+            "/users",
+            &[("idempotency-key", "rename-ann")],
+            Some(json!({ "user": { "name": "Bob" } })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+}
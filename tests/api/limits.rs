@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use backend_lab_3::limits::{Quota, RateLimits};
+use serde_json::json;
+
+use crate::TestApp;
+
+#[tokio::test]
+async fn limits_requests_per_client_and_group() {
+    let app = TestApp::with(|app| {
+        app.rate_limits(
+            RateLimits::new(Quota::new(2, Duration::from_secs(60)))
+                .group("categories", Quota::Unlimited),
+        )
+    })
+    .await;
+
+    let res = app.get("/records").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers["ratelimit-limit"], "2");
+    assert_eq!(res.headers["ratelimit-remaining"], "1");
+    assert_eq!(res.headers["ratelimit-policy"], "2;w=60");
+    assert_eq!(app.get("/records").await.status, StatusCode::OK);
+
+    let res = app.get("/records").await;
+    assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.content_type(), "application/problem+json");
+    assert_eq!(res.body["code"], "rate_limited");
+    assert_eq!(res.body["instance"], "/records");
+    assert_eq!(res.headers["retry-after"], "30");
+    assert_eq!(res.headers["ratelimit-remaining"], "0");
+    assert_eq!(res.headers["ratelimit-reset"], "60");
+
+    // Other groups, clients and unlimited groups have their own allowance.
+    assert_eq!(app.get("/users").await.status, StatusCode::OK);
+    let (_, token) = app.create_member("Ann").await;
+    let res = app.with_token(&token).get("/records").await;
+    assert_eq!(res.status, StatusCode::OK);
+    for _ in 0..3 {
+        let res = app.get("/categories").await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert!(res.headers.get("ratelimit-limit").is_none());
+    }
+    assert_eq!(app.get("/health/live").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn limits_clients_by_the_user_they_authenticate_as() {
+    let app = TestApp::with(|app| {
+        app.rate_limits(RateLimits::new(Quota::new(2, Duration::from_secs(60))))
+    })
+    .await;
+
+    // Made-up tokens share the bucket of clients without credentials.
+    for (token, status) in [
+        ("made-up-1", StatusCode::UNAUTHORIZED),
+        ("made-up-2", StatusCode::UNAUTHORIZED),
+        ("made-up-3", StatusCode::TOO_MANY_REQUESTS),
+    ] {
+        assert_eq!(app.with_token(token).get("/records").await.status, status);
+    }
+    assert_eq!(
+        app.anonymous().get("/records").await.status,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    // A user's API keys share the user's bucket.
+    let res = app
+        .post(
+            "/api-keys",
+            json!({ "api_key": { "name": "Reports", "scopes": ["records:read"] } }),
+        )
+        .await;
+    let key = res.body["key"].as_str().unwrap();
+    assert_eq!(
+        app.with_token(key).get("/records").await.status,
+        StatusCode::OK
+    );
+    assert_eq!(app.get("/records").await.status, StatusCode::OK);
+    let res = app.with_token(key).get("/records").await;
+    assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn limits_addresses_before_authenticating() {
+    let app = TestApp::with(|app| {
+        app.rate_limits(
+            RateLimits::new(Quota::new(1, Duration::from_secs(60)))
+                .group("users", Quota::Unlimited),
+        )
+    })
+    .await;
+
+    // An address gets ten times the quota across every user it authenticates as.
+    for i in 0..10 {
+        let (_, token) = app.anonymous().create_member(&format!("User {i}")).await;
+        let res = app.with_token(&token).get("/records").await;
+        assert_eq!(res.status, StatusCode::OK);
+    }
+    let (_, token) = app.anonymous().create_member("Ann").await;
+    let res = app.with_token(&token).get("/records").await;
+    assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers["ratelimit-limit"], "10");
+}
+
+#[tokio::test]
+async fn rejects_large_bodies() {
+    let app = TestApp::with(|app| app.max_body_size(64)).await;
+    let body = json!({ "user": { "name": "A".repeat(64) } });
+
+    let res = app.post("/users", body.clone()).await;
+    assert_eq!(res.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(res.body["code"], "payload_too_large");
+
+    let res = app
+        .request(
+            Method::POST,
+            "/users",
+            &[("idempotency-key", "large")],
+            Some(body),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let res = app
+        .post("/users", json!({ "user": { "name": "Ann" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+}
@@ -0,0 +1,236 @@
+//! Exercises the API end to end, against an in-memory SQLite database.
+
+mod api_keys;
+mod authorization;
+mod bulk;
+mod categories;
+mod events;
+mod graphql;
+mod health;
+mod households;
+mod idempotency;
+mod limits;
+mod middleware;
+mod outbox;
+mod records;
+mod settlements;
+mod users;
+mod webhooks;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{HeaderMap, Method, Request, StatusCode, header},
+    response::Response,
+};
+use backend_lab_3::{
+    App,
+    auth::{self, Role},
+    events::RecordEvents,
+    migrate,
+};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+/// The app, making requests as an admin unless told otherwise.
+#[derive(Clone)]
+pub struct TestApp {
+    router: Router,
+    token: Option<String>,
+    /// The app's database, for running its background tasks.
+    pub db: DatabaseConnection,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestResponse {
+    pub fn etag(&self) -> &str {
+        self.headers[header::ETAG].to_str().unwrap()
+    }
+
+    pub fn content_type(&self) -> &str {
+        self.headers[header::CONTENT_TYPE].to_str().unwrap()
+    }
+
+    /// The field and code of each error in a problem response.
+    pub fn errors(&self) -> Vec<(&str, &str)> {
+        self.body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| {
+                (
+                    error["field"].as_str().unwrap(),
+                    error["code"].as_str().unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with(|app| app).await
+    }
+
+    /// The app, configured by `configure`.
+    pub async fn with(configure: impl FnOnce(App) -> App) -> Self {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options
+            .max_connections(1)
+            .min_connections(1)
+            .sqlx_logging(false);
+        let db = Database::connect(options).await.unwrap();
+        migrate::up(&db, None).await.unwrap();
+        let (_, token) = auth::create_user(&db, "Admin".to_owned(), Role::Admin)
+            .await
+            .unwrap();
+        let events = RecordEvents::new();
+        tokio::spawn(events.clone().relay(db.clone(), CancellationToken::new()));
+        Self {
+            router: configure(App::new(db.clone()).events(events)).build(),
+            token: Some(token),
+            db,
+        }
+    }
+
+    /// The same app, making requests with `token`.
+    pub fn with_token(&self, token: &str) -> Self {
+        Self {
+            router: self.router.clone(),
+            token: Some(token.to_owned()),
+            db: self.db.clone(),
+        }
+    }
+
+    /// The same app, making requests without credentials.
+    pub fn anonymous(&self) -> Self {
+        Self {
+            router: self.router.clone(),
+            token: None,
+            db: self.db.clone(),
+        }
+    }
+
+    /// Makes a request without reading the response body, e.g. a stream.
+    pub async fn open(&self, uri: &str, headers: &[(&str, &str)]) -> Response {
+        let mut req = Request::builder().uri(uri);
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        self.router.clone().oneshot(req).await.unwrap()
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = match body {
+            Some(body) => req
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => req.body(Body::empty()),
+        };
+
+        let res = self.router.clone().oneshot(req.unwrap()).await.unwrap();
+        let status = res.status();
+        let headers = res.headers().clone();
+        let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.request(Method::GET, uri, &[], None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, &[], Some(body)).await
+    }
+
+    pub async fn put(&self, uri: &str, etag: &str, body: Value) -> TestResponse {
+        self.request(Method::PUT, uri, &[("if-match", etag)], Some(body))
+            .await
+    }
+
+    pub async fn delete(&self, uri: &str, etag: &str) -> TestResponse {
+        self.request(Method::DELETE, uri, &[("if-match", etag)], None)
+            .await
+    }
+
+    pub async fn create_user(&self, name: &str) -> Value {
+        let res = self
+            .post("/users", json!({ "user": { "name": name } }))
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        res.body["user"].clone()
+    }
+
+    /// Creates a member, returning it along with its token.
+    pub async fn create_member(&self, name: &str) -> (Value, String) {
+        let res = self
+            .anonymous()
+            .post("/users", json!({ "user": { "name": name } }))
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        let token = res.body["token"].as_str().unwrap().to_owned();
+        (res.body["user"].clone(), token)
+    }
+
+    pub async fn create_category(&self, name: &str) -> Value {
+        let res = self
+            .post("/categories", json!({ "category": { "name": name } }))
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        res.body["category"].clone()
+    }
+
+    pub async fn create_record(&self, user: &Value, category: &Value, sum: &str) -> Value {
+        let res = self
+            .post(
+                "/records",
+                json!({
+                    "record": {
+                        "user_id": user["id"],
+                        "category_id": category["id"],
+                        "sum": sum,
+                    }
+                }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        res.body["record"].clone()
+    }
+}
+
+/// A UUID no row has.
+pub const MISSING_ID: &str = "00000000-0000-0000-0000-000000000000";
@@ -0,0 +1,83 @@
+use axum::http::{HeaderValue, Method, StatusCode};
+
+use crate::TestApp;
+
+const ORIGIN: &str = "https://app.example.com";
+
+#[tokio::test]
+async fn allows_configured_origins() {
+    let app =
+        TestApp::with(|app| app.allowed_origins(vec![HeaderValue::from_static(ORIGIN)])).await;
+
+    let res = app
+        .request(
+            Method::OPTIONS,
+            "/records",
+            &[
+                ("origin", ORIGIN),
+                ("access-control-request-method", "POST"),
+                (
+                    "access-control-request-headers",
+                    "authorization,content-type",
+                ),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers["access-control-allow-origin"], ORIGIN);
+
+    let res = app
+        .request(Method::GET, "/records", &[("origin", ORIGIN)], None)
+        .await;
+    assert_eq!(res.headers["access-control-allow-origin"], ORIGIN);
+    let exposed = res.headers["access-control-expose-headers"]
+        .to_str()
+        .unwrap();
+    assert!(exposed.contains("etag") && exposed.contains("x-request-id"));
+
+    let res = app
+        .request(
+            Method::GET,
+            "/records",
+            &[("origin", "https://evil.example.com")],
+            None,
+        )
+        .await;
+    assert!(!res.headers.contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn compresses_responses() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let food = app.create_category("Food").await;
+    app.create_record(&user, &food, "5").await;
+
+    for encoding in ["gzip", "br", "zstd"] {
+        let res = app
+            .request(
+                Method::GET,
+                "/records",
+                &[("accept-encoding", encoding)],
+                None,
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.headers["content-encoding"], encoding);
+    }
+
+    let res = app.get("/records").await;
+    assert!(!res.headers.contains_key("content-encoding"));
+
+    let app = TestApp::with(|app| app.compression(false)).await;
+    let res = app
+        .request(
+            Method::GET,
+            "/records",
+            &[("accept-encoding", "gzip")],
+            None,
+        )
+        .await;
+    assert!(!res.headers.contains_key("content-encoding"));
+}
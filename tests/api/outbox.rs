@@ -0,0 +1,233 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use backend_lab_3::outbox::{Dispatcher, DomainEvent, Envelope, Subscriber};
+use entity::outbox;
+use sea_orm::{EntityTrait, PaginatorTrait};
+use serde_json::json;
+use tokio::{sync::mpsc, time};
+use tokio_util::sync::CancellationToken;
+
+use crate::TestApp;
+
+/// Passes events on to the test.
+struct Collect(mpsc::UnboundedSender<Envelope>);
+
+#[async_trait]
+impl Subscriber for Collect {
+    fn name(&self) -> &'static str {
+        "collect"
+    }
+
+    async fn handle(&self, envelope: &Envelope) -> Result<(), String> {
+        let _ = self.0.send(envelope.clone());
+        Ok(())
+    }
+}
+
+/// Fails to handle its first `failures` events.
+struct Flaky {
+    failures: u32,
+    attempts: AtomicU32,
+}
+
+#[async_trait]
+impl Subscriber for Flaky {
+    fn name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn handle(&self, _: &Envelope) -> Result<(), String> {
+        match self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+            true => Err("unavailable".to_owned()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Runs `dispatcher`, polling and retrying quickly.
+fn start_dispatcher(dispatcher: Dispatcher) -> CancellationToken {
+    let token = CancellationToken::new();
+    let dispatcher = dispatcher
+        .poll_interval(Duration::from_millis(20))
+        .retry_base(Duration::from_millis(50));
+    tokio::spawn(dispatcher.run(token.clone()));
+    token
+}
+
+async fn next(events: &mut mpsc::UnboundedReceiver<Envelope>) -> DomainEvent {
+    time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("an event is dispatched")
+        .unwrap()
+        .event
+}
+
+#[tokio::test]
+async fn dispatches_events_of_committed_changes() {
+    let app = TestApp::new().await;
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let dispatcher = Dispatcher::new(app.db.clone()).subscribe(Collect(sender));
+    let _dispatcher = start_dispatcher(dispatcher).drop_guard();
+    assert!(
+        matches!(next(&mut events).await, DomainEvent::UserCreated { name, .. } if name == "Admin")
+    );
+
+    let (ann, _) = app.create_member("Ann").await;
+    let food = app.create_category("Food").await;
+    let record = app.create_record(&ann, &food, "5").await;
+    let record_uri = format!("/records/{}", record["id"].as_str().unwrap());
+    let category_uri = format!("/categories/{}", food["id"].as_str().unwrap());
+    let user_uri = format!("/users/{}", ann["id"].as_str().unwrap());
+    let res = app
+        .put(
+            &record_uri,
+            "*",
+            json!({ "record": { "category_id": food["id"], "sum": "6" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.delete(&record_uri, "\"1\"").await.status,
+        StatusCode::PRECONDITION_FAILED
+    );
+    assert_eq!(
+        app.delete(&record_uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        app.delete(&category_uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        app.delete(&user_uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+
+    let DomainEvent::UserCreated { user_id, name, .. } = next(&mut events).await else {
+        panic!("expected user.created");
+    };
+    assert_eq!(json!(user_id), ann["id"]);
+    assert_eq!(name, "Ann");
+    let DomainEvent::CategoryCreated(category) = next(&mut events).await else {
+        panic!("expected category.created");
+    };
+    assert_eq!(json!(category.id), food["id"]);
+    let DomainEvent::RecordCreated(created) = next(&mut events).await else {
+        panic!("expected record.created");
+    };
+    assert_eq!(json!(created.id), record["id"]);
+    let DomainEvent::RecordUpdated(updated) = next(&mut events).await else {
+        panic!("expected record.updated");
+    };
+    assert_eq!((updated.id, updated.version), (created.id, 2));
+    assert_eq!(next(&mut events).await, DomainEvent::RecordDeleted(updated));
+    assert_eq!(
+        next(&mut events).await,
+        DomainEvent::CategoryDeleted(category)
+    );
+    assert_eq!(
+        next(&mut events).await,
+        DomainEvent::UserDeleted { user_id }
+    );
+
+    // The failed delete published nothing.
+    time::sleep(Duration::from_millis(100)).await;
+    assert!(events.try_recv().is_err());
+    let published = outbox::Entity::find().count(&app.db).await.unwrap();
+    assert_eq!(published, 8);
+}
+
+#[tokio::test]
+async fn publishes_what_is_deleted_with_what_it_belongs_to() {
+    let app = TestApp::new().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let res = ann_app
+        .post("/households", json!({ "household": { "name": "Home" } }))
+        .await;
+    let household = res.body["household"].clone();
+    let res = ann_app
+        .post(
+            "/categories",
+            json!({ "category": { "name": "Rent", "household_id": household["id"] } }),
+        )
+        .await;
+    let rent = res.body["category"].clone();
+    let res = ann_app
+        .post(
+            "/records",
+            json!({ "record": {
+                "user_id": ann["id"], "category_id": rent["id"], "sum": "3",
+                "household_id": household["id"],
+            } }),
+        )
+        .await;
+    let shared = res.body["record"].clone();
+    let food = app.create_category("Food").await;
+    let own = app.create_record(&ann, &food, "2").await;
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let dispatcher = Dispatcher::new(app.db.clone()).subscribe(Collect(sender));
+    let _dispatcher = start_dispatcher(dispatcher).drop_guard();
+    while !matches!(next(&mut events).await, DomainEvent::RecordCreated(record) if json!(record.id) == own["id"])
+    {
+    }
+
+    for uri in [
+        format!("/households/{}", household["id"].as_str().unwrap()),
+        format!("/users/{}", ann["id"].as_str().unwrap()),
+    ] {
+        assert_eq!(app.delete(&uri, "*").await.status, StatusCode::NO_CONTENT);
+    }
+    let DomainEvent::RecordDeleted(record) = next(&mut events).await else {
+        panic!("expected record.deleted");
+    };
+    assert_eq!(json!(record.id), shared["id"]);
+    let DomainEvent::CategoryDeleted(category) = next(&mut events).await else {
+        panic!("expected category.deleted");
+    };
+    assert_eq!(json!(category.id), rent["id"]);
+    let DomainEvent::RecordDeleted(record) = next(&mut events).await else {
+        panic!("expected record.deleted");
+    };
+    assert_eq!(json!(record.id), own["id"]);
+    assert!(matches!(
+        next(&mut events).await,
+        DomainEvent::UserDeleted { .. }
+    ));
+}
+
+#[tokio::test]
+async fn retries_only_failed_subscribers() {
+    let app = TestApp::new().await;
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let dispatcher = Dispatcher::new(app.db.clone())
+        .subscribe(Collect(sender))
+        .subscribe(Flaky {
+            failures: 2,
+            attempts: AtomicU32::new(0),
+        });
+    let _dispatcher = start_dispatcher(dispatcher).drop_guard();
+
+    next(&mut events).await;
+    for _ in 0..250 {
+        let event = outbox::Entity::find().one(&app.db).await.unwrap().unwrap();
+        if event.dispatched_at.is_some() {
+            assert_eq!(event.attempts, 3);
+            assert_eq!(event.pending_subscribers, None);
+            // Subscribers that succeeded don't get the event again.
+            assert!(events.try_recv().is_err());
+            return;
+        }
+        if event.attempts > 0 {
+            assert_eq!(event.pending_subscribers.as_deref(), Some("flaky"));
+            assert_eq!(event.last_error.as_deref(), Some("flaky: unavailable"));
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the event wasn't dispatched");
+}
@@ -0,0 +1,254 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use crate::{MISSING_ID, TestApp};
+
+#[tokio::test]
+async fn creates_and_gets_record() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let category = app.create_category("Food").await;
+
+    let record = app.create_record(&user, &category, "12.5").await;
+    assert_eq!(record["user_id"], user["id"]);
+    assert_eq!(record["category_id"], category["id"]);
+    assert_eq!(record["sum"], "12.5");
+
+    let res = app
+        .get(&format!("/records/{}", record["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"1\"");
+    assert_eq!(res.body["record"], record);
+}
+
+#[tokio::test]
+async fn rejects_invalid_records() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+
+    let res = app
+        .post(
+            "/records",
+            json!({
+                "record": { "user_id": MISSING_ID, "category_id": MISSING_ID, "sum": "0" }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.errors(),
+        [
+            ("user_id", "not_found"),
+            ("category_id", "not_found"),
+            ("sum", "not_positive"),
+        ]
+    );
+
+    let res = app
+        .post(
+            "/records",
+            json!({ "record": { "user_id": user["id"], "category_id": MISSING_ID } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("record.sum", "missing")]);
+}
+
+#[tokio::test]
+async fn filters_records() {
+    let app = TestApp::new().await;
+    let ann = app.create_user("Ann").await;
+    let bob = app.create_user("Bob").await;
+    let food = app.create_category("Food").await;
+    let rent = app.create_category("Rent").await;
+    let ann_food = app.create_record(&ann, &food, "1").await;
+    let ann_rent = app.create_record(&ann, &rent, "2").await;
+    let bob_food = app.create_record(&bob, &food, "3").await;
+
+    let ids = |res: crate::TestResponse| {
+        let mut ids: Vec<_> = res.body["records"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| record["id"].as_str().unwrap().to_owned())
+            .collect();
+        ids.sort();
+        ids
+    };
+    let sorted = |mut records: Vec<&serde_json::Value>| {
+        records.sort_by_key(|record| record["id"].as_str().unwrap());
+        records
+            .iter()
+            .map(|record| record["id"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let res = app.get("/records").await;
+    assert_eq!(ids(res), sorted(vec![&ann_food, &ann_rent, &bob_food]));
+
+    let res = app
+        .get(&format!("/records?user_id={}", ann["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(ids(res), sorted(vec![&ann_food, &ann_rent]));
+
+    let res = app
+        .get(&format!(
+            "/records?user_id={}&category_id={}",
+            ann["id"].as_str().unwrap(),
+            food["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(ids(res), sorted(vec![&ann_food]));
+}
+
+#[tokio::test]
+async fn rejects_malformed_filters() {
+    let app = TestApp::new().await;
+
+    let res = app.get("/records?user_id=nope").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("user_id", "invalid_value")]);
+}
+
+#[tokio::test]
+async fn expands_related_objects() {
+    let app = TestApp::new().await;
+    let ann = app.create_user("Ann").await;
+    let bob = app.create_user("Bob").await;
+    let food = app.create_category("Food").await;
+    let record = app.create_record(&ann, &food, "5").await;
+    app.create_record(&bob, &food, "3").await;
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+
+    let res = app.get(&format!("{uri}?expand=user,category")).await;
+    assert_eq!(res.status, StatusCode::OK);
+    // Only what other members of a household may see of the user.
+    assert_eq!(
+        res.body["record"]["user"],
+        json!({ "id": ann["id"], "name": "Ann" })
+    );
+    assert_eq!(res.body["record"]["category"], food);
+    assert_eq!(res.body["record"]["sum"], "5");
+    let etag = res.etag().to_owned();
+    assert!(etag.starts_with("W/"));
+    let expanded = format!("{uri}?expand=user,category");
+    let res = app
+        .request(Method::GET, &expanded, &[("if-none-match", &etag)], None)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_MODIFIED);
+    assert_eq!(res.etag(), etag);
+
+    let category_uri = format!("/categories/{}", food["id"].as_str().unwrap());
+    let category_etag = app.get(&category_uri).await.etag().to_owned();
+    let res = app
+        .put(
+            &category_uri,
+            &category_etag,
+            json!({ "category": { "name": "Groceries" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app
+        .request(Method::GET, &expanded, &[("if-none-match", &etag)], None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["record"]["category"]["name"], "Groceries");
+    assert_ne!(res.etag(), etag);
+
+    let res = app
+        .request(
+            Method::GET,
+            &format!("{uri}?expand=user"),
+            &[("if-none-match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["record"]["user"]["name"], "Ann");
+    assert!(res.body["record"].get("category").is_none());
+    assert!(app.get(&uri).await.body["record"].get("user").is_none());
+
+    let res = app
+        .get(&format!(
+            "/records?category_id={}&expand=user",
+            food["id"].as_str().unwrap()
+        ))
+        .await;
+    let mut names: Vec<_> = res.body["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["user"]["name"].as_str().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Ann", "Bob"]);
+    let res = app
+        .get(&format!("/records?user_id={MISSING_ID}&expand=category"))
+        .await;
+    assert_eq!(res.body["records"], json!([]));
+
+    let res = app.get("/records?expand=household").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("expand", "invalid_value")]);
+}
+
+#[tokio::test]
+async fn updates_record_if_version_matches() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let food = app.create_category("Food").await;
+    let rent = app.create_category("Rent").await;
+    let record = app.create_record(&user, &food, "12.5").await;
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+
+    let res = app
+        .put(
+            &uri,
+            "\"1\"",
+            json!({ "record": { "category_id": rent["id"], "sum": "20" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"2\"");
+    assert_eq!(res.body["record"]["category_id"], rent["id"]);
+    assert_eq!(res.body["record"]["sum"], "20");
+
+    let res = app
+        .put(
+            &uri,
+            "\"2\"",
+            json!({ "record": { "category_id": MISSING_ID, "sum": "-1" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.errors(),
+        [("category_id", "not_found"), ("sum", "not_positive")]
+    );
+
+    let res = app
+        .put(
+            &uri,
+            "\"1\"",
+            json!({ "record": { "category_id": food["id"], "sum": "1" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn deletes_record() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let category = app.create_category("Food").await;
+    let record = app.create_record(&user, &category, "12.5").await;
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+
+    assert_eq!(
+        app.delete(&uri, "\"1\"").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(app.get(&uri).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(app.delete(&uri, "*").await.status, StatusCode::NOT_FOUND);
+}
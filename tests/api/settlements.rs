@@ -0,0 +1,395 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::TestApp;
+
+fn shares_uri(record: &Value) -> String {
+    format!("/records/{}/shares", record["id"].as_str().unwrap())
+}
+
+async fn share(app: &TestApp, record: &Value, etag: &str, split: Value) -> crate::TestResponse {
+    app.put(&shares_uri(record), etag, json!({ "split": split }))
+        .await
+}
+
+/// Creates a household as `owner`, which each of `members` joins as an editor.
+async fn create_household(owner: &TestApp, members: &[&TestApp]) -> Value {
+    let res = owner
+        .post("/households", json!({ "household": { "name": "Home" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let household = res.body["household"].clone();
+    let uri = format!(
+        "/households/{}/invitations",
+        household["id"].as_str().unwrap()
+    );
+    for member in members {
+        let res = owner
+            .post(&uri, json!({ "invitation": { "role": "editor" } }))
+            .await;
+        let code = res.body["invitation"]["code"].clone();
+        let res = member
+            .post(
+                "/households/join",
+                json!({ "invitation": { "code": code } }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+    }
+    household
+}
+
+/// Creates a record in `household` as `app`.
+async fn create_record(
+    app: &TestApp,
+    household: &Value,
+    user: &Value,
+    category: &Value,
+    sum: &str,
+) -> Value {
+    let res = app
+        .post(
+            "/records",
+            json!({
+                "record": {
+                    "user_id": user["id"],
+                    "category_id": category["id"],
+                    "sum": sum,
+                    "household_id": household["id"],
+                }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    res.body["record"].clone()
+}
+
+/// The debts in a balances response, by the names of the users in `users`.
+fn debts<'a>(
+    body: &Value,
+    key: &str,
+    users: &[(&'a str, &Value)],
+) -> Vec<(&'a str, &'a str, String)> {
+    let name = |id: &Value| users.iter().find(|(_, user)| user["id"] == *id).unwrap().0;
+    let mut debts: Vec<_> = body[key]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|debt| {
+            (
+                name(&debt["from_user_id"]),
+                name(&debt["to_user_id"]),
+                debt["sum"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect();
+    debts.sort();
+    debts
+}
+
+#[tokio::test]
+async fn splits_records_equally_by_percentage_or_exactly() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, bob_token) = app.create_member("Bob").await;
+    let (carol, carol_token) = app.create_member("Carol").await;
+    let members = [&ann_token, &bob_token, &carol_token].map(|token| app.with_token(token));
+    let household = create_household(&app, &members.each_ref()).await;
+    let food = app.create_category("Food").await;
+    let record = create_record(&app, &household, &ann, &food, "100").await;
+
+    let sums = |res: &crate::TestResponse| {
+        res.body["shares"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|share| share["sum"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let res = share(
+        &app,
+        &record,
+        "\"1\"",
+        json!({ "kind": "equal", "user_ids": [ann["id"], bob["id"], carol["id"]] }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"2\"");
+    let mut equal = sums(&res);
+    equal.sort();
+    assert_eq!(equal, ["33.33", "33.33", "33.34"]);
+
+    let percentage = json!({
+        "kind": "percentage",
+        "shares": [
+            { "user_id": ann["id"], "percentage": "25" },
+            { "user_id": bob["id"], "percentage": "75" },
+        ]
+    });
+    let res = share(&app, &record, "\"1\"", percentage.clone()).await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    let res = share(&app, &record, "\"2\"", percentage).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get(&shares_uri(&record)).await;
+    assert_eq!(res.etag(), "\"3\"");
+    let mut percentage = sums(&res);
+    percentage.sort();
+    assert_eq!(percentage, ["25", "75"]);
+
+    let res = share(
+        &app,
+        &record,
+        "*",
+        json!({
+            "kind": "percentage",
+            "shares": [
+                { "user_id": ann["id"], "percentage": "25" },
+                { "user_id": bob["id"], "percentage": "75" },
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(app.get(&shares_uri(&record)).await.body, res.body);
+
+    let res = share(
+        &app,
+        &record,
+        "*",
+        json!({
+            "kind": "exact",
+            "shares": [
+                { "user_id": bob["id"], "sum": "60" },
+                { "user_id": bob["id"], "sum": "30" },
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.errors(),
+        [("split", "duplicate_user"), ("split", "total_mismatch")]
+    );
+
+    let (dan, _) = app.create_member("Dan").await;
+    let res = share(
+        &app,
+        &record,
+        "*",
+        json!({ "kind": "equal", "user_ids": [ann["id"], dan["id"]] }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("split", "not_member")]);
+
+    // Personal records can't put debts on anyone.
+    let personal = app.create_record(&ann, &food, "10").await;
+    let res = share(
+        &app,
+        &personal,
+        "*",
+        json!({ "kind": "equal", "user_ids": [ann["id"], bob["id"]] }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("split", "personal_record")]);
+
+    let res = app.delete(&shares_uri(&record), "\"4\"").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert_eq!(res.etag(), "\"5\"");
+    assert_eq!(
+        app.get(&shares_uri(&record)).await.body["shares"],
+        json!([])
+    );
+}
+
+#[tokio::test]
+async fn rescales_shares_with_the_record() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, bob_token) = app.create_member("Bob").await;
+    let members = [app.with_token(&ann_token), app.with_token(&bob_token)];
+    let household = create_household(&app, &members.each_ref()).await;
+    let food = app.create_category("Food").await;
+    let record = create_record(&app, &household, &ann, &food, "30").await;
+    let res = share(
+        &app,
+        &record,
+        "*",
+        json!({
+            "kind": "exact",
+            "shares": [
+                { "user_id": ann["id"], "sum": "10" },
+                { "user_id": bob["id"], "sum": "20" },
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .put(
+            &format!("/records/{}", record["id"].as_str().unwrap()),
+            res.etag(),
+            json!({ "record": { "category_id": food["id"], "sum": "60" } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get(&shares_uri(&record)).await;
+    let sum_of = |user: &Value| {
+        res.body["shares"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|share| share["user_id"] == user["id"])
+            .unwrap()["sum"]
+            .clone()
+    };
+    assert_eq!(sum_of(&ann), "20");
+    assert_eq!(sum_of(&bob), "40");
+}
+
+#[tokio::test]
+async fn balances_and_settles_shared_records() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, bob_token) = app.create_member("Bob").await;
+    let (carol, carol_token) = app.create_member("Carol").await;
+    let users = [("Ann", &ann), ("Bob", &bob), ("Carol", &carol)];
+    let food = app.create_category("Food").await;
+    let taxi = app.create_category("Taxi").await;
+    let household = create_household(
+        &app.with_token(&ann_token),
+        &[&app.with_token(&bob_token), &app.with_token(&carol_token)],
+    )
+    .await;
+
+    let dinner = create_record(&app.with_token(&ann_token), &household, &ann, &food, "90").await;
+    let res = share(
+        &app.with_token(&ann_token),
+        &dinner,
+        "*",
+        json!({ "kind": "equal", "user_ids": [ann["id"], bob["id"], carol["id"]] }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let ride = create_record(&app.with_token(&bob_token), &household, &bob, &taxi, "60").await;
+    let res = share(
+        &app.with_token(&bob_token),
+        &ride,
+        "*",
+        json!({
+            "kind": "exact",
+            "shares": [
+                { "user_id": bob["id"], "sum": "20" },
+                { "user_id": carol["id"], "sum": "40" },
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/settlements/balances").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        debts(&res.body, "balances", &users),
+        [
+            ("Bob", "Ann", "30".to_owned()),
+            ("Carol", "Ann", "30".to_owned()),
+            ("Carol", "Bob", "40".to_owned()),
+        ]
+    );
+    assert_eq!(
+        debts(&res.body, "transfers", &users),
+        [
+            ("Carol", "Ann", "60".to_owned()),
+            ("Carol", "Bob", "10".to_owned()),
+        ]
+    );
+
+    let res = app
+        .with_token(&ann_token)
+        .get("/settlements/balances")
+        .await;
+    assert_eq!(
+        debts(&res.body, "balances", &users),
+        [
+            ("Bob", "Ann", "30".to_owned()),
+            ("Carol", "Ann", "30".to_owned()),
+        ]
+    );
+
+    let carol_app = app.with_token(&carol_token);
+    let mut settlements = Vec::new();
+    for (to, token, sum) in [(&ann, &ann_token, "60"), (&bob, &bob_token, "10")] {
+        let body = json!({
+            "settlement": { "from_user_id": carol["id"], "to_user_id": to["id"], "sum": sum }
+        });
+        // Only whoever was paid may record it.
+        let res = carol_app.post("/settlements", body.clone()).await;
+        assert_eq!(res.status, StatusCode::FORBIDDEN);
+        let res = app.with_token(token).post("/settlements", body).await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        settlements.push(res.body["settlement"].clone());
+    }
+    let res = app.get("/settlements/balances").await;
+    assert_eq!(res.body["transfers"], json!([]));
+    let res = carol_app.get("/settlements").await;
+    assert_eq!(res.body["settlements"].as_array().unwrap().len(), 2);
+
+    // Voiding a settlement brings its debt back.
+    let uri = format!("/settlements/{}", settlements[1]["id"].as_str().unwrap());
+    assert_eq!(
+        carol_app.delete(&uri, "*").await.status,
+        StatusCode::FORBIDDEN
+    );
+    let bob_app = app.with_token(&bob_token);
+    assert_eq!(
+        bob_app.delete(&uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        bob_app.delete(&uri, "*").await.status,
+        StatusCode::NOT_FOUND
+    );
+    let res = app.get("/settlements/balances").await;
+    assert_eq!(
+        debts(&res.body, "transfers", &users),
+        [("Carol", "Bob", "10".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn rejects_invalid_settlements() {
+    let app = TestApp::new().await;
+    let (ann, ann_token) = app.create_member("Ann").await;
+    let (bob, _) = app.create_member("Bob").await;
+    let (carol, _) = app.create_member("Carol").await;
+
+    let res = app
+        .with_token(&ann_token)
+        .post(
+            "/settlements",
+            json!({
+                "settlement": { "from_user_id": bob["id"], "to_user_id": carol["id"], "sum": "5" }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .with_token(&ann_token)
+        .post(
+            "/settlements",
+            json!({
+                "settlement": { "from_user_id": ann["id"], "to_user_id": ann["id"], "sum": "0" }
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        res.errors(),
+        [("to_user_id", "same_user"), ("sum", "not_positive")]
+    );
+}
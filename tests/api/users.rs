@@ -0,0 +1,199 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use crate::{MISSING_ID, TestApp};
+
+#[tokio::test]
+async fn creates_and_gets_user() {
+    let app = TestApp::new().await;
+
+    let res = app
+        .post("/users", json!({ "user": { "name": "Ann" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.etag(), "\"1\"");
+    assert_eq!(res.body["user"]["name"], "Ann");
+    assert!(res.body["token"].is_string());
+
+    let id = res.body["user"]["id"].as_str().unwrap();
+    let res = app.get(&format!("/users/{id}")).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"1\"");
+    assert_eq!(
+        res.body["user"],
+        json!({ "id": id, "name": "Ann", "role": "member" })
+    );
+}
+
+#[tokio::test]
+async fn lists_users() {
+    let app = TestApp::new().await;
+    app.create_user("Ann").await;
+    app.create_user("Bob").await;
+
+    let res = app.get("/users").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let mut names: Vec<_> = res.body["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|user| user["name"].as_str().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Admin", "Ann", "Bob"]);
+}
+
+#[tokio::test]
+async fn rejects_invalid_names() {
+    let app = TestApp::new().await;
+
+    let res = app.post("/users", json!({ "user": { "name": "" } })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.content_type(), "application/problem+json");
+    assert_eq!(res.body["code"], "validation_failed");
+    assert_eq!(res.errors(), [("name", "empty")]);
+
+    let name = "a".repeat(101);
+    let res = app
+        .post("/users", json!({ "user": { "name": name } }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("name", "too_long")]);
+    assert_eq!(res.body["errors"][0]["params"]["max_length"], 100);
+}
+
+#[tokio::test]
+async fn rejects_malformed_bodies() {
+    let app = TestApp::new().await;
+
+    let res = app.post("/users", json!({ "name": "Ann" })).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("user", "missing")]);
+
+    let res = app.post("/users", json!({ "user": { "name": 1 } })).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("user.name", "invalid_value")]);
+
+    let res = app
+        .request(
+            Method::POST,
+            "/users",
+            &[("content-type", "text/plain")],
+            None,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn returns_not_found_for_missing_user() {
+    let app = TestApp::new().await;
+
+    let uri = format!("/users/{MISSING_ID}");
+    let res = app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.body["code"], "not_found");
+    assert_eq!(res.body["instance"], uri);
+
+    let res = app
+        .put(&uri, "*", json!({ "user": { "name": "Ann" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.delete(&uri, "*").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn rejects_malformed_id() {
+    let app = TestApp::new().await;
+
+    let res = app.get("/users/not-a-uuid").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.errors(), [("user_id", "invalid_value")]);
+}
+
+#[tokio::test]
+async fn updates_user_if_version_matches() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let uri = format!("/users/{}", user["id"].as_str().unwrap());
+    let body = json!({ "user": { "name": "Bob" } });
+
+    let res = app
+        .request(Method::PUT, &uri, &[], Some(body.clone()))
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_REQUIRED);
+
+    let res = app.put(&uri, "\"1\"", body.clone()).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.etag(), "\"2\"");
+    assert_eq!(res.body["user"]["name"], "Bob");
+
+    let res = app.put(&uri, "\"1\"", body).await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+
+    let res = app
+        .put(&uri, "\"2\"", json!({ "user": { "name": "" } }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn responds_not_modified_to_current_etag() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let uri = format!("/users/{}", user["id"].as_str().unwrap());
+
+    let res = app
+        .request(Method::GET, &uri, &[("if-none-match", "\"1\"")], None)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_MODIFIED);
+    assert_eq!(res.etag(), "\"1\"");
+
+    let res = app
+        .request(Method::GET, &uri, &[("if-none-match", "\"0\"")], None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deletes_user_if_version_matches() {
+    let app = TestApp::new().await;
+    let user = app.create_user("Ann").await;
+    let uri = format!("/users/{}", user["id"].as_str().unwrap());
+
+    let res = app.request(Method::DELETE, &uri, &[], None).await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_REQUIRED);
+
+    let res = app.delete(&uri, "\"2\"").await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+
+    let res = app.delete(&uri, "\"1\"").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn deleting_user_deletes_their_records() {
+    let app = TestApp::new().await;
+    let ann = app.create_user("Ann").await;
+    let bob = app.create_user("Bob").await;
+    let category = app.create_category("Food").await;
+    let record = app.create_record(&ann, &category, "12.5").await;
+    app.create_record(&bob, &category, "3").await;
+
+    let res = app
+        .delete(&format!("/users/{}", ann["id"].as_str().unwrap()), "*")
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app
+        .get(&format!("/records/{}", record["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app.get("/records").await;
+    assert_eq!(res.body["records"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+};
+use backend_lab_3::{
+    outbox::Dispatcher,
+    webhooks::{self, Deliveries},
+};
+use entity::webhook_delivery;
+use sea_orm::{EntityTrait, PaginatorTrait};
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::mpsc, time};
+use tokio_util::sync::CancellationToken;
+
+use crate::TestApp;
+
+/// A request the receiver got, with its headers and body.
+type Received = (HeaderMap, Bytes);
+
+/// A local webhook receiver, responding with queued statuses, then `200 OK`.
+struct Receiver {
+    url: String,
+    /// The secret deliveries are expected to be signed with.
+    secret: String,
+    statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+    requests: mpsc::UnboundedReceiver<Received>,
+}
+
+#[derive(Clone)]
+struct ReceiverState {
+    statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+    requests: mpsc::UnboundedSender<Received>,
+}
+
+impl Receiver {
+    async fn start() -> Self {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let statuses = Arc::new(Mutex::new(VecDeque::new()));
+        let state = ReceiverState {
+            statuses: statuses.clone(),
+            requests: sender,
+        };
+        let router = Router::new()
+            .route("/hook", post(receive))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        Self {
+            url: format!("http://{addr}/hook"),
+            secret: String::new(),
+            statuses,
+            requests,
+        }
+    }
+
+    fn respond_with(&self, status: StatusCode, times: usize) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .extend(std::iter::repeat_n(status, times));
+    }
+
+    async fn next(&mut self) -> (HeaderMap, Value) {
+        let (headers, body) = time::timeout(Duration::from_secs(5), self.requests.recv())
+            .await
+            .expect("a delivery arrives")
+            .unwrap();
+        let timestamp: i64 = headers["webhook-timestamp"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            headers["webhook-signature"],
+            webhooks::sign(&self.secret, timestamp, &body)
+        );
+        (headers, serde_json::from_slice(&body).unwrap())
+    }
+}
+
+async fn receive(
+    State(state): State<ReceiverState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let _ = state.requests.send((headers, body));
+    state
+        .statuses
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or(StatusCode::OK)
+}
+
+/// The app, letting webhooks be pointed at the local receiver.
+async fn local_app() -> TestApp {
+    TestApp::with(|app| app.webhooks_private_networks(true)).await
+}
+
+/// Runs a dispatcher retrying quickly against the app's database, delivering to
+/// the local receiver.
+fn start_deliveries(app: &TestApp, max_attempts: u32) -> CancellationToken {
+    run_deliveries(app, deliveries(app, max_attempts).private_networks(true))
+}
+
+fn deliveries(app: &TestApp, max_attempts: u32) -> Deliveries {
+    Deliveries::new(app.db.clone()).max_attempts(max_attempts)
+}
+
+fn run_deliveries(app: &TestApp, deliveries: Deliveries) -> CancellationToken {
+    let token = CancellationToken::new();
+    let dispatcher = Dispatcher::new(app.db.clone())
+        .subscribe(deliveries)
+        .poll_interval(Duration::from_millis(20))
+        .retry_base(Duration::from_millis(50));
+    tokio::spawn(dispatcher.run(token.clone()));
+    token
+}
+
+async fn create_webhook(app: &TestApp, url: &str, events: Value) -> (Value, String) {
+    let res = app
+        .post(
+            "/webhooks",
+            json!({ "webhook": { "url": url, "events": events } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let secret = res.body["secret"].as_str().unwrap().to_owned();
+    (res.body["webhook"].clone(), secret)
+}
+
+/// Waits until the webhook's deliveries satisfy `done`, returning them.
+async fn wait_for_deliveries(
+    app: &TestApp,
+    webhook: &Value,
+    done: impl Fn(&[Value]) -> bool,
+) -> Vec<Value> {
+    let uri = format!("/webhooks/{}/deliveries", webhook["id"].as_str().unwrap());
+    for _ in 0..250 {
+        let res = app.get(&uri).await;
+        assert_eq!(res.status, StatusCode::OK);
+        let deliveries = res.body["deliveries"].as_array().unwrap().clone();
+        if done(&deliveries) {
+            return deliveries;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("deliveries didn't settle");
+}
+
+#[tokio::test]
+async fn delivers_signed_events_the_owner_may_see() {
+    let app = local_app().await;
+    let mut receiver = Receiver::start().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let (bob, _) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    let (webhook, secret) = create_webhook(
+        &ann_app,
+        &receiver.url,
+        json!(["record.created", "record.deleted"]),
+    )
+    .await;
+    assert!(secret.starts_with("whsec_"));
+    assert_eq!(webhook["user_id"], ann["id"]);
+    assert_eq!(
+        webhook["events"],
+        json!(["record.created", "record.deleted"])
+    );
+    let _deliveries = start_deliveries(&app, 3).drop_guard();
+
+    receiver.secret = secret;
+    app.create_record(&bob, &food, "3").await;
+    let record = ann_app.create_record(&ann, &food, "5").await;
+    let (headers, payload) = receiver.next().await;
+    assert_eq!(payload["type"], "record.created");
+    assert_eq!(payload["data"]["record"]["id"], record["id"]);
+    assert!(payload["id"].is_i64());
+
+    let uri = format!("/records/{}", record["id"].as_str().unwrap());
+    ann_app
+        .put(&uri, "*", json!({ "record": { "sum": "6" } }))
+        .await;
+    ann_app.delete(&uri, "*").await;
+    let (_, payload) = receiver.next().await;
+    assert_eq!(payload["type"], "record.deleted");
+
+    let deliveries = wait_for_deliveries(&ann_app, &webhook, |deliveries| {
+        deliveries.iter().all(|d| d["status"] == "delivered")
+    })
+    .await;
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0]["type"], "record.deleted");
+    assert_eq!(deliveries[1]["id"], headers["webhook-id"].to_str().unwrap());
+    assert_eq!(deliveries[1]["attempts"], 1);
+    assert_eq!(deliveries[1]["response_status"], 200);
+    assert_eq!(deliveries[0]["event_id"], payload["id"]);
+
+    // Deleting the webhook deletes its deliveries.
+    let uri = format!("/webhooks/{}", webhook["id"].as_str().unwrap());
+    ann_app.delete(&uri, "*").await;
+    let deliveries = webhook_delivery::Entity::find().count(&app.db).await;
+    assert_eq!(deliveries.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn retries_failed_deliveries_until_dead() {
+    let app = local_app().await;
+    let mut receiver = Receiver::start().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    let (webhook, secret) =
+        create_webhook(&ann_app, &receiver.url, json!(["record.created"])).await;
+    receiver.respond_with(StatusCode::INTERNAL_SERVER_ERROR, 3);
+    let _deliveries = start_deliveries(&app, 2).drop_guard();
+
+    receiver.secret = secret;
+    ann_app.create_record(&ann, &food, "5").await;
+    receiver.next().await;
+    receiver.next().await;
+    let deliveries = wait_for_deliveries(&ann_app, &webhook, |deliveries| {
+        deliveries.first().is_some_and(|d| d["status"] == "dead")
+    })
+    .await;
+    let delivery = &deliveries[0];
+    assert_eq!(delivery["attempts"], 2);
+    assert_eq!(delivery["response_status"], 500);
+    assert_eq!(
+        delivery["last_error"],
+        "receiver responded with 500 Internal Server Error"
+    );
+
+    let uri = format!(
+        "/webhooks/{}/deliveries/{}/retry",
+        webhook["id"].as_str().unwrap(),
+        delivery["id"].as_str().unwrap()
+    );
+    let res = ann_app.post(&uri, json!({})).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["delivery"]["status"], "pending");
+    assert_eq!(res.body["delivery"]["attempts"], 0);
+
+    // The third response is still a failure, so it takes another attempt.
+    receiver.next().await;
+    receiver.next().await;
+    wait_for_deliveries(&ann_app, &webhook, |deliveries| {
+        deliveries[0]["status"] == "delivered"
+    })
+    .await;
+    let res = ann_app.post(&uri, json!({})).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn manages_webhooks() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_member("Ann").await;
+    let (_, bob_token) = app.create_member("Bob").await;
+    let ann_app = app.with_token(&token);
+    let (webhook, _) = create_webhook(
+        &ann_app,
+        "https://example.com/hook",
+        json!(["record.updated"]),
+    )
+    .await;
+    let uri = format!("/webhooks/{}", webhook["id"].as_str().unwrap());
+
+    let bob_app = app.with_token(&bob_token);
+    assert_eq!(bob_app.get(&uri).await.status, StatusCode::FORBIDDEN);
+    assert!(
+        bob_app.get("/webhooks").await.body["webhooks"]
+            .as_array()
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(app.get("/webhooks").await.body["webhooks"][0], webhook);
+
+    let res = ann_app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let body = json!({
+        "webhook": { "url": "https://example.com/v2", "events": ["record.deleted", "record.created"] }
+    });
+    let res = ann_app.put(&uri, res.etag(), body.clone()).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["webhook"]["url"], "https://example.com/v2");
+    assert_eq!(
+        res.body["webhook"]["events"],
+        json!(["record.created", "record.deleted"])
+    );
+    assert_eq!(
+        ann_app.put(&uri, "\"1\"", body).await.status,
+        StatusCode::PRECONDITION_FAILED
+    );
+
+    let res = ann_app
+        .post(
+            "/webhooks",
+            json!({ "webhook": { "url": "ftp://example.com", "events": [] } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.errors(), [("url", "invalid"), ("events", "empty")]);
+    for url in [
+        "http://127.0.0.1:8080/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]/hook",
+        "http://localhost/hook",
+        "https://metadata.google.internal/",
+    ] {
+        let res = ann_app
+            .post(
+                "/webhooks",
+                json!({ "webhook": { "url": url, "events": ["record.created"] } }),
+            )
+            .await;
+        assert_eq!(res.errors(), [("url", "not_public")], "{url}");
+    }
+    let res = ann_app
+        .post(
+            "/webhooks",
+            json!({ "webhook": { "url": "https://example.com", "events": ["user.created"] } }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    assert_eq!(
+        ann_app.delete(&uri, "*").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(ann_app.get(&uri).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn refuses_to_deliver_to_private_addresses() {
+    let app = local_app().await;
+    let (ann, token) = app.create_member("Ann").await;
+    let ann_app = app.with_token(&token);
+    let food = app.create_category("Food").await;
+    let (webhook, _) = create_webhook(
+        &ann_app,
+        "http://127.0.0.1:9/hook",
+        json!(["record.created"]),
+    )
+    .await;
+    let _deliveries = run_deliveries(&app, deliveries(&app, 1)).drop_guard();
+
+    ann_app.create_record(&ann, &food, "5").await;
+    let deliveries = wait_for_deliveries(&ann_app, &webhook, |deliveries| {
+        deliveries.first().is_some_and(|d| d["status"] == "dead")
+    })
+    .await;
+    assert_eq!(deliveries[0]["response_status"], Value::Null);
+    assert_eq!(deliveries[0]["last_error"], "url is not a public address");
+}